@@ -0,0 +1,50 @@
+//! Shell completions. clap_complete only knows the static structure of the CLI, so a wrapper
+//! completing channel names through the hidden `names` command is added for every shell.
+use std::io::Write;
+use clap::{Command, CommandFactory};
+use clap_complete::Shell;
+use crate::Cli;
+
+/// Commands creating a channel, so their name must not complete to existing ones.
+const NEW_CHANNEL: [&str; 3] = ["propose", "accept", "recover"];
+
+/// Prints the completion script for the shell.
+pub fn generate(shell: Shell, out: &mut impl Write) -> std::io::Result<()> {
+    let mut command = Cli::command();
+    let bin = command.get_name().to_string();
+    clap_complete::generate(shell, &mut command, &bin, out);
+    let commands = channel_commands(&command).join(" ");
+    match shell {
+        Shell::Bash => write!(out, r#"
+_{f}_with_names() {{
+    if [[ $COMP_CWORD -eq 2 && " {commands} " == *" ${{COMP_WORDS[1]}} "* ]]; then
+        COMPREPLY=( $(compgen -W "$({bin} names 2>/dev/null)" -- "${{COMP_WORDS[2]}}") )
+        return 0
+    fi
+    _{f} "$@"
+}}
+complete -F _{f}_with_names -o bashdefault -o default {bin}
+"#, f = bin.replace('-', "__")),
+        Shell::Zsh => write!(out, r#"
+_{bin}_with_names() {{
+    if (( CURRENT == 3 )) && [[ " {commands} " == *" $words[2] "* ]]; then
+        compadd -- ${{(f)"$({bin} names 2>/dev/null)"}}
+    else
+        _{bin} "$@"
+    fi
+}}
+compdef _{bin}_with_names {bin}
+"#),
+        Shell::Fish => writeln!(out, "complete -c {bin} -n \"__fish_seen_subcommand_from {commands}\" -f -a \"({bin} names)\""),
+        _ => Ok(()),
+    }
+}
+
+/// The subcommands taking the name of an existing channel as first argument.
+fn channel_commands(command: &Command) -> Vec<String> {
+    command.get_subcommands()
+        .filter(|sub| sub.get_positionals().next().is_some_and(|arg| arg.get_id() == "name"))
+        .map(|sub| sub.get_name().to_string())
+        .filter(|name| !NEW_CHANNEL.contains(&name.as_str()))
+        .collect()
+}
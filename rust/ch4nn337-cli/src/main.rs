@@ -1,67 +1,687 @@
 use std::{env, fs};
+use std::collections::{BTreeMap, HashMap};
 use std::fs::File;
-use std::io::{BufRead, stdin};
+use std::io::{BufRead, IsTerminal, stdin};
 use std::num::NonZeroU128;
+use std::path::{Path, PathBuf};
+use std::process::Command;
 use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use anyhow::anyhow;
 use clap::{Parser, Subcommand};
+use clap_complete::Shell;
+use ethers::middleware::SignerMiddleware;
 use ethers::prelude::{Http, Provider};
-use ch4nn337_lib::Channel;
+use ethers::providers::Middleware;
+use ethers::signers::{LocalWallet, Signer};
+use ethers::core::k256::ecdsa::{SigningKey, VerifyingKey};
+use ethers::types::{Address, U256};
+use ethers::utils::{hex, public_key_to_address};
+use ch4nn337_lib::{qr, Channel, ChannelState, Message, Summary, WithdrawalStatus};
+use ch4nn337_lib::amount::{format_amount, parse_amount, SignedWei, Wei};
+use ch4nn337_lib::advert::SignedAdvertisement;
+use ch4nn337_lib::autosign::AutoSigner;
+use ch4nn337_lib::ceremony::{Acceptance, Proposal};
+use ch4nn337_lib::chain::BlockPolicy;
+use ch4nn337_lib::cold::SigningRequest;
+use ch4nn337_lib::contacts::{Contact, ContactBook};
+use ch4nn337_lib::failover::FailoverClient;
+use ch4nn337_lib::inspect;
+use ch4nn337_lib::journal::{Intent, Journal, Recovery};
+use ch4nn337_lib::filedrop::FileDrop;
+use ch4nn337_lib::export;
+use ch4nn337_lib::gas::GasSplit;
+use ch4nn337_lib::history::Action;
+use ch4nn337_lib::policy::Policy;
+use ch4nn337_lib::preview::Preview;
+use ch4nn337_lib::protocol::OutgoingMessage;
+use ch4nn337_lib::price::{ChainlinkFeed, format_fiat, HttpSource, parse_fiat, PriceSource};
+use ch4nn337_lib::receipt::{PaymentReceipt, verify_receipt};
+use ch4nn337_lib::nostr::NostrTransport;
+use ch4nn337_lib::relay::{self, RelayClient, RelayConfig, RelayMetrics};
+use ch4nn337_lib::snapshot;
+use ch4nn337_lib::transport::Transport;
+use ch4nn337_lib::validation::ValidationPolicy;
+use ch4nn337_lib::watch::{ChainWatcher, Wakeup};
+use ch4nn337_lib::store::{ChannelStore, FileStore, Version};
+use ch4nn337_lib::stream::{MILLIWEI_PER_WEI, PaymentStream};
+use qrcode::QrCode;
+use qrcode::render::unicode;
+use tracing::info;
+use tracing_subscriber::EnvFilter;
+
+mod completions;
+
+type Client = Provider<FailoverClient<Http>>;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
+    /// log filter, e.g. "debug" or "ch4nn337_lib=trace"
+    #[arg(long, global = true, default_value = "warn")]
+    log_level: String,
+    /// log as JSON lines
+    #[arg(long, global = true)]
+    log_json: bool,
+    /// encrypt the messages we output to the counterparty
+    #[arg(long, global = true)]
+    seal: bool,
+    /// Chainlink feed for the ETH price, to show and request fiat amounts
+    #[arg(long, global = true, conflicts_with = "price_url")]
+    price_feed: Option<String>,
+    /// URL returning the ETH price as JSON, instead of a Chainlink feed
+    #[arg(long, global = true, requires = "price_pointer")]
+    price_url: Option<String>,
+    /// JSON pointer to the price in the response of --price-url, e.g. /ethereum/usd
+    #[arg(long, global = true)]
+    price_pointer: Option<String>,
+    /// currency of the price source
+    #[arg(long, global = true, default_value = "USD")]
+    currency: String,
     #[command(subcommand)]
     command: Commands,
 }
 
 #[derive(Subcommand, Debug)]
 enum Commands {
-    Open {
-        #[arg(short, long, default_value_t = 5)]
-        chain_id: u128,
+    /// Propose a channel as party A, on the chain of ETH_RPC_URL
+    Propose {
         #[arg(short, long, default_value = "0x5ff137d4b0fdcd49dca30c7cf57e578a026d2789")]
         entry_point: String,
-        #[arg(short, long, default_value = "TODO")]
-        factory: String,
+        /// defaults to the factory deployed with deploy-factory for the chain
+        #[arg(short, long)]
+        factory: Option<String>,
+        name: String,
+        /// also print the proposal as QR code
+        #[arg(long)]
+        qr: bool,
+        /// advertise the proposal, offering to fund the channel with this amount
+        #[arg(long, value_parser = parse_wei)]
+        advertise: Option<u128>,
+        /// fee the taker of the advertisement pays
+        #[arg(long, default_value = "0", value_parser = parse_wei, requires = "advertise")]
+        fee: u128,
+        /// hours the advertisement holds
+        #[arg(long, default_value_t = 24, requires = "advertise")]
+        expires_in: u64,
+        /// relay to publish the advertisement on
+        #[arg(long, requires = "advertise")]
+        relay: Option<String>,
+    },
+    /// Accept a channel proposal as party B
+    Accept {
         name: String,
+        /// the proposal as JSON, a file containing it, or - to read it from stdin without a prompt
+        proposal: Option<String>,
+        /// the proposal is a liquidity advertisement, as listed by ads
+        #[arg(long)]
+        from_ad: bool,
+        /// also print the acceptance as QR code
+        #[arg(long)]
+        qr: bool,
+    },
+    /// Complete opening a proposed channel with the acceptance of party B
+    Confirm {
+        name: String,
+        /// the acceptance as JSON, a file containing it, or - to read it from stdin without a prompt
+        acceptance: Option<String>,
+        /// fund the channel with this amount right away, like 1.5eth, 20gwei or 1000wei
+        #[arg(long, value_parser = parse_wei, requires = "funding_key")]
+        fund: Option<u128>,
+        /// keystore file of the key paying the funding, its password is read from stdin
+        #[arg(long, requires = "fund")]
+        funding_key: Option<PathBuf>,
+    },
+    /// Deploy a channel factory, paid for by the key in ETH_PRIVATE_KEY
+    DeployFactory {
+        #[arg(short, long, default_value = "0x5ff137d4b0fdcd49dca30c7cf57e578a026d2789")]
+        entry_point: String,
     },
     Status {
         name: String,
+        /// verify the channel state with storage proofs against this block hash, which has to come
+        /// from a source you trust
+        #[arg(long)]
+        trusted_block: Option<String>,
+        /// also list the userops of the channel included on-chain
+        #[arg(long)]
+        history: bool,
+        /// block to start the history from, as some RPC providers limit the range of log queries
+        #[arg(long, default_value_t = 0, requires = "history")]
+        from_block: u64,
+        /// read the chain even if the channel was read within the cache TTL of its policy
+        #[arg(long)]
+        refresh: bool,
+    },
+    /// Show the balances and disputes of all channels, read from the chain at once
+    List,
+    /// Export all transfers and settlements for bookkeeping
+    Export {
+        name: String,
+        /// csv, json or ofx
+        #[arg(long, default_value = "csv")]
+        format: String,
+        /// block to look for on-chain settlements from
+        #[arg(long, default_value_t = 0)]
+        from_block: u64,
     },
+    /// Deploy the channel contract, paid for by the key in ETH_PRIVATE_KEY
     Deploy {
         name: String,
+        /// only print the factory call and its estimated gas, without sending it
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Top up the entry point deposit of the channel, which pays for gas, with funds from ETH_PRIVATE_KEY
+    AddDeposit {
+        name: String,
+        /// amount like 1.5eth, 20gwei or 1000wei, plain numbers being wei
+        #[arg(value_parser = parse_wei)]
+        wei: u128,
+    },
+    /// Issue a receipt for the latest transfer, as proof of payment
+    Receipt {
+        name: String,
+        /// hash of the invoice the transfer pays
+        #[arg(long)]
+        invoice: Option<String>,
+    },
+    /// Check the signatures of a payment receipt
+    VerifyReceipt {
+        /// the receipt as JSON, a file containing it, or - to read it from stdin without a prompt
+        receipt: Option<String>,
+    },
+    /// Check that the factory, implementation and channel run the expected code
+    Verify {
+        name: String,
+    },
+    /// Check the signatures, nonces and value transfers of all stored messages
+    Audit {
+        name: String,
+    },
+    /// Show the on-chain state of any channel, without needing its key or local state
+    Inspect {
+        /// channel address, or give the parties and salt instead
+        #[arg(required_unless_present_all = ["party_a", "party_b", "salt"], conflicts_with_all = ["party_a", "party_b", "salt"])]
+        address: Option<String>,
+        /// address or hex encoded public key of party A
+        #[arg(long, requires_all = ["party_b", "salt"])]
+        party_a: Option<String>,
+        /// address or hex encoded public key of party B
+        #[arg(long)]
+        party_b: Option<String>,
+        #[arg(long)]
+        salt: Option<String>,
+        /// defaults to the factory deployed with deploy-factory for the chain
+        #[arg(long)]
+        factory: Option<String>,
+        /// print the report as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Rebuild a lost channel from its key and the chain, without the history; the key is read from stdin
+    Recover {
+        name: String,
+        counterparty: String,
+        salt: String,
+        /// defaults to the factory deployed with deploy-factory for the chain
+        #[arg(long)]
+        factory: Option<String>,
+    },
+    Rename {
+        name: String,
+        new_name: String,
+    },
+    /// Move a channel out of the way, keeping its data and a backup of its key encrypted with a
+    /// password read from stdin
+    Archive {
+        name: String,
+    },
+    /// Delete a channel, which is refused while it holds funds or a dispute is open
+    Delete {
+        name: String,
+        /// delete anyway, losing access to any funds left
+        #[arg(long, requires = "i_understand")]
+        force: bool,
+        #[arg(long, requires = "force")]
+        i_understand: bool,
     },
     Request {
         name: String,
-        wei: NonZeroU128,
+        /// amount like 1.5eth, 20gwei or 1000wei, plain numbers being wei
+        #[arg(required_unless_present = "fiat", conflicts_with = "fiat", value_parser = parse_positive_wei)]
+        wei: Option<NonZeroU128>,
+        /// request a fiat amount like 5.00USD, converted at the current price
+        #[arg(long)]
+        fiat: Option<String>,
+        /// also print the request as QR code
+        #[arg(long)]
+        qr: bool,
+        /// only print the userop and the resulting balances, without signing or saving anything
+        #[arg(long)]
+        dry_run: bool,
+        /// id of this request, running the command again with the same id hands out the same request
+        #[arg(long)]
+        op_id: Option<String>,
+        /// check the balance against the state last read by `status` instead of the chain, run
+        /// `reconcile` once back online
+        #[arg(long, conflicts_with_all = ["fiat", "dry_run", "op_id"])]
+        offline: bool,
+        /// hours the state read by `status` may be old for --offline
+        #[arg(long, default_value_t = 24, requires = "offline")]
+        max_age: u64,
+    },
+    /// Request several payments netted into one transfer
+    Batch {
+        name: String,
+        /// payments as AMOUNT or AMOUNT:MEMO, amounts like 1.5eth, 20gwei or 1000wei
+        #[arg(required = true)]
+        payments: Vec<String>,
+        /// also print the request as QR code
+        #[arg(long)]
+        qr: bool,
+        /// id of this request, running the command again with the same id hands out the same request
+        #[arg(long)]
+        op_id: Option<String>,
     },
     Withdraw {
         name: String, // todo implement partial withdrawal
+        /// also print the request as QR code
+        #[arg(long)]
+        qr: bool,
+        /// only print the userop and the payout, without signing or saving anything
+        #[arg(long)]
+        dry_run: bool,
+        /// id of this request, running the command again with the same id hands out the same request
+        #[arg(long)]
+        op_id: Option<String>,
+    },
+    /// Request a replacement for a stuck withdrawal that pays higher fees
+    Bump {
+        name: String,
+        /// also print the request as QR code
+        #[arg(long)]
+        qr: bool,
+    },
+    /// Hand our signed withdrawal to the bundler again, after submitting it failed
+    Resubmit {
+        name: String,
+        /// also print the response as QR code
+        #[arg(long)]
+        qr: bool,
     },
     Receive {
         name: String,
+        /// sign without asking for confirmation
+        #[arg(short, long)]
+        yes: bool,
+        /// the message as JSON, a file containing it, or - to read it from stdin without a prompt
+        #[arg(short, long, conflicts_with = "scan")]
+        message: Option<String>,
+        /// read the message from an image of its QR code
+        #[arg(long)]
+        scan: Option<PathBuf>,
+        /// write the response to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// also print the response as QR code
+        #[arg(long)]
+        qr: bool,
+        /// hash of the counterparty's funding transaction the transfer relies on, holding the
+        /// transfer until it confirms
+        #[arg(long)]
+        funding: Option<String>,
+    },
+    /// Sign a transfer held by receive --funding, once the funding confirmed
+    Release {
+        name: String,
+        /// write the response to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// also print the response as QR code
+        #[arg(long)]
+        qr: bool,
     },
     Response {
         name: String,
+        /// the response as JSON, a file containing it, or - to read it from stdin without a prompt
+        #[arg(short, long)]
+        message: Option<String>,
     },
     Cancel {
         name: String,
     },
+    /// Check transfers requested offline against the chain, dropping them if they would be refused
+    Reconcile {
+        name: String,
+    },
+    /// Copy a channel without its key, for building and submitting userops on an online machine
+    WatchOnly {
+        name: String,
+        /// name of the copy
+        copy: String,
+    },
+    /// Copy a channel without its key and unable to sign, for monitoring or accounting
+    Observer {
+        name: String,
+        /// name of the copy
+        copy: String,
+    },
+    /// Sign a signing request of a watch-only channel, on the machine holding the key
+    SignOffline {
+        name: String,
+        /// the request as JSON, a file containing it, or - to read it from stdin without a prompt
+        #[arg(short, long)]
+        request: Option<String>,
+    },
+    /// Merge a signature from the offline machine into a watch-only channel
+    ApplySignature {
+        name: String,
+        signature: String,
+        /// also print the result as QR code
+        #[arg(long)]
+        qr: bool,
+    },
+    /// Start a dispute with the latest transfer, for when the counterparty stops cooperating
+    Dispute {
+        name: String,
+    },
+    /// Close a timed out dispute, paid for by the key in ETH_PRIVATE_KEY
+    CloseDispute {
+        name: String,
+    },
+    /// Archive a channel once its withdrawal is included on-chain
+    Close {
+        name: String,
+    },
+    /// Pay continuously until interrupted
+    Stream {
+        name: String,
+        /// wei per second, with up to three decimals
+        #[arg(value_parser = parse_milliwei)]
+        rate: u128,
+        /// seconds between updated transfer requests
+        #[arg(short, long, default_value_t = 10)]
+        interval: u64,
+    },
+    /// Watch all channels for disputes and warn before their challenge windows close
+    Monitor {
+        /// seconds between checks
+        #[arg(short, long, default_value_t = 60)]
+        interval: u64,
+        /// warn when this many seconds are left, may be given multiple times
+        #[arg(long, default_values_t = [21600, 3600, 600])]
+        threshold: Vec<u64>,
+        /// also POST warnings as JSON to this URL
+        #[arg(long)]
+        webhook: Option<String>,
+        /// also show warnings as desktop notifications, using notify-send
+        #[arg(long)]
+        desktop: bool,
+        /// start a dispute once the counterparty has not answered for this many hours, if the
+        /// latest transfer leaves us a balance
+        #[arg(long)]
+        auto_dispute_after: Option<u64>,
+        /// close timed out disputes, paid for by the key in ETH_PRIVATE_KEY
+        #[arg(long)]
+        auto_close: bool,
+        /// websocket endpoint to check on channel activity right away instead of only every
+        /// interval, polling while it is unreachable
+        #[arg(long)]
+        ws: Option<String>,
+    },
+    /// Read messages from stdin, one per line, and sign every payment to us unattended
+    Autosign {
+        name: String,
+        /// smallest incoming transfer to sign
+        #[arg(long, default_value = "0", value_parser = parse_wei)]
+        minimum: u128,
+    },
+    /// Show or change the spending policy of a channel
+    Policy {
+        #[command(subcommand)]
+        command: PolicyCommands,
+    },
+    /// Catch up with the counterparty after restoring an old backup
+    Sync {
+        #[command(subcommand)]
+        command: SyncCommands,
+    },
+    /// Manage known counterparties
+    Contacts {
+        #[command(subcommand)]
+        command: ContactsCommands,
+    },
+    /// Print the completion script for a shell, e.g. `source <(ch4nn337-cli completions bash)`
+    Completions {
+        shell: Shell,
+    },
+    /// List the names of all channels, for completion
+    #[command(hide = true)]
+    Names,
+    /// Run a relay that stores messages until their recipient fetches them
+    Relay {
+        #[arg(long, default_value = "0.0.0.0:4337")]
+        listen: String,
+        /// puts a single peer can make per minute
+        #[arg(long, default_value = "60")]
+        puts_per_minute: u32,
+        /// gets a single peer can make per minute
+        #[arg(long, default_value = "120")]
+        gets_per_minute: u32,
+        /// puts, and separately gets, a single peer can make at once after being idle
+        #[arg(long, default_value = "20")]
+        burst: u32,
+        /// seconds the timestamp of a put may be off our clock
+        #[arg(long, default_value = "300")]
+        max_skew: u64,
+        /// connections served at once
+        #[arg(long, default_value = "1024")]
+        max_connections: usize,
+        /// seconds a connection is served for before it is dropped
+        #[arg(long, default_value = "30")]
+        connection_timeout: u64,
+        /// log the counts of stored and refused puts every this many seconds, at the info level
+        #[arg(long, default_value = "600")]
+        metrics_interval: u64,
+    },
+    /// Send a message to the counterparty through a relay or Nostr, encrypted to them
+    Send {
+        name: String,
+        /// address of the relay
+        #[arg(long, required_unless_present_any = ["nostr", "outbox"])]
+        relay: Option<String>,
+        /// Nostr relay to publish to, may be given multiple times
+        #[arg(long, conflicts_with_all = ["relay", "outbox"])]
+        nostr: Vec<String>,
+        /// directory to drop the message into
+        #[arg(long, conflicts_with = "relay")]
+        outbox: Option<PathBuf>,
+        /// the message as JSON, a file containing it, or - to read it from stdin without a prompt
+        #[arg(short, long)]
+        message: Option<String>,
+    },
+    /// Fetch messages for us from a relay or Nostr, one per line
+    Fetch {
+        name: String,
+        /// address of the relay
+        #[arg(long, required_unless_present_any = ["nostr", "inbox"])]
+        relay: Option<String>,
+        /// Nostr relay to query, may be given multiple times
+        #[arg(long, conflicts_with_all = ["relay", "inbox"])]
+        nostr: Vec<String>,
+        /// directory to pick up dropped messages from
+        #[arg(long, conflicts_with = "relay")]
+        inbox: Option<PathBuf>,
+        /// skip messages fetched before, as given by the last fetch
+        #[arg(long, default_value_t = 0)]
+        since: u64,
+    },
+    /// List the liquidity advertisements published on a relay
+    Ads {
+        /// address of the relay
+        #[arg(long)]
+        relay: String,
+        /// skip advertisements fetched before, as given by the last fetch
+        #[arg(long, default_value_t = 0)]
+        since: u64,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ContactsCommands {
+    Add {
+        label: String,
+        address: String,
+        /// where to send messages for this counterparty
+        #[arg(long)]
+        endpoint: Option<String>,
+        /// chain id of the network they prefer
+        #[arg(long)]
+        chain_id: Option<u128>,
+    },
+    List,
+    Remove {
+        label: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum SyncCommands {
+    /// Ask the counterparty for the messages after our last one
+    Request {
+        name: String,
+        /// write the request to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Answer a sync request of the counterparty with the messages they are missing
+    Answer {
+        name: String,
+        /// the request as JSON, a file containing it, or - to read it from stdin without a prompt
+        request: Option<String>,
+        /// write the answer to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Add the messages of the counterparty's answer, after checking their signatures
+    Apply {
+        name: String,
+        /// the answer as JSON, a file containing it, or - to read it from stdin without a prompt
+        answer: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum PolicyCommands {
+    Get {
+        name: String,
+    },
+    Set {
+        name: String,
+        /// maximum wei per transfer
+        #[arg(long, value_parser = parse_wei)]
+        max_transfer: Option<u128>,
+        /// maximum wei paid within 24 hours
+        #[arg(long, value_parser = parse_wei)]
+        max_daily_outflow: Option<u128>,
+        /// only pay this counterparty, may be given multiple times
+        #[arg(long)]
+        allow: Vec<String>,
+        /// who pays the gas of userops, as agreed with the counterparty: even or submitter
+        #[arg(long)]
+        gas_split: Option<String>,
+        /// accept incoming userops with a max fee per gas up to this, instead of only ours
+        #[arg(long, value_parser = parse_wei)]
+        accept_max_fee: Option<u128>,
+        /// accept incoming userops with a max priority fee per gas up to this, instead of only ours
+        #[arg(long, value_parser = parse_wei)]
+        accept_priority_fee: Option<u128>,
+        /// accept incoming userops with gas limits deviating from ours by this many percent
+        #[arg(long, default_value_t = 0)]
+        gas_limit_tolerance: u64,
+        /// read balances and disputes at latest, safe, finalized or this many confirmations
+        #[arg(long)]
+        read_at: Option<BlockPolicy>,
+        /// seconds to reuse what was read from the chain for, zero to always read it
+        #[arg(long)]
+        cache_ttl: Option<u64>,
+    },
 }
 
 #[tokio::main(flavor = "current_thread")]
 async fn main() {
     let cli = Cli::parse();
+    let filter = match EnvFilter::try_new(&cli.log_level) {
+        Ok(filter) => filter,
+        Err(err) => {
+            eprintln!("invalid log level: {err}");
+            return;
+        }
+    };
+    let logger = tracing_subscriber::fmt().with_env_filter(filter).with_writer(std::io::stderr);
+    if cli.log_json {
+        logger.json().init();
+    } else {
+        logger.init();
+    }
+    match &cli.command {
+        Commands::Completions { shell } => {
+            if let Err(err) = completions::generate(*shell, &mut std::io::stdout()) {
+                eprintln!("unable to print completions: {err}");
+            }
+            return;
+        }
+        Commands::Names => {
+            match store().list().await {
+                Ok(names) => names.iter().for_each(|name| println!("{name}")),
+                Err(err) => eprintln!("unable to list channels: {err}"),
+            }
+            return;
+        }
+        _ => {}
+    }
+    if let Commands::Relay { listen, puts_per_minute, gets_per_minute, burst, max_skew, max_connections, connection_timeout, metrics_interval } = &cli.command {
+        let config = RelayConfig {
+            puts_per_minute: *puts_per_minute,
+            gets_per_minute: *gets_per_minute,
+            burst: *burst,
+            max_skew: *max_skew,
+            max_connections: *max_connections,
+            connection_timeout: *connection_timeout,
+        };
+        let metrics = Arc::new(RelayMetrics::default());
+        let logged = metrics.clone();
+        let interval = Duration::from_secs((*metrics_interval).max(1));
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(interval);
+            ticks.tick().await;
+            loop {
+                ticks.tick().await;
+                info!("relay puts: {logged}");
+            }
+        });
+        if let Err(err) = relay::serve_with(listen.as_str(), config, metrics).await {
+            eprintln!("relay failed: {err}");
+        }
+        return;
+    }
+
     let Ok(rpc) = env::var("ETH_RPC_URL") else {
         eprintln!("unable to read ETH_RPC_URL from env!");
         return;
     };
 
-    let Ok(provider) = Provider::<Http>::try_from(rpc) else {
-        eprintln!("unable to create provider");
-        return;
-    };
-    let provider = Arc::new(provider);
+    // multiple endpoints can be given separated by commas
+    let mut endpoints = vec![];
+    for url in rpc.split(',') {
+        let Ok(endpoint) = url.trim().parse::<Http>() else {
+            eprintln!("unable to create provider for {url}");
+            return;
+        };
+        endpoints.push(endpoint);
+    }
+    let provider = Arc::new(Provider::new(FailoverClient::new(endpoints)));
 
     let mut data_dir = dirs::home_dir().unwrap();
     data_dir.push(".ch4nn337");
@@ -75,47 +695,291 @@ async fn main() {
     }
 }
 
-async fn execute(cli: Cli, provider: Arc<Provider<Http>>) -> Result<(), anyhow::Error> {
+async fn execute(cli: Cli, provider: Arc<Client>) -> Result<(), anyhow::Error> {
+    let seal = cli.seal;
+    let prices = price_source(&cli, provider.clone())?;
+    resume_interrupted(seal, provider.clone()).await?;
     match cli.command {
-        Commands::Open { chain_id, entry_point, factory, name } => {
+        Commands::Propose { entry_point, factory, name, qr, advertise, fee, expires_in, relay } => {
+            if read(&name).await.is_some() {
+                eprintln!("channel {name} already exists");
+                return Ok(());
+            }
             let Ok(entry_point) = entry_point.parse() else {
                 eprintln!("entry point is not an address");
                 return Ok(());
             };
-            let Ok(factory) = factory.parse() else {
-                eprintln!("factory is not an address");
+            let Some(factory) = resolve_factory(factory, &provider).await? else {
                 return Ok(());
             };
-
-            let (a, b) = match Channel::open(chain_id.into(), entry_point, factory, provider).await {
-                Ok(x) => x,
-                Err(err) => {
-                    eprintln!("could not open channel: {err}");
+            let chain_id = provider.get_chainid().await?;
+            let (channel, proposal) = Channel::propose(chain_id, entry_point, factory);
+            if let Err(err) = channel.verify_contracts(provider).await {
+                eprintln!("refusing to propose channel: {err}");
+                return Ok(());
+            }
+            write(&name, &channel, None).await?;
+            if let Some(funding) = advertise {
+                let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+                let Some(expires) = expires_in.checked_mul(3600).and_then(|seconds| now.checked_add(seconds)) else {
+                    eprintln!("the advertisement would expire too far in the future");
                     return Ok(());
+                };
+                let advertisement = channel.advertise(proposal, funding, fee, expires).await?;
+                if let Some(relay) = relay {
+                    RelayClient::new(relay).publish(&advertisement).await?;
+                    eprintln!("Advertisement published, confirm with the acceptance of whoever takes it.");
                 }
+                println!("{}", serde_json::to_string(&advertisement)?);
+                return Ok(());
+            }
+            let proposal = serde_json::to_string(&proposal)?;
+            eprintln!("Send this proposal to the counterparty, then confirm with their acceptance:");
+            println!("{proposal}");
+            if qr {
+                print_qr(&proposal)?;
+            }
+        }
+        Commands::Accept { name, proposal, from_ad, qr } => {
+            if read(&name).await.is_some() {
+                eprintln!("channel {name} already exists");
+                return Ok(());
+            }
+            let proposal: Proposal = if from_ad {
+                let advertisement: SignedAdvertisement = serde_json::from_str(&read_message(proposal)?)?;
+                let advertisement = match advertisement.verify() {
+                    Ok(advertisement) => advertisement,
+                    Err(err) => {
+                        eprintln!("refusing advertisement: {err}");
+                        return Ok(());
+                    }
+                };
+                eprintln!("Funding {} for a fee of {}", format_amount(advertisement.funding), format_amount(advertisement.fee));
+                advertisement.proposal.clone()
+            } else {
+                serde_json::from_str(&read_message(proposal)?)?
             };
-
-            write(&format!("{name}_a"), &a);
-            write(&format!("{name}_b"), &b);
-            println!("{name}_a and {name}_b successfully created!");
-            println!("Channel address: {:?}", a.address());
-            println!("{name}_a address: {:?}", a.our_address());
-            println!("{name}_b address: {:?}", b.our_address());
+            let chain_id = provider.get_chainid().await?;
+            if proposal.chain_id != chain_id {
+                eprintln!("proposal is for chain {}, but ETH_RPC_URL is on chain {chain_id}", proposal.chain_id);
+                return Ok(());
+            }
+            let (channel, acceptance) = Channel::accept(&proposal)?;
+            if let Err(err) = channel.verify_contracts(provider).await {
+                eprintln!("refusing to accept channel: {err}");
+                return Ok(());
+            }
+            write(&name, &channel, None).await?;
+            println!("Channel address: {:?}", channel.address());
+            let acceptance = serde_json::to_string(&acceptance)?;
+            eprintln!("Send this acceptance to the counterparty:");
+            println!("{acceptance}");
+            if qr {
+                print_qr(&acceptance)?;
+            }
+        }
+        Commands::Confirm { name, acceptance, fund, funding_key } => {
+            let Some((mut channel, version)) = read(&name).await else {
+                eprintln!("unable to load channel data");
+                return Ok(());
+            };
+            let acceptance: Acceptance = serde_json::from_str(&read_message(acceptance)?)?;
+            channel.confirm(&acceptance)?;
+            write(&name, &channel, Some(version)).await?;
+            println!("{name} is open at {:?} with {:?}", channel.address(), channel.their_address());
+            if let (Some(wei), Some(funding_key)) = (fund, funding_key) {
+                eprintln!("Please enter the password of the funding key:");
+                let password = read_line();
+                let chain_id = provider.get_chainid().await?;
+                let wallet = LocalWallet::decrypt_keystore(funding_key, password)?.with_chain_id(chain_id.as_u64());
+                let client = Arc::new(SignerMiddleware::new(provider.clone(), wallet));
+                let transaction = channel.fund(wei.into(), client).await?;
+                println!("Funded with {} in transaction {transaction:?}", Wei(wei));
+            }
+        }
+        Commands::DeployFactory { entry_point } => {
+            let Ok(entry_point) = entry_point.parse() else {
+                eprintln!("entry point is not an address");
+                return Ok(());
+            };
+            let Ok(key) = env::var("ETH_PRIVATE_KEY") else {
+                eprintln!("unable to read ETH_PRIVATE_KEY from env!");
+                return Ok(());
+            };
+            let chain_id = provider.get_chainid().await?;
+            let wallet = key.parse::<LocalWallet>()?.with_chain_id(chain_id.as_u64());
+            let client = Arc::new(SignerMiddleware::new(provider, wallet));
+            let factory = ch4nn337_lib::deploy_factory(entry_point, client).await?;
+            let mut factories = read_factories();
+            factories.insert(chain_id.to_string(), factory);
+            write_factories(&factories)?;
+            println!("Factory deployed at {factory:?}, used by default for chain {chain_id}");
+        }
+        Commands::Receipt { name, invoice } => {
+            let Some((channel, _)) = read(&name).await else {
+                eprintln!("unable to load channel data");
+                return Ok(());
+            };
+            let invoice = match invoice {
+                Some(invoice) => match invoice.parse() {
+                    Ok(invoice) => Some(invoice),
+                    Err(_) => {
+                        eprintln!("invoice is not a hash");
+                        return Ok(());
+                    }
+                },
+                None => None,
+            };
+            let receipt = channel.payment_receipt(invoice, provider).await?;
+            println!("{}", serde_json::to_string(&receipt)?);
+        }
+        Commands::VerifyReceipt { receipt } => {
+            let receipt: PaymentReceipt = serde_json::from_str(&read_message(receipt)?)?;
+            verify_receipt(&receipt)?;
+            println!("Signed by both {:?} and {:?}.", receipt.party_a, receipt.party_b);
+            println!("Channel {:?}, nonce {}, value transfer {} (this payment {}).", receipt.userop.sender, receipt.userop.nonce, SignedWei(receipt.value_transfer), receipt.amount);
+        }
+        Commands::Inspect { address, party_a, party_b, salt, factory, json } => {
+            let address = match (address, party_a, party_b, salt) {
+                (Some(address), _, _, _) => match address.parse() {
+                    Ok(address) => address,
+                    Err(_) => {
+                        eprintln!("channel is not an address");
+                        return Ok(());
+                    }
+                },
+                (None, Some(party_a), Some(party_b), Some(salt)) => {
+                    let (Some(party_a), Some(party_b)) = (parse_party(&party_a), parse_party(&party_b)) else {
+                        eprintln!("parties have to be addresses or hex encoded public keys");
+                        return Ok(());
+                    };
+                    let Some(salt) = U256::from_dec_str(&salt).ok().or_else(|| salt.parse().ok()) else {
+                        eprintln!("salt is not a number");
+                        return Ok(());
+                    };
+                    let Some(factory) = resolve_factory(factory, &provider).await? else {
+                        return Ok(());
+                    };
+                    inspect::channel_address(factory, party_a, party_b, salt)
+                }
+                _ => unreachable!("clap requires an address or parties and salt"),
+            };
+            let report = inspect::inspect(address, provider).await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+                return Ok(());
+            }
+            println!("Channel {address:?}");
+            if !report.deployed {
+                println!("Not deployed, holding {} for party A", Wei::try_from(report.balance)?);
+                return Ok(());
+            }
+            if let Some((party_a, party_b)) = report.parties {
+                println!("A: {party_a:?} with balance {}", Wei(report.balance_a));
+                println!("B: {party_b:?} with balance {}", Wei(report.balance_b));
+            }
+            println!("Nonce: {}", report.nonce);
+            match report.dispute {
+                Some(dispute) => {
+                    println!("DISPUTE!");
+                    println!("Dispute nonce: {}", dispute.start_nonce);
+                    println!("Dispute value transfer: {}", SignedWei(dispute.value_transfer));
+                    println!("Dispute timeout: {}", dispute.timeout);
+                }
+                None => println!("No ongoing dispute"),
+            }
+        }
+        Commands::Recover { name, counterparty, salt, factory } => {
+            if read(&name).await.is_some() {
+                eprintln!("channel {name} already exists");
+                return Ok(());
+            }
+            let Ok(counterparty) = counterparty.parse() else {
+                eprintln!("counterparty is not an address");
+                return Ok(());
+            };
+            let Some(salt) = U256::from_dec_str(&salt).ok().or_else(|| salt.parse().ok()) else {
+                eprintln!("salt is not a number");
+                return Ok(());
+            };
+            let Some(factory) = resolve_factory(factory, &provider).await? else {
+                return Ok(());
+            };
+            eprintln!("Please paste the channel key (hex):");
+            let Some(key) = hex::decode(read_line().trim().trim_start_matches("0x")).ok().and_then(|key| SigningKey::from_slice(&key).ok()) else {
+                eprintln!("key is not a hex encoded private key");
+                return Ok(());
+            };
+            let channel = Channel::recover(key, factory, counterparty, salt, provider).await?;
+            write(&name, &channel, None).await?;
+            println!("Recovered {name} at {:?} in state {:?}, without history.", channel.address(), channel.state());
+            println!("Ask the counterparty to resend their latest update.");
+        }
+        Commands::Verify { name } => {
+            let Some((channel, _)) = read(&name).await else {
+                eprintln!("unable to load channel data");
+                return Ok(());
+            };
+            channel.verify_contracts(provider).await?;
+            println!("Contracts of {name} run the expected code.");
+        }
+        Commands::Audit { name } => {
+            let Some((channel, _)) = read(&name).await else {
+                eprintln!("unable to load channel data");
+                return Ok(());
+            };
+            channel.audit()?;
+            println!("All {} messages of {name} are consistent.", channel.messages().len());
         }
-        Commands::Status { name } => {
-            let Some(channel) = read(&name) else {
+        Commands::Status { name, trusted_block, history, from_block, refresh } => {
+            let Some((mut channel, version)) = read(&name).await else {
                 eprintln!("unable to load channel data");
                 return Ok(());
             };
+            if let Some(block) = trusted_block {
+                let Ok(block) = block.parse() else {
+                    eprintln!("trusted block is not a hash");
+                    return Ok(());
+                };
+                channel.set_trusted_block(Some(block));
+            }
+            if refresh || !channel.is_cached() {
+                channel.refresh(provider.clone()).await?;
+            }
             let (our_balance, their_balance) = channel.get_sorted_balances(provider.clone()).await?;
+            let price = match &prices {
+                Some(prices) => Some(prices.price().await?),
+                None => None,
+            };
+            let in_fiat = |wei: u128| match &price {
+                Some(price) => format!(" ({} {})", format_fiat(price.to_fiat(wei)), price.currency),
+                None => String::new(),
+            };
             println!("{name} at {:?}", channel.address());
-            println!("Us:   {:?} with balance {our_balance}", channel.our_address());
-            println!("Them: {:?} with balance {their_balance}", channel.their_address());
+            println!("Us:   {:?} with balance {}{}", channel.our_address()?, our_balance, in_fiat(our_balance.0));
+            match read_contacts().counterparty(&channel) {
+                Some(contact) => println!("Them: {} ({:?}) with balance {}{}", contact.label, channel.their_address(), their_balance, in_fiat(their_balance.0)),
+                None => println!("Them: {:?} with balance {}{}", channel.their_address(), their_balance, in_fiat(their_balance.0)),
+            }
+            if let Some(price) = &price {
+                println!("Price: {price}");
+            }
+            println!("Entry point deposit: {}", Wei::try_from(channel.entry_point_deposit(provider.clone()).await?)?);
+            for charge in channel.gas_charges() {
+                println!("Gas of {:?}: {}, A paid {}, B paid {}", charge.transaction, format_amount(charge.cost), format_amount(charge.paid_a), format_amount(charge.paid_b));
+            }
             println!("Last nonce: {}", channel.last_nonce());
+            println!("State: {:?}", channel.update_state(provider.clone()).await?);
+            if let Some(known) = channel.known_state() {
+                println!("Read at block {}, {}s ago", known.block, known.age());
+            }
+            if !channel.is_reconciled() {
+                println!("Pending transfer was requested offline, run `reconcile` to check it.");
+            }
             if let Some(_) = channel.pending_message() {
                 println!("Waiting for response...");
             }
-            if let Some(dispute) = channel.get_dispute_info(provider).await? {
+            if let Some(dispute) = channel.get_dispute_info(provider.clone()).await? {
                 println!("DISPUTE!");
                 println!("Dispute nonce: {}", dispute.nonce);
                 println!("Dispute timeout: {}", dispute.timeout);
@@ -124,74 +988,985 @@ async fn execute(cli: Cli, provider: Arc<Provider<Http>>) -> Result<(), anyhow::
             } else {
                 println!("No ongoing dispute :)")
             }
+            match channel.withdrawal_status(provider.clone()).await? {
+                Some(WithdrawalStatus::Submitted) => println!("Withdrawal submitted, waiting for inclusion..."),
+                Some(WithdrawalStatus::Included { transaction }) => println!("Withdrawal included in {transaction:?}"),
+                Some(WithdrawalStatus::Failed { transaction }) => println!("WITHDRAWAL FAILED in {transaction:?}"),
+                None => {}
+            }
+            if history {
+                println!("On-chain history:");
+                for event in channel.onchain_history(from_block, provider).await? {
+                    let action = match event.action {
+                        Action::Dispute { value_transfer } => format!("dispute with value transfer {}", SignedWei(value_transfer)),
+                        Action::Withdrawal { value_transfer, withdraw_a, withdraw_b } => format!("withdrawal of {} to A and {} to B after value transfer {}", format_amount(withdraw_a), format_amount(withdraw_b), SignedWei(value_transfer)),
+                        Action::Other => "other call".to_string(),
+                    };
+                    let failed = if event.success { "" } else { " (FAILED)" };
+                    let unknown = if event.known { "" } else { ", NOT IN LOCAL HISTORY" };
+                    println!("Block {} tx {:?}: nonce {} {action}{failed}, gas {}{unknown}", event.block, event.transaction, event.nonce, format_amount(event.gas_cost.low_u128()));
+                }
+            }
+            write(&name, &channel, Some(version)).await?;
+        }
+        Commands::List => {
+            let mut channels = vec![];
+            for name in store().list().await? {
+                match read(&name).await {
+                    Some((channel, _)) => channels.push((name, channel)),
+                    None => eprintln!("unable to load {name}"),
+                }
+            }
+            let refs: Vec<&Channel> = channels.iter().map(|(_, channel)| channel).collect();
+            let accounts = snapshot::read(provider.as_ref(), &refs, BlockPolicy::default()).await?;
+            for ((name, channel), account) in channels.iter().zip(&accounts) {
+                let (our_balance, their_balance) = channel.balances_at(account)?;
+                let dispute = match channel.dispute_info_at(account)? {
+                    Some(dispute) => format!(", DISPUTE of nonce {} until {}", dispute.nonce, dispute.timeout),
+                    None => String::new(),
+                };
+                println!("{name}: {:?}, us {our_balance}, them {their_balance}{dispute}", channel.state());
+            }
+        }
+        Commands::Export { name, format, from_block } => {
+            let Some((channel, _)) = read(&name).await else {
+                eprintln!("unable to load channel data");
+                return Ok(());
+            };
+            let history = channel.history(from_block, provider).await?;
+            match format.as_str() {
+                "csv" => print!("{}", export::to_csv(&history)),
+                "json" => println!("{}", serde_json::to_string_pretty(&history)?),
+                "ofx" => print!("{}", export::to_ofx(&history, channel.address())),
+                _ => eprintln!("unknown format {format}, use csv, json or ofx"),
+            }
+        }
+        Commands::Deploy { name, dry_run } => {
+            let Some((channel, _)) = read(&name).await else {
+                eprintln!("unable to load channel data");
+                return Ok(());
+            };
+            if dry_run {
+                let preview = channel.preview_deploy(provider).await?;
+                println!("Factory {:?}: {}", preview.factory, preview.call);
+                match preview.gas {
+                    Some(gas) => println!("Estimated gas: {gas}"),
+                    None => println!("The channel is deployed already, deploying would revert."),
+                }
+                return Ok(());
+            }
+            let Ok(key) = env::var("ETH_PRIVATE_KEY") else {
+                eprintln!("unable to read ETH_PRIVATE_KEY from env!");
+                return Ok(());
+            };
+            let chain_id = provider.get_chainid().await?;
+            let wallet = key.parse::<LocalWallet>()?.with_chain_id(chain_id.as_u64());
+            let client = Arc::new(SignerMiddleware::new(provider.clone(), wallet));
+            channel.deploy(client).await?;
+            println!("Deployed channel at {:?}", channel.address());
         }
-        Commands::Deploy { name } => todo!(),
-        Commands::Request { name, wei } => {
-            let Some(mut channel) = read(&name) else {
+        Commands::AddDeposit { name, wei } => {
+            let Some((mut channel, version)) = read(&name).await else {
                 eprintln!("unable to load channel data");
                 return Ok(());
             };
-            let request = channel.request_transfer(wei, provider).await?;
-            println!("Send this to be signed by the counterparty:\n{request}");
-            write(&name, &channel);
+            let Ok(key) = env::var("ETH_PRIVATE_KEY") else {
+                eprintln!("unable to read ETH_PRIVATE_KEY from env!");
+                return Ok(());
+            };
+            let chain_id = provider.get_chainid().await?;
+            let wallet = key.parse::<LocalWallet>()?.with_chain_id(chain_id.as_u64());
+            let client = Arc::new(SignerMiddleware::new(provider.clone(), wallet));
+            channel.add_deposit(wei.into(), client).await?;
+            channel.invalidate_cache();
+            write(&name, &channel, Some(version)).await?;
+            println!("Deposit is now {}", Wei::try_from(channel.entry_point_deposit(provider).await?)?);
         }
-        Commands::Withdraw { name } => {
-            let Some(mut channel) = read(&name) else {
+        Commands::Rename { name, new_name } => {
+            let Some((_, version)) = read(&name).await else {
                 eprintln!("unable to load channel data");
                 return Ok(());
             };
-            let request = channel.request_full_withdraw(provider).await?;
-            println!("Send this to be signed by the counterparty:\n{request}");
-            write(&name, &channel);
+            store().rename(&name, &new_name, version).await?;
+            println!("Renamed {name} to {new_name}");
         }
-        Commands::Receive { name } => {
-            let Some(mut channel) = read(&name) else {
+        Commands::Archive { name } => {
+            let Some((channel, version)) = read(&name).await else {
                 eprintln!("unable to load channel data");
                 return Ok(());
             };
-            println!("Please paste message:");
-            let userop = serde_json::from_str(&read_line())?;
-            let request = channel.receive_message(userop, provider.clone()).await?;
-            println!("Sign? (y/N)");
-            let mut line = read_line();
-            line.make_ascii_lowercase();
-            if line == "y" {
-                let response = channel.sign_message(request, provider).await?;
-                println!("Please send this response back:\n{response}");
-                write(&name, &channel);
+            let store = store();
+            if !channel.is_watch_only() {
+                eprintln!("Please enter a password for the key backup:");
+                let password = read_line();
+                if password.is_empty() {
+                    eprintln!("the password must not be empty");
+                    return Ok(());
+                }
+                let backup = channel.backup_key(&store.archive_dir(), &name, &password)?;
+                println!("Key backed up to {}", backup.display());
+            }
+            store.archive(&name, version).await?;
+            println!("Archived {name}");
+        }
+        Commands::Delete { name, force, .. } => {
+            let Some((channel, version)) = read(&name).await else {
+                eprintln!("unable to load channel data");
+                return Ok(());
+            };
+            if let Err(err) = channel.check_disposable(provider).await {
+                if !force {
+                    eprintln!("refusing to delete {name}: {err}");
+                    return Ok(());
+                }
+                eprintln!("WARNING: deleting {name} anyway: {err}");
+            }
+            store().delete(&name, version).await?;
+            println!("Deleted {name}");
+        }
+        Commands::Request { name, wei, fiat, qr, dry_run, op_id, offline, max_age } => {
+            let Some((mut channel, version)) = read(&name).await else {
+                eprintln!("unable to load channel data");
+                return Ok(());
+            };
+            let payment = match (wei, fiat) {
+                (Some(wei), _) => (wei, None),
+                (None, Some(fiat)) => {
+                    let Some(prices) = prices else {
+                        eprintln!("fiat requests need --price-feed or --price-url");
+                        return Ok(());
+                    };
+                    let Some((amount, currency)) = parse_fiat(&fiat) else {
+                        eprintln!("fiat amount is not like 5.00USD");
+                        return Ok(());
+                    };
+                    let price = prices.price().await?;
+                    if currency != price.currency {
+                        eprintln!("price source quotes {}, not {currency}", price.currency);
+                        return Ok(());
+                    }
+                    let Some(wei) = NonZeroU128::new(price.to_wei(amount)) else {
+                        eprintln!("amount is less than a wei");
+                        return Ok(());
+                    };
+                    println!("{} {currency} is {} at {price}", format_fiat(amount), Wei(wei.get()));
+                    (wei, Some(format!("{} {currency} at {price}", format_fiat(amount))))
+                }
+                (None, None) => unreachable!("clap requires wei or --fiat"),
+            };
+            if dry_run {
+                print_preview(&channel.preview_transfer(payment.0, provider).await?)?;
+                return Ok(());
+            }
+            let Some(max_age) = max_age.checked_mul(3600) else {
+                eprintln!("max age is out of range");
+                return Ok(());
+            };
+            let request = match op_id {
+                _ if offline => channel.request_transfer_offline(vec![payment], max_age).await?,
+                Some(op_id) => channel.request_transfer_batch_once(&op_id, vec![payment], provider).await?,
+                None => channel.request_transfer_batch(vec![payment], provider).await?,
+            };
+            if offline {
+                eprintln!("Checked against the state of block {}, run `reconcile` once online.", channel.known_state().map_or(0, |known| known.block));
+            }
+            hand_out(&channel, seal, qr, request)?;
+            write(&name, &channel, Some(version)).await?;
+        }
+        Commands::Batch { name, payments, qr, op_id } => {
+            let Some((mut channel, version)) = read(&name).await else {
+                eprintln!("unable to load channel data");
+                return Ok(());
+            };
+            let mut parsed = vec![];
+            for payment in payments {
+                let (wei, memo) = match payment.split_once(':') {
+                    Some((wei, memo)) => (wei, Some(memo.to_string())),
+                    None => (payment.as_str(), None),
+                };
+                let wei = match parse_positive_wei(wei) {
+                    Ok(wei) => wei,
+                    Err(err) => {
+                        eprintln!("{err}");
+                        return Ok(());
+                    }
+                };
+                parsed.push((wei, memo));
+            }
+            let request = match op_id {
+                Some(op_id) => channel.request_transfer_batch_once(&op_id, parsed, provider).await?,
+                None => channel.request_transfer_batch(parsed, provider).await?,
+            };
+            hand_out(&channel, seal, qr, request)?;
+            write(&name, &channel, Some(version)).await?;
+        }
+        Commands::Withdraw { name, qr, dry_run, op_id } => {
+            let Some((mut channel, version)) = read(&name).await else {
+                eprintln!("unable to load channel data");
+                return Ok(());
+            };
+            if dry_run {
+                print_preview(&channel.preview_full_withdraw(provider).await?)?;
+                return Ok(());
+            }
+            let request = match op_id {
+                Some(op_id) => channel.request_full_withdraw_once(&op_id, provider).await?,
+                None => channel.request_full_withdraw(provider).await?,
+            };
+            hand_out(&channel, seal, qr, request)?;
+            write(&name, &channel, Some(version)).await?;
+        }
+        Commands::Bump { name, qr } => {
+            let Some((mut channel, version)) = read(&name).await else {
+                eprintln!("unable to load channel data");
+                return Ok(());
+            };
+            let request = channel.bump_withdrawal_fee().await?;
+            hand_out(&channel, seal, qr, request)?;
+            write(&name, &channel, Some(version)).await?;
+        }
+        Commands::Resubmit { name, qr } => {
+            let Some((channel, _)) = read(&name).await else {
+                eprintln!("unable to load channel data");
+                return Ok(());
+            };
+            let response = channel.resubmit_withdrawal(provider).await?;
+            let response = outgoing(&channel, seal, response)?;
+            println!("Send this to the counterparty:\n{response}");
+            if qr {
+                print_qr(&response)?;
+            }
+        }
+        Commands::Receive { name, yes, message, scan, output, qr, funding } => {
+            let Some((mut channel, version)) = read(&name).await else {
+                eprintln!("unable to load channel data");
+                return Ok(());
+            };
+            if !yes && !stdin().is_terminal() {
+                eprintln!("stdin is not a terminal, pass --yes to sign without confirmation");
+                return Ok(());
+            }
+            // do not sign on top of a corrupted or tampered history
+            channel.audit()?;
+            let message = match scan {
+                Some(image) => scan_qr(&image)?,
+                None => read_message(message)?,
+            };
+            let message = incoming(&channel, &message)?;
+            if let Some(funding) = funding {
+                let Ok(funding) = funding.parse() else {
+                    eprintln!("funding is not a transaction hash");
+                    return Ok(());
+                };
+                let summary = channel.hold_for_funding(&message, funding, provider).await?;
+                write(&name, &channel, Some(version)).await?;
+                if let Summary::Transfer { amount, .. } = summary {
+                    eprintln!("They send {amount} to us once their funding confirms, sign it then with release.");
+                }
+                return Ok(());
+            }
+            let (request, summary) = channel.receive_message(&message, provider.clone()).await?;
+            match summary {
+                Summary::Transfer { amount, incoming, our_balance, their_balance } => {
+                    if incoming {
+                        eprintln!("They send {amount} to us.");
+                    } else {
+                        eprintln!("We send {amount} to them.");
+                    }
+                    eprintln!("Resulting balances: us {our_balance}, them {their_balance}");
+                }
+                Summary::Withdrawal { withdraw_us, withdraw_them, payout } => {
+                    eprintln!("Withdraw {withdraw_us} to us and {withdraw_them} to them.");
+                    match payout {
+                        Some((payout_us, payout_them)) => eprintln!("After fees from the deposit: {payout_us} to us and {payout_them} to them."),
+                        None => eprintln!("WARNING: the deposit does not cover the fees, the withdrawal will fail."),
+                    }
+                }
+            }
+            let confirmed = yes || {
+                eprintln!("Sign? (y/N)");
+                let mut line = read_line();
+                line.make_ascii_lowercase();
+                line == "y"
+            };
+            if confirmed && channel.is_watch_only() {
+                let request = channel.request_signature(request)?;
+                write(&name, &channel, Some(version)).await?;
+                emit(output, "Sign this on the offline machine", &serde_json::to_string(&request)?)?;
+            } else if confirmed {
+                // a crash while submitting a withdrawal is noticed on the next start
+                if matches!(request, Message::Withdrawal(_)) {
+                    store().begin(&name, &Intent::SignWithdrawal { message: request.clone() }).await?;
+                }
+                let response = channel.sign_message(request, provider).await;
+                // a withdrawal is recorded before it is submitted, keep it even if submitting failed
+                write(&name, &channel, Some(version)).await?;
+                store().complete(&name).await?;
+                let response = outgoing(&channel, seal, response?)?;
+                emit(output, "Please send this response back", &response)?;
+                if qr {
+                    print_qr(&response)?;
+                }
             } else {
-                println!("Abort.")
+                eprintln!("Abort.")
+            }
+        }
+        Commands::Release { name, output, qr } => {
+            let Some((mut channel, version)) = read(&name).await else {
+                eprintln!("unable to load channel data");
+                return Ok(());
+            };
+            if channel.funding_hold().is_none() {
+                eprintln!("no transfer is waiting for funding");
+                return Ok(());
+            }
+            let Some((request, _)) = channel.release_funded(provider.clone()).await? else {
+                write(&name, &channel, Some(version)).await?;
+                match channel.funding_hold() {
+                    Some(_) => eprintln!("The funding has not confirmed yet."),
+                    None => eprintln!("The held transfer was discarded, its funding failed or it is no longer valid."),
+                }
+                return Ok(());
+            };
+            let response = channel.sign_message(request, provider).await;
+            write(&name, &channel, Some(version)).await?;
+            let response = outgoing(&channel, seal, response?)?;
+            emit(output, "Please send this response back", &response)?;
+            if qr {
+                print_qr(&response)?;
+            }
+        }
+        Commands::Response { name, message } => {
+            let Some((mut channel, version)) = read(&name).await else {
+                eprintln!("unable to load channel data");
+                return Ok(());
+            };
+            channel.receive_response(&incoming(&channel, &read_message(message)?)?)?;
+            write(&name, &channel, Some(version)).await?;
+            eprintln!("Response accepted.");
+        }
+        Commands::Relay { .. } | Commands::Completions { .. } | Commands::Names => unreachable!("handled before connecting to the chain"),
+        Commands::Send { name, relay, nostr, outbox, message } => {
+            let Some((channel, _)) = read(&name).await else {
+                eprintln!("unable to load channel data");
+                return Ok(());
+            };
+            let message = read_message(message)?;
+            match (relay, outbox) {
+                (Some(relay), _) => RelayClient::new(relay).send(&channel, message.trim()).await?,
+                (_, Some(outbox)) => FileDrop::new(outbox, "").send(&channel, message.trim()).await?,
+                _ => NostrTransport::new(nostr).send(&channel, message.trim()).await?,
+            }
+            eprintln!("Message sent.");
+        }
+        Commands::Fetch { name, relay, nostr, inbox, since } => {
+            let Some((channel, _)) = read(&name).await else {
+                eprintln!("unable to load channel data");
+                return Ok(());
+            };
+            let (messages, next) = match (relay, inbox) {
+                (Some(relay), _) => RelayClient::new(relay).receive(&channel, since).await?,
+                (_, Some(inbox)) => FileDrop::new("", inbox).receive(&channel, since).await?,
+                _ => NostrTransport::new(nostr).receive(&channel, since).await?,
+            };
+            for message in messages {
+                println!("{message}");
             }
+            eprintln!("Next time, fetch with --since {next}");
+        }
+        Commands::Ads { relay, since } => {
+            let (advertisements, next) = RelayClient::new(relay).advertisements(since).await?;
+            for advertisement in advertisements {
+                let terms = &advertisement.advertisement;
+                eprintln!("Funding {} for a fee of {} on chain {}:", format_amount(terms.funding), format_amount(terms.fee), terms.proposal.chain_id);
+                println!("{}", serde_json::to_string(&advertisement)?);
+            }
+            eprintln!("Next time, fetch with --since {next}");
+        }
+        Commands::Reconcile { name } => {
+            let Some((mut channel, version)) = read(&name).await else {
+                eprintln!("unable to load channel data");
+                return Ok(());
+            };
+            let result = channel.reconcile(provider).await;
+            write(&name, &channel, Some(version)).await?;
+            result?;
+            println!("Up to date with the chain.");
         }
-        Commands::Response { name } => todo!(),
         Commands::Cancel { name } => {
-            let Some(mut channel) = read(&name) else {
+            let Some((mut channel, version)) = read(&name).await else {
                 eprintln!("unable to load channel data");
                 return Ok(());
             };
             if channel.cancel_pending_message() {
-                write(&name, &channel);
+                write(&name, &channel, Some(version)).await?;
                 println!("Cancelled.");
             } else {;
                 println!("Nothing to cancel.");
             }
         }
+        Commands::WatchOnly { name, copy } => {
+            let Some((channel, _)) = read(&name).await else {
+                eprintln!("unable to load channel data");
+                return Ok(());
+            };
+            if read(&copy).await.is_some() {
+                eprintln!("channel {copy} already exists");
+                return Ok(());
+            }
+            write(&copy, &channel.into_watch_only(), None).await?;
+            println!("Move {copy} to the online machine, {name} stays offline.");
+        }
+        Commands::Observer { name, copy } => {
+            let Some((channel, _)) = read(&name).await else {
+                eprintln!("unable to load channel data");
+                return Ok(());
+            };
+            if read(&copy).await.is_some() {
+                eprintln!("channel {copy} already exists");
+                return Ok(());
+            }
+            write(&copy, &channel.into_observer(), None).await?;
+            println!("{copy} can be handed out, it can not sign for {name}.");
+        }
+        Commands::SignOffline { name, request } => {
+            let Some((channel, _)) = read(&name).await else {
+                eprintln!("unable to load channel data");
+                return Ok(());
+            };
+            let request: SigningRequest = serde_json::from_str(&read_message(request)?)?;
+            eprintln!("Signing user operation {:?} with nonce {}", request.hash, request.userop.nonce);
+            let signature = channel.sign_request(&request).await?;
+            println!("{signature}");
+        }
+        Commands::ApplySignature { name, signature, qr } => {
+            let Some((mut channel, version)) = read(&name).await else {
+                eprintln!("unable to load channel data");
+                return Ok(());
+            };
+            let message = channel.apply_signature(signature.parse()?, provider).await;
+            // a withdrawal is recorded before it is submitted, keep it even if submitting failed
+            write(&name, &channel, Some(version)).await?;
+            let message = outgoing(&channel, seal, message?)?;
+            println!("Send this to the counterparty:\n{message}");
+            if qr {
+                print_qr(&message)?;
+            }
+        }
+        Commands::Close { name } => {
+            let Some((mut channel, version)) = read(&name).await else {
+                eprintln!("unable to load channel data");
+                return Ok(());
+            };
+            if channel.update_state(provider).await? != ChannelState::Closed {
+                write(&name, &channel, Some(version)).await?;
+                eprintln!("the channel has to be withdrawn before closing it");
+                return Ok(());
+            }
+            // archived as closed, so it is not mistaken for an open channel if ever restored
+            let store = store();
+            let version = store.save(&name, &channel, Some(version)).await?;
+            store.archive(&name, version).await?;
+            println!("{name} closed and archived");
+        }
+        Commands::Dispute { name } => {
+            let Some((mut channel, version)) = read(&name).await else {
+                eprintln!("unable to load channel data");
+                return Ok(());
+            };
+            channel.start_dispute(provider).await?;
+            write(&name, &channel, Some(version)).await?;
+            println!("Dispute submitted, it can be closed once it timed out.");
+        }
+        Commands::CloseDispute { name } => {
+            let Some((mut channel, version)) = read(&name).await else {
+                eprintln!("unable to load channel data");
+                return Ok(());
+            };
+            let Ok(key) = env::var("ETH_PRIVATE_KEY") else {
+                eprintln!("unable to read ETH_PRIVATE_KEY from env!");
+                return Ok(());
+            };
+            let chain_id = provider.get_chainid().await?;
+            let wallet = key.parse::<LocalWallet>()?.with_chain_id(chain_id.as_u64());
+            let client = Arc::new(SignerMiddleware::new(provider.clone(), wallet));
+            // the counterparty may have started the dispute
+            channel.update_state(provider).await?;
+            channel.close_dispute(client).await?;
+            write(&name, &channel, Some(version)).await?;
+            println!("Dispute closed, the balances are paid out.");
+        }
+        Commands::Monitor { interval, mut threshold, webhook, desktop, auto_dispute_after, auto_close, ws } => {
+            threshold.sort_unstable();
+            let closer = if auto_close {
+                let Ok(key) = env::var("ETH_PRIVATE_KEY") else {
+                    eprintln!("unable to read ETH_PRIVATE_KEY from env!");
+                    return Ok(());
+                };
+                let chain_id = provider.get_chainid().await?;
+                let wallet = key.parse::<LocalWallet>()?.with_chain_id(chain_id.as_u64());
+                Some(Arc::new(SignerMiddleware::new(provider.clone(), wallet)))
+            } else {
+                None
+            };
+            // smallest threshold we warned about for each dispute
+            let mut warned: HashMap<(String, u128), u64> = HashMap::new();
+            let interval = Duration::from_secs(interval);
+            let mut watcher = ChainWatcher::new(ws, interval);
+            let mut checked: Option<Instant> = None;
+            loop {
+                let wakeup = tokio::select! {
+                    wakeup = watcher.next(provider.as_ref()) => wakeup,
+                    _ = tokio::signal::ctrl_c() => break,
+                };
+                let active = match wakeup {
+                    // the dispute timers do not need a check every block
+                    Ok(Wakeup::Head(_)) if checked.is_some_and(|checked| checked.elapsed() < interval) => continue,
+                    Ok(Wakeup::Activity(active)) => active,
+                    Ok(_) => vec![],
+                    Err(err) => {
+                        eprintln!("unable to watch the chain: {err}");
+                        vec![]
+                    }
+                };
+                checked = Some(Instant::now());
+                let mut channels = vec![];
+                for name in store().list().await? {
+                    if let Some((mut channel, mut version)) = read(&name).await {
+                        // a userop of the channel was included, so what was read is outdated
+                        if active.contains(&channel.address()) && channel.known_state().is_some() {
+                            channel.invalidate_cache();
+                            match store().save(&name, &channel, Some(version)).await {
+                                Ok(saved) => version = saved,
+                                Err(err) => eprintln!("unable to save {name}: {err}"),
+                            }
+                        }
+                        channels.push((name, channel, version));
+                    }
+                }
+                // one read for all channels, falling back to reading them one by one
+                let refs: Vec<&Channel> = channels.iter().map(|(_, channel, _)| channel).collect();
+                watcher.watch(&refs);
+                let accounts = match snapshot::read(provider.as_ref(), &refs, BlockPolicy::default()).await {
+                    Ok(accounts) => Some(accounts),
+                    Err(err) => {
+                        eprintln!("unable to read all channels at once: {err}");
+                        None
+                    }
+                };
+                for (index, (name, mut channel, version)) in channels.into_iter().enumerate() {
+                    if let Some(hours) = auto_dispute_after {
+                        match channel.should_dispute(hours * 3600, provider.clone()).await {
+                            Ok(false) => {}
+                            Ok(true) => {
+                                let text = match channel.start_dispute(provider.clone()).await {
+                                    Ok(()) => format!("Started a dispute of {name}, the counterparty has not answered for {hours}h."),
+                                    Err(err) => format!("Failed to start a dispute of {name}: {err}"),
+                                };
+                                if let Err(err) = write(&name, &channel, Some(version)).await {
+                                    eprintln!("unable to save {name}: {err}");
+                                }
+                                let payload = serde_json::json!({ "channel": name, "address": channel.address(), "action": "start_dispute", "message": text });
+                                alert(&text, payload, webhook.as_deref(), desktop).await;
+                                continue;
+                            }
+                            Err(err) => {
+                                eprintln!("unable to check {name}: {err}");
+                                continue;
+                            }
+                        }
+                    }
+                    let dispute = match &accounts {
+                        Some(accounts) => channel.dispute_info_at(&accounts[index]),
+                        None => channel.get_dispute_info(provider.clone()).await,
+                    };
+                    let dispute = match dispute {
+                        Ok(Some(dispute)) => dispute,
+                        Ok(None) => continue,
+                        Err(err) => {
+                            eprintln!("unable to check {name}: {err}");
+                            continue;
+                        }
+                    };
+                    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+                    let remaining = dispute.timeout.saturating_sub(now);
+                    if let (0, Some(closer)) = (remaining, &closer) {
+                        let result = match channel.update_state(provider.clone()).await {
+                            Ok(_) => channel.close_dispute(closer.clone()).await,
+                            Err(err) => Err(err),
+                        };
+                        let text = match result {
+                            Ok(()) => format!("Closed the dispute of {name}, the balances are paid out."),
+                            Err(err) => format!("Failed to close the dispute of {name}: {err}"),
+                        };
+                        if let Err(err) = write(&name, &channel, Some(version)).await {
+                            eprintln!("unable to save {name}: {err}");
+                        }
+                        let payload = serde_json::json!({ "channel": name, "address": channel.address(), "action": "close_dispute", "message": text });
+                        alert(&text, payload, webhook.as_deref(), desktop).await;
+                        continue;
+                    }
+                    let Some(&crossed) = threshold.iter().find(|&&threshold| remaining <= threshold) else {
+                        continue;
+                    };
+                    let key = (name.clone(), dispute.nonce);
+                    if warned.get(&key).map_or(false, |&last| last <= crossed) {
+                        continue;
+                    }
+                    warned.insert(key, crossed);
+                    let warning = if remaining == 0 {
+                        format!("The dispute of {name} is over, the challenge window has closed.")
+                    } else {
+                        format!("The dispute of {name} ends in {}, answer it before then.", format_duration(remaining))
+                    };
+                    let payload = serde_json::json!({
+                        "channel": name,
+                        "address": channel.address(),
+                        "dispute_nonce": dispute.nonce.to_string(),
+                        "dispute_ends": dispute.timeout,
+                        "remaining_seconds": remaining,
+                    });
+                    alert(&warning, payload, webhook.as_deref(), desktop).await;
+                }
+            }
+        }
+        Commands::Stream { name, rate, interval } => {
+            let mut stream = PaymentStream::start_milliwei(rate);
+            let mut ticks = tokio::time::interval(Duration::from_secs(interval));
+            ticks.tick().await;
+            loop {
+                tokio::select! {
+                    _ = ticks.tick() => {}
+                    _ = tokio::signal::ctrl_c() => break,
+                }
+                // reload every time to pick up responses imported in the meantime
+                let Some((mut channel, version)) = read(&name).await else {
+                    eprintln!("unable to load channel data");
+                    return Ok(());
+                };
+                if let Some(request) = stream.tick(&mut channel, provider.clone()).await? {
+                    write(&name, &channel, Some(version)).await?;
+                    println!("Send this to be signed by the counterparty:\n{}", request.to_json());
+                }
+            }
+            let Some((mut channel, version)) = read(&name).await else {
+                eprintln!("unable to load channel data");
+                return Ok(());
+            };
+            if let Some(request) = stream.settle(&mut channel, provider).await? {
+                write(&name, &channel, Some(version)).await?;
+                println!("Stream stopped, send this final state to be signed by the counterparty:\n{}", request.to_json());
+            } else {
+                println!("Stream stopped, nothing left to pay.");
+            }
+        }
+        Commands::Autosign { name, minimum } => {
+            let signer = AutoSigner { minimum: Wei(minimum) };
+            for line in stdin().lock().lines() {
+                let line = line?;
+                let Some((mut channel, version)) = read(&name).await else {
+                    eprintln!("unable to load channel data");
+                    return Ok(());
+                };
+                let message = match incoming(&channel, &line) {
+                    Ok(message) => message,
+                    Err(err) => {
+                        eprintln!("skipping malformed message: {err}");
+                        continue;
+                    }
+                };
+                match signer.handle(&mut channel, &message, provider.clone()).await {
+                    Ok(response) => {
+                        write(&name, &channel, Some(version)).await?;
+                        println!("{}", outgoing(&channel, seal, response)?);
+                    }
+                    Err(err) => eprintln!("not signed: {err}"),
+                }
+            }
+        }
+        Commands::Sync { command: SyncCommands::Request { name, output } } => {
+            let Some((channel, _)) = read(&name).await else {
+                eprintln!("unable to load channel data");
+                return Ok(());
+            };
+            let request = serde_json::to_string(&channel.sync_request())?;
+            let request = if seal { channel.seal(&request)? } else { request };
+            emit(output, "Send this to the counterparty", &request)?;
+        }
+        Commands::Sync { command: SyncCommands::Answer { name, request, output } } => {
+            let Some((channel, _)) = read(&name).await else {
+                eprintln!("unable to load channel data");
+                return Ok(());
+            };
+            let request = serde_json::from_str(&incoming(&channel, &read_message(request)?)?)?;
+            let answer = channel.answer_sync(&request)?;
+            eprintln!("{} messages they are missing.", answer.userops.len());
+            let answer = serde_json::to_string(&answer)?;
+            let answer = if seal { channel.seal(&answer)? } else { answer };
+            emit(output, "Send this to the counterparty", &answer)?;
+        }
+        Commands::Sync { command: SyncCommands::Apply { name, answer } } => {
+            let Some((mut channel, version)) = read(&name).await else {
+                eprintln!("unable to load channel data");
+                return Ok(());
+            };
+            let answer = serde_json::from_str(&incoming(&channel, &read_message(answer)?)?)?;
+            let count = channel.apply_sync(answer)?;
+            write(&name, &channel, Some(version)).await?;
+            println!("Added {count} messages, last nonce is now {}.", channel.last_nonce());
+        }
+        Commands::Policy { command: PolicyCommands::Get { name } } => {
+            let Some((channel, _)) = read(&name).await else {
+                eprintln!("unable to load channel data");
+                return Ok(());
+            };
+            let policy = channel.policy();
+            let unlimited = || "unlimited".to_string();
+            println!("Max transfer: {}", policy.max_transfer.map_or_else(unlimited, |wei| wei.to_string()));
+            println!("Max daily outflow: {}", policy.max_daily_outflow.map_or_else(unlimited, |wei| wei.to_string()));
+            match &policy.allowed_counterparties {
+                Some(allowed) => println!("Allowed counterparties: {allowed:?}"),
+                None => println!("Allowed counterparties: any"),
+            }
+            println!("Gas split: {:?}", channel.gas_split());
+            let validation = channel.validation_policy();
+            println!("Accepted max fee per gas: {}", validation.max_fee_per_gas.map_or_else(|| "ours only".to_string(), |wei| wei.to_string()));
+            println!("Accepted max priority fee per gas: {}", validation.max_priority_fee_per_gas.map_or_else(|| "ours only".to_string(), |wei| wei.to_string()));
+            println!("Gas limit tolerance: {}%", validation.gas_limit_tolerance);
+            println!("Reads at: {}", channel.block_policy());
+            println!("Cache TTL: {}s", channel.cache_ttl());
+        }
+        Commands::Policy { command: PolicyCommands::Set { name, max_transfer, max_daily_outflow, allow, gas_split, accept_max_fee, accept_priority_fee, gas_limit_tolerance, read_at, cache_ttl } } => {
+            let Some((mut channel, version)) = read(&name).await else {
+                eprintln!("unable to load channel data");
+                return Ok(());
+            };
+            let mut policy = Policy::default();
+            policy.max_transfer = max_transfer.map(Wei);
+            policy.max_daily_outflow = max_daily_outflow.map(Wei);
+            if !allow.is_empty() {
+                let mut allowed = vec![];
+                for address in allow {
+                    let Ok(address) = address.parse() else {
+                        eprintln!("{address} is not an address");
+                        return Ok(());
+                    };
+                    allowed.push(address);
+                }
+                policy.allowed_counterparties = Some(allowed);
+            }
+            channel.set_policy(policy);
+            channel.set_validation_policy(ValidationPolicy {
+                max_fee_per_gas: accept_max_fee.map(Into::into),
+                max_priority_fee_per_gas: accept_priority_fee.map(Into::into),
+                gas_limit_tolerance,
+            });
+            if accept_max_fee.is_some() || accept_priority_fee.is_some() || gas_limit_tolerance > 0 {
+                eprintln!("WARNING: relaxed checks let the counterparty have you sign userops costing more gas than ours.");
+            }
+            match gas_split.as_deref() {
+                None => {}
+                Some("even") => channel.set_gas_split(GasSplit::Even),
+                Some("submitter") => channel.set_gas_split(GasSplit::Submitter),
+                Some(split) => {
+                    eprintln!("unknown gas split {split}, use even or submitter");
+                    return Ok(());
+                }
+            }
+            if let Some(read_at) = read_at {
+                channel.set_block_policy(read_at);
+            }
+            if let Some(cache_ttl) = cache_ttl {
+                channel.set_cache_ttl(cache_ttl);
+            }
+            write(&name, &channel, Some(version)).await?;
+            println!("Policy updated.");
+        }
+        Commands::Contacts { command: ContactsCommands::Add { label, address, endpoint, chain_id } } => {
+            let Ok(address) = address.parse() else {
+                eprintln!("{address} is not an address");
+                return Ok(());
+            };
+            let mut contacts = read_contacts();
+            contacts.add(Contact { label, address, endpoint, chain_id: chain_id.map(Into::into) })?;
+            write_contacts(&contacts)?;
+            println!("Contact added.");
+        }
+        Commands::Contacts { command: ContactsCommands::List } => {
+            for contact in read_contacts().iter() {
+                print!("{}: {:?}", contact.label, contact.address);
+                if let Some(endpoint) = &contact.endpoint {
+                    print!(" at {endpoint}");
+                }
+                if let Some(chain_id) = contact.chain_id {
+                    print!(" on chain {chain_id}");
+                }
+                println!();
+            }
+        }
+        Commands::Contacts { command: ContactsCommands::Remove { label } } => {
+            let mut contacts = read_contacts();
+            if contacts.remove(&label).is_none() {
+                eprintln!("no contact named {label}");
+                return Ok(());
+            }
+            write_contacts(&contacts)?;
+            println!("Contact removed.");
+        }
+    }
+    Ok(())
+}
+
+fn store() -> FileStore {
+    let mut dir = dirs::home_dir().unwrap();
+    dir.push(".ch4nn337");
+    FileStore::new(dir)
+}
+
+async fn read(name: &str) -> Option<(Channel, Version)> {
+    store().load(name).await.ok()
+}
+
+/// Saves the channel unless it changed since it was read at the given version.
+async fn write(name: &str, channel: &Channel, version: Option<Version>) -> Result<(), anyhow::Error> {
+    store().save(name, channel, version).await?;
+    Ok(())
+}
+
+/// Finishes or rolls back operations interrupted by a crash, before running the command.
+async fn resume_interrupted(seal: bool, provider: Arc<Client>) -> Result<(), anyhow::Error> {
+    for (name, intent) in store().pending().await? {
+        let Some((mut channel, version)) = read(&name).await else {
+            // the channel is gone, and with it what the intent was about
+            store().complete(&name).await?;
+            continue;
+        };
+        match channel.resume(intent, provider.clone()).await? {
+            Recovery::Completed => {}
+            Recovery::RolledBack => eprintln!("Signing a withdrawal on {name} was interrupted and rolled back, receive it again to sign it."),
+            Recovery::Resumed(response) => {
+                write(&name, &channel, Some(version)).await?;
+                let response = outgoing(&channel, seal, response)?;
+                eprintln!("Finished an interrupted withdrawal on {name}, please send this response back:\n{response}");
+            }
+        }
+        store().complete(&name).await?;
+    }
+    Ok(())
+}
+
+fn price_source(cli: &Cli, provider: Arc<Client>) -> Result<Option<Box<dyn PriceSource>>, anyhow::Error> {
+    if let Some(feed) = &cli.price_feed {
+        let feed = feed.parse().map_err(|_| anyhow!("price feed is not an address"))?;
+        return Ok(Some(Box::new(ChainlinkFeed::new(feed, cli.currency.to_ascii_uppercase(), provider))));
+    }
+    Ok(match (&cli.price_url, &cli.price_pointer) {
+        (Some(url), Some(pointer)) => Some(Box::new(HttpSource::new(url, pointer, cli.currency.to_ascii_uppercase()))),
+        _ => None,
+    })
+}
+
+/// The given factory, or the one deployed with deploy-factory for the chain of the provider.
+async fn resolve_factory(factory: Option<String>, provider: &Client) -> Result<Option<Address>, anyhow::Error> {
+    if let Some(factory) = factory {
+        let factory = factory.parse().ok();
+        if factory.is_none() {
+            eprintln!("factory is not an address");
+        }
+        return Ok(factory);
+    }
+    let chain_id = provider.get_chainid().await?;
+    let factory = read_factories().get(&chain_id.to_string()).copied();
+    if factory.is_none() {
+        eprintln!("no factory known for chain {chain_id}, pass --factory");
+    }
+    Ok(factory)
+}
+
+/// Parses a party given as address or as hex encoded SEC1 public key.
+fn parse_party(party: &str) -> Option<Address> {
+    if let Ok(address) = party.parse() {
+        return Some(address);
     }
+    let key = hex::decode(party.trim_start_matches("0x")).ok()?;
+    let key = VerifyingKey::from_sec1_bytes(&key).ok()?;
+    Some(public_key_to_address(&key))
+}
+
+fn format_duration(seconds: u64) -> String {
+    format!("{}h {:02}m", seconds / 3600, seconds / 60 % 60)
+}
+
+/// Parses an amount with unit into wei.
+fn parse_wei(amount: &str) -> Result<u128, String> {
+    parse_amount(amount).ok_or_else(|| format!("{amount} is not an amount like 1.5eth, 20gwei or 1000wei"))
+}
+
+fn parse_positive_wei(amount: &str) -> Result<NonZeroU128, String> {
+    NonZeroU128::new(parse_wei(amount)?).ok_or_else(|| "the amount has to be positive".to_string())
+}
+
+/// Parses a positive decimal wei amount with up to three decimals into milliwei.
+fn parse_milliwei(amount: &str) -> Result<u128, String> {
+    let (whole, fraction) = amount.split_once('.').unwrap_or((amount, ""));
+    if fraction.len() > 3 || !fraction.chars().all(|c| c.is_ascii_digit()) {
+        return Err("at most three decimals are supported".to_string());
+    }
+    let whole: u128 = if whole.is_empty() { 0 } else { whole.parse().map_err(|err| format!("{err}"))? };
+    let fraction: u128 = format!("{fraction:0<3}").parse().map_err(|err| format!("{err}"))?;
+    match whole.checked_mul(MILLIWEI_PER_WEI).and_then(|milliwei| milliwei.checked_add(fraction)) {
+        Some(0) => Err("the amount has to be positive".to_string()),
+        Some(milliwei) => Ok(milliwei),
+        None => Err("amount too large".to_string()),
+    }
+}
+
+fn read_contacts() -> ContactBook {
+    let mut file = dirs::home_dir().unwrap();
+    file.push(".ch4nn337");
+    file.push("contacts.json");
+    File::open(file).ok().and_then(|file| serde_json::from_reader(file).ok()).unwrap_or_default()
+}
+
+fn write_contacts(contacts: &ContactBook) -> Result<(), anyhow::Error> {
+    let mut file = dirs::home_dir().unwrap();
+    file.push(".ch4nn337");
+    file.push("contacts.json");
+    serde_json::to_writer(File::create(file)?, contacts)?;
     Ok(())
 }
 
-fn read(name: &str) -> Option<Channel> {
+/// Factories deployed with deploy-factory, by chain id.
+fn read_factories() -> BTreeMap<String, Address> {
     let mut file = dirs::home_dir().unwrap();
     file.push(".ch4nn337");
-    file.push(format!("{name}.json"));
-    serde_json::from_reader(File::open(file).ok()?).ok()?
+    file.push("factories.json");
+    File::open(file).ok().and_then(|file| serde_json::from_reader(file).ok()).unwrap_or_default()
 }
 
-fn write(name: &str, channel: &Channel) {
+fn write_factories(factories: &BTreeMap<String, Address>) -> Result<(), anyhow::Error> {
     let mut file = dirs::home_dir().unwrap();
     file.push(".ch4nn337");
-    file.push(format!("{name}.json"));
-    serde_json::to_writer(File::create(file).unwrap(), channel).unwrap();
+    file.push("factories.json");
+    serde_json::to_writer(File::create(file)?, factories)?;
+    Ok(())
+}
+
+/// Prints an alert of the monitor and forwards it to the webhook and the desktop.
+async fn alert(text: &str, payload: serde_json::Value, webhook: Option<&str>, desktop: bool) {
+    println!("{text}");
+    if let Some(url) = webhook {
+        let result = reqwest::Client::new().post(url).json(&payload).send().await
+            .and_then(|response| response.error_for_status());
+        if let Err(err) = result {
+            eprintln!("webhook failed: {err}");
+        }
+    }
+    if desktop {
+        if let Err(err) = Command::new("notify-send").arg("ch4nn337").arg(text).status() {
+            eprintln!("desktop notification failed: {err}");
+        }
+    }
 }
 
 fn read_line() -> String {
@@ -199,4 +1974,95 @@ fn read_line() -> String {
     stdin().lock().read_line(&mut line).unwrap();
     line.truncate(line.len() - 1);
     line
-}
\ No newline at end of file
+}
+
+fn read_message(message: Option<String>) -> Result<String, anyhow::Error> {
+    match message.as_deref() {
+        None => {
+            eprintln!("Please paste message:");
+            Ok(read_line())
+        }
+        Some("-") => Ok(read_line()),
+        Some(json) if json.trim_start().starts_with('{') => Ok(json.to_string()),
+        // a file wins over a sealed message, its name may well be all hex digits
+        Some(path) if Path::new(path).is_file() => Ok(fs::read_to_string(path)?),
+        Some(sealed) if !sealed.is_empty() && sealed.chars().all(|c| c.is_ascii_hexdigit()) => Ok(sealed.to_string()),
+        Some(path) => Ok(fs::read_to_string(path)?),
+    }
+}
+
+/// Prints a request for the counterparty, or on watch-only channels what to sign offline first.
+fn hand_out(channel: &Channel, seal: bool, qr: bool, request: OutgoingMessage) -> Result<(), anyhow::Error> {
+    if let Some(request) = channel.signing_request() {
+        println!("Sign this on the offline machine:\n{}", serde_json::to_string(&request)?);
+        return Ok(());
+    }
+    let request = outgoing(channel, seal, request)?;
+    println!("Send this to be signed by the counterparty:\n{request}");
+    if qr {
+        print_qr(&request)?;
+    }
+    Ok(())
+}
+
+/// Prints what a dry run would do.
+fn print_preview(preview: &Preview) -> Result<(), anyhow::Error> {
+    println!("Userop (unsigned):\n{}", serde_json::to_string_pretty(&preview.userop)?);
+    println!("Call: {}", preview.call);
+    println!("Gas: call {}, verification {}, pre-verification {}, max fee {}, priority fee {}", preview.userop.call_gas_limit, preview.userop.verification_gas_limit, preview.userop.pre_verificaiton_gas, preview.userop.max_fee_per_gas, preview.userop.max_priority_fee_per_gas);
+    match preview.summary {
+        Summary::Transfer { our_balance, their_balance, .. } => println!("Resulting balances: us {our_balance}, them {their_balance}"),
+        Summary::Withdrawal { withdraw_us, withdraw_them, payout } => {
+            println!("Withdraw {withdraw_us} to us and {withdraw_them} to them.");
+            match payout {
+                Some((payout_us, payout_them)) => println!("After fees from the deposit: {payout_us} to us and {payout_them} to them."),
+                None => println!("WARNING: the deposit does not cover the fees, the withdrawal will fail."),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Seals a message to the counterparty if requested.
+fn outgoing(channel: &Channel, seal: bool, message: OutgoingMessage) -> Result<String, anyhow::Error> {
+    let message = message.to_json();
+    Ok(if seal { channel.seal(&message)? } else { message })
+}
+
+/// Unseals a message unless it is plain JSON.
+fn incoming(channel: &Channel, message: &str) -> Result<String, anyhow::Error> {
+    if message.trim_start().starts_with('{') {
+        Ok(message.to_string())
+    } else {
+        Ok(channel.unseal(message)?)
+    }
+}
+
+fn emit(output: Option<PathBuf>, what: &str, payload: &str) -> Result<(), anyhow::Error> {
+    match output {
+        Some(path) => {
+            fs::write(&path, payload)?;
+            eprintln!("{what}, written to {}", path.display());
+        }
+        None => {
+            eprintln!("{what}:");
+            println!("{payload}");
+        }
+    }
+    Ok(())
+}
+
+fn print_qr(payload: &str) -> Result<(), anyhow::Error> {
+    let code = QrCode::new(qr::to_qr_bytes(payload))?;
+    println!("{}", code.render::<unicode::Dense1x2>().build());
+    Ok(())
+}
+
+fn scan_qr(path: &Path) -> Result<String, anyhow::Error> {
+    let mut image = rqrr::PreparedImage::prepare(image::open(path)?.to_luma8());
+    let grid = image.detect_grids().into_iter().next()
+        .ok_or_else(|| anyhow!("no QR code found"))?;
+    let mut bytes = Vec::new();
+    grid.decode_to(&mut bytes)?;
+    Ok(qr::from_qr_bytes(&bytes)?)
+}
@@ -0,0 +1,66 @@
+use ch4nn337_lib::mock::MockClient;
+use ch4nn337_lib::proto;
+use ch4nn337_lib::Channel;
+use criterion::{criterion_group, criterion_main, Criterion};
+use ethers::types::Address;
+use std::num::NonZeroU128;
+use std::sync::Arc;
+use tokio::runtime::Runtime;
+
+const FUNDING: u128 = 1_000_000_000_000_000_000;
+
+fn runtime() -> Runtime {
+    tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap()
+}
+
+fn messages(c: &mut Criterion) {
+    let runtime = runtime();
+    let (provider, mock) = MockClient::mocked();
+    let provider = Arc::new(provider);
+    let (mut a, b) = runtime
+        .block_on(Channel::open(
+            5.into(),
+            Address::random(),
+            Address::random(),
+            provider.clone(),
+        ))
+        .unwrap();
+    mock.set_balance(a.address(), FUNDING.into());
+    let wei = NonZeroU128::new(400).unwrap();
+
+    c.bench_function("request transfer", |bench| {
+        bench.iter(|| {
+            runtime
+                .block_on(a.request_transfer(wei, provider.clone()))
+                .unwrap();
+            a.cancel_pending_message();
+        })
+    });
+
+    let request = runtime
+        .block_on(a.request_transfer(wei, provider.clone()))
+        .unwrap()
+        .to_json();
+    c.bench_function("receive transfer", |bench| {
+        bench.iter(|| {
+            runtime
+                .block_on(b.receive_message(&request, provider.clone()))
+                .unwrap()
+        })
+    });
+
+    let userop = runtime
+        .block_on(b.receive_message(&request, provider.clone()))
+        .unwrap()
+        .0
+        .userop()
+        .clone();
+    c.bench_function("user op hash", |bench| {
+        bench.iter(|| proto::user_op_hash(&userop, Address::zero(), 5.into()))
+    });
+}
+
+criterion_group!(benches, messages);
+criterion_main!(benches);
@@ -0,0 +1,12 @@
+//! Decoding of untrusted input: messages of the counterparty and stored messages.
+#![no_main]
+use ch4nn337_lib::protocol::decode_message;
+use ch4nn337_lib::Message;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(message) = std::str::from_utf8(data) {
+        let _ = decode_message(message);
+    }
+    let _ = serde_json::from_slice::<Message>(data);
+});
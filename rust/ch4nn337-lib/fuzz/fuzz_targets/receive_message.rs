@@ -0,0 +1,144 @@
+//! Validation of messages of the counterparty against a channel on the mock chain.
+#![no_main]
+use arbitrary::Arbitrary;
+use ch4nn337_lib::mock::{MockClient, MockMiddleware};
+use ch4nn337_lib::protocol::decode_message;
+use ch4nn337_lib::Channel;
+use ethers::types::{Address, Bytes};
+use libfuzzer_sys::fuzz_target;
+use std::num::NonZeroU128;
+use std::sync::Arc;
+use tokio::runtime::Runtime;
+
+const FUNDING: u128 = 1_000_000_000_000_000_000;
+
+#[derive(Arbitrary, Debug)]
+enum Input {
+    /// arbitrary text, exercising the parsing
+    Raw(String),
+    /// a genuine request of the counterparty with some fields replaced, exercising the
+    /// validation behind the parsing
+    Mutated {
+        withdrawal: bool,
+        mutations: Vec<Mutation>,
+    },
+}
+
+#[derive(Arbitrary, Debug)]
+enum Mutation {
+    Sender([u8; 20]),
+    Nonce(u64),
+    InitCode(Vec<u8>),
+    CallData(Vec<u8>),
+    CallGasLimit(u64),
+    VerificationGasLimit(u64),
+    PreVerificationGas(u64),
+    MaxFeePerGas(u64),
+    MaxPriorityFeePerGas(u64),
+    PaymasterAndData(Vec<u8>),
+    Signature(Vec<u8>),
+}
+
+struct Setup {
+    runtime: Runtime,
+    provider: Arc<MockMiddleware>,
+    receiver: Channel,
+    /// genuine requests of the counterparty, issued once as issuing records them
+    transfer: String,
+    withdrawal: String,
+}
+
+thread_local! {
+    static SETUP: Setup = setup();
+}
+
+fn setup() -> Setup {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .build()
+        .unwrap();
+    let (provider, mock) = MockClient::mocked();
+    let provider = Arc::new(provider);
+    let (mut sender, receiver) = runtime
+        .block_on(Channel::open(
+            5.into(),
+            Address::random(),
+            Address::random(),
+            provider.clone(),
+        ))
+        .unwrap();
+    mock.set_balance(sender.address(), FUNDING.into());
+    let (transfer, withdrawal) = runtime.block_on(async {
+        let half = NonZeroU128::new(FUNDING / 2).unwrap();
+        let transfer = sender
+            .request_transfer(half, provider.clone())
+            .await
+            .unwrap()
+            .to_json();
+        sender.cancel_pending_message();
+        let withdrawal = sender
+            .request_full_withdraw(provider.clone())
+            .await
+            .unwrap()
+            .to_json();
+        (transfer, withdrawal)
+    });
+    Setup {
+        runtime,
+        provider,
+        receiver,
+        transfer,
+        withdrawal,
+    }
+}
+
+fuzz_target!(|input: Input| {
+    SETUP.with(|setup| {
+        let Setup {
+            runtime,
+            provider,
+            receiver,
+            transfer,
+            withdrawal,
+        } = setup;
+        runtime.block_on(async {
+            let message = match input {
+                Input::Raw(message) => message,
+                Input::Mutated {
+                    withdrawal: withdraw,
+                    mutations,
+                } => {
+                    let request = if withdraw { withdrawal } else { transfer };
+                    let mut userop = decode_message(request).unwrap();
+                    for mutation in mutations {
+                        match mutation {
+                            Mutation::Sender(sender) => userop.sender = sender.into(),
+                            Mutation::Nonce(nonce) => userop.nonce = nonce.into(),
+                            Mutation::InitCode(code) => userop.init_code = Bytes::from(code),
+                            Mutation::CallData(data) => userop.call_data = Bytes::from(data),
+                            Mutation::CallGasLimit(gas) => userop.call_gas_limit = gas.into(),
+                            Mutation::VerificationGasLimit(gas) => {
+                                userop.verification_gas_limit = gas.into()
+                            }
+                            Mutation::PreVerificationGas(gas) => {
+                                userop.pre_verificaiton_gas = gas.into()
+                            }
+                            Mutation::MaxFeePerGas(fee) => userop.max_fee_per_gas = fee.into(),
+                            Mutation::MaxPriorityFeePerGas(fee) => {
+                                userop.max_priority_fee_per_gas = fee.into()
+                            }
+                            Mutation::PaymasterAndData(data) => {
+                                userop.paymaster_and_data = Bytes::from(data)
+                            }
+                            Mutation::Signature(signature) => {
+                                userop.signature = Bytes::from(signature)
+                            }
+                        }
+                    }
+                    serde_json::to_string(&userop).unwrap()
+                }
+            };
+            let _ = receiver.receive_message(&message, provider.clone()).await;
+        });
+    });
+});
@@ -0,0 +1,82 @@
+//! Liquidity advertisements: an offer to open a channel funded with some wei for a fee, signed
+//! with the channel key of the advertiser, so strangers can find liquidity. The advertisement
+//! carries a [`Proposal`], which the taker accepts like any other. As a proposal can only be
+//! confirmed once, so can the advertisement.
+use crate::ceremony::{address_of, Proposal};
+use crate::{now, Channel, ChannelState, Error};
+use ch4nn337_sys::signature::verify_signer;
+use ethers::signers::Signer;
+use ethers::types::{Signature, H256};
+use ethers::utils::keccak256;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Advertisement {
+    pub proposal: Proposal,
+    /// wei the advertiser funds the channel with
+    pub funding: u128,
+    /// wei the taker pays for the funding
+    pub fee: u128,
+    /// unix time after which the offer no longer holds
+    pub expires: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SignedAdvertisement {
+    pub advertisement: Advertisement,
+    /// by the key of party A in the proposal
+    pub signature: Signature,
+}
+
+impl Advertisement {
+    fn hash(&self) -> [u8; 32] {
+        keccak256(serde_json::to_vec(self).expect("advertisements serialize"))
+    }
+}
+
+impl SignedAdvertisement {
+    /// Checks the signature and expiry, returning the advertisement.
+    pub fn verify(&self) -> Result<&Advertisement, Error> {
+        let advertiser = address_of(&self.advertisement.proposal.key_a)?;
+        let hash = H256(self.advertisement.hash());
+        verify_signer(&self.signature.to_vec(), hash, advertiser)?;
+        if self.advertisement.expires < now() {
+            return Err(Error::IllegalProposal);
+        }
+        Ok(&self.advertisement)
+    }
+}
+
+impl Channel {
+    /// Advertises a channel proposed with [`Channel::propose`].
+    pub async fn advertise(
+        &self,
+        proposal: Proposal,
+        funding: u128,
+        fee: u128,
+        expires: u64,
+    ) -> Result<SignedAdvertisement, Error> {
+        self.check_signer(false)?;
+        self.check_state(&[ChannelState::Proposed])?;
+        let Some(wallet) = self.wallet() else {
+            return Err(Error::WatchOnly);
+        };
+        if address_of(&proposal.key_a)? != self.our_address()? {
+            return Err(Error::IllegalProposal);
+        }
+        let advertisement = Advertisement {
+            proposal,
+            funding,
+            fee,
+            expires,
+        };
+        let signature = wallet
+            .sign_message(&advertisement.hash()[..])
+            .await
+            .unwrap();
+        Ok(SignedAdvertisement {
+            advertisement,
+            signature,
+        })
+    }
+}
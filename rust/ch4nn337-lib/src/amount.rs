@@ -0,0 +1,303 @@
+//! Amounts of ether with units, like `1.5eth`, `20gwei` or `1000wei`. Parsing is exact: an amount
+//! with more decimals than the unit allows is rejected instead of rounded.
+//!
+//! [`Wei`] and [`SignedWei`] carry amounts through the API, so balances and value transfers are
+//! not mixed up with other integers and every conversion between them is checked.
+use ethers::types::U256;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+const UNITS: [(&str, u32); 4] = [("ether", 18), ("eth", 18), ("gwei", 9), ("wei", 0)];
+
+/// Parses an amount into wei. Amounts without unit are wei.
+pub fn parse_amount(amount: &str) -> Option<u128> {
+    let amount = amount.trim().to_ascii_lowercase();
+    let (number, decimals) = UNITS
+        .iter()
+        .find_map(|(unit, decimals)| Some((amount.strip_suffix(unit)?, *decimals)))
+        .unwrap_or((amount.as_str(), 0));
+    let number = number.trim_end();
+    let (whole, fraction) = number.split_once('.').unwrap_or((number, ""));
+    if whole.is_empty() && fraction.is_empty()
+        || !whole
+            .chars()
+            .chain(fraction.chars())
+            .all(|c| c.is_ascii_digit())
+    {
+        return None;
+    }
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.len() > decimals as usize {
+        return None;
+    }
+    let whole: u128 = match whole {
+        "" => 0,
+        whole => whole.parse().ok()?,
+    };
+    let fraction: u128 = match fraction {
+        "" => 0,
+        fraction => fraction.parse::<u128>().ok()? * 10u128.pow(decimals - fraction.len() as u32),
+    };
+    whole
+        .checked_mul(10u128.pow(decimals))?
+        .checked_add(fraction)
+}
+
+/// Formats wei in ETH with all significant decimals, e.g. `1.5 ETH`.
+pub fn format_amount(wei: u128) -> String {
+    let scale = 10u128.pow(18);
+    let (whole, fraction) = (wei / scale, wei % scale);
+    if fraction == 0 {
+        return format!("{whole} ETH");
+    }
+    let fraction = format!("{fraction:018}");
+    format!("{whole}.{} ETH", fraction.trim_end_matches('0'))
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum AmountError {
+    #[error("amount out of range")]
+    Overflow,
+    #[error("invalid amount")]
+    Invalid,
+}
+
+/// An amount of wei, e.g. a balance.
+#[derive(
+    Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+#[serde(transparent)]
+pub struct Wei(pub u128);
+
+/// A signed amount of wei, e.g. a value transfer or a change of balance.
+#[derive(
+    Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+#[serde(transparent)]
+pub struct SignedWei(pub i128);
+
+impl Wei {
+    pub const ZERO: Wei = Wei(0);
+
+    pub fn is_zero(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn checked_add(self, other: Wei) -> Option<Wei> {
+        self.0.checked_add(other.0).map(Wei)
+    }
+
+    pub fn checked_sub(self, other: Wei) -> Option<Wei> {
+        self.0.checked_sub(other.0).map(Wei)
+    }
+
+    pub fn saturating_add(self, other: Wei) -> Wei {
+        Wei(self.0.saturating_add(other.0))
+    }
+
+    pub fn saturating_sub(self, other: Wei) -> Wei {
+        Wei(self.0.saturating_sub(other.0))
+    }
+
+    pub fn checked_add_signed(self, delta: SignedWei) -> Option<Wei> {
+        self.0.checked_add_signed(delta.0).map(Wei)
+    }
+
+    /// Adds the delta, stopping at zero and at the maximum.
+    pub fn saturating_add_signed(self, delta: SignedWei) -> Wei {
+        Wei(self.0.saturating_add_signed(delta.0))
+    }
+}
+
+impl SignedWei {
+    pub const ZERO: SignedWei = SignedWei(0);
+
+    pub fn is_negative(self) -> bool {
+        self.0 < 0
+    }
+
+    pub fn checked_add(self, other: SignedWei) -> Option<SignedWei> {
+        self.0.checked_add(other.0).map(SignedWei)
+    }
+
+    pub fn checked_sub(self, other: SignedWei) -> Option<SignedWei> {
+        self.0.checked_sub(other.0).map(SignedWei)
+    }
+
+    pub fn checked_neg(self) -> Option<SignedWei> {
+        self.0.checked_neg().map(SignedWei)
+    }
+
+    pub fn saturating_neg(self) -> SignedWei {
+        SignedWei(self.0.saturating_neg())
+    }
+
+    pub fn unsigned_abs(self) -> Wei {
+        Wei(self.0.unsigned_abs())
+    }
+}
+
+impl From<u128> for Wei {
+    fn from(wei: u128) -> Self {
+        Wei(wei)
+    }
+}
+
+impl From<Wei> for u128 {
+    fn from(wei: Wei) -> Self {
+        wei.0
+    }
+}
+
+impl From<Wei> for U256 {
+    fn from(wei: Wei) -> Self {
+        U256::from(wei.0)
+    }
+}
+
+impl TryFrom<U256> for Wei {
+    type Error = AmountError;
+
+    fn try_from(wei: U256) -> Result<Self, Self::Error> {
+        u128::try_from(wei)
+            .map(Wei)
+            .map_err(|_| AmountError::Overflow)
+    }
+}
+
+impl From<i128> for SignedWei {
+    fn from(wei: i128) -> Self {
+        SignedWei(wei)
+    }
+}
+
+impl From<SignedWei> for i128 {
+    fn from(wei: SignedWei) -> Self {
+        wei.0
+    }
+}
+
+impl TryFrom<Wei> for SignedWei {
+    type Error = AmountError;
+
+    fn try_from(wei: Wei) -> Result<Self, Self::Error> {
+        i128::try_from(wei.0)
+            .map(SignedWei)
+            .map_err(|_| AmountError::Overflow)
+    }
+}
+
+impl TryFrom<SignedWei> for Wei {
+    type Error = AmountError;
+
+    fn try_from(wei: SignedWei) -> Result<Self, Self::Error> {
+        u128::try_from(wei.0)
+            .map(Wei)
+            .map_err(|_| AmountError::Overflow)
+    }
+}
+
+impl fmt::Display for Wei {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&format_amount(self.0))
+    }
+}
+
+impl fmt::Display for SignedWei {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_negative() {
+            f.write_str("-")?;
+        }
+        write!(f, "{}", self.unsigned_abs())
+    }
+}
+
+impl FromStr for Wei {
+    type Err = AmountError;
+
+    /// Parses an amount with unit, like [`parse_amount`].
+    fn from_str(amount: &str) -> Result<Self, Self::Err> {
+        parse_amount(amount).map(Wei).ok_or(AmountError::Invalid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ETH: u128 = 1_000_000_000_000_000_000;
+
+    #[test]
+    fn units_are_matched_longest_first() {
+        assert_eq!(parse_amount("1ether"), Some(ETH));
+        assert_eq!(parse_amount("1eth"), Some(ETH));
+        assert_eq!(parse_amount("1gwei"), Some(1_000_000_000));
+        assert_eq!(parse_amount("1wei"), Some(1));
+        assert_eq!(parse_amount("1"), Some(1));
+        assert_eq!(parse_amount(" 1.5 ETH "), Some(3 * ETH / 2));
+        // not a unit, and not a number either
+        assert_eq!(parse_amount("1er"), None);
+    }
+
+    #[test]
+    fn decimals_beyond_the_unit_are_rejected() {
+        assert_eq!(parse_amount("0.000000000000000001eth"), Some(1));
+        assert_eq!(parse_amount("0.0000000000000000001eth"), None);
+        // trailing zeros are not significant
+        assert_eq!(parse_amount("1.5000000000000000000eth"), Some(3 * ETH / 2));
+        assert_eq!(parse_amount("1.5gwei"), Some(1_500_000_000));
+        assert_eq!(parse_amount("1.5"), None);
+        assert_eq!(parse_amount("1.0wei"), Some(1));
+    }
+
+    #[test]
+    fn bare_decimal_points() {
+        assert_eq!(parse_amount("."), None);
+        assert_eq!(parse_amount(".eth"), None);
+        assert_eq!(parse_amount("1."), Some(1));
+        assert_eq!(parse_amount("1.eth"), Some(ETH));
+        assert_eq!(parse_amount(".5eth"), Some(ETH / 2));
+        assert_eq!(parse_amount(""), None);
+        assert_eq!(parse_amount("eth"), None);
+        assert_eq!(parse_amount("1.2.3eth"), None);
+        assert_eq!(parse_amount("-1eth"), None);
+    }
+
+    #[test]
+    fn overflow_is_rejected() {
+        assert_eq!(parse_amount(&u128::MAX.to_string()), Some(u128::MAX));
+        assert_eq!(
+            parse_amount("340282366920938463463374607431768211456"),
+            None
+        );
+        assert_eq!(parse_amount("340282366920938463464eth"), None);
+    }
+
+    #[test]
+    fn formats_significant_decimals() {
+        assert_eq!(format_amount(0), "0 ETH");
+        assert_eq!(format_amount(ETH), "1 ETH");
+        assert_eq!(format_amount(3 * ETH / 2), "1.5 ETH");
+        assert_eq!(format_amount(1), "0.000000000000000001 ETH");
+        assert_eq!(SignedWei(-(ETH as i128) / 2).to_string(), "-0.5 ETH");
+    }
+
+    #[test]
+    fn formatted_amounts_parse_back() {
+        for wei in [
+            0,
+            1,
+            999,
+            ETH - 1,
+            ETH,
+            3 * ETH / 2,
+            123_456_789 * ETH + 42,
+            u128::MAX,
+        ] {
+            assert_eq!(parse_amount(&format_amount(wei)), Some(wei), "{wei}");
+            assert_eq!(Wei(wei).to_string().parse::<Wei>(), Ok(Wei(wei)));
+        }
+    }
+}
@@ -0,0 +1,115 @@
+//! Re-verification of the stored history against the protocol rules. Every message was checked
+//! when it was stored, so an inconsistency means the state file was corrupted or tampered with,
+//! and signing anything on top of it could lose funds.
+use crate::amount::{SignedWei, Wei};
+use crate::proto::{self, Call};
+use crate::{Channel, Message, Party};
+use thiserror::Error;
+
+/// The first inconsistency found, with the index of the message in [`Channel::messages`].
+#[derive(Error, Debug)]
+pub enum AuditError {
+    #[error("message {0} is not for this channel")]
+    Sender(usize),
+    #[error("message {0} is not signed by both parties")]
+    Signature(usize),
+    #[error("message {0} does not increase the nonce")]
+    Nonce(usize),
+    #[error("message {0} has calldata not matching its kind")]
+    Calldata(usize),
+    #[error("message {0} does not match its calldata")]
+    ValueTransfer(usize),
+    #[error("payments of message {0} do not add up to its transfer")]
+    Items(usize),
+    #[error("pending message does not follow the history")]
+    Pending,
+}
+
+impl Channel {
+    /// Checks every stored message: that it is for this channel and signed by both parties, that
+    /// nonces strictly increase, and that the value transfers and withdrawals are what the
+    /// calldata says, with the payments of a transfer adding up to the change from the one
+    /// before. Only reads local state, so it is cheap enough to run before signing anything on a
+    /// channel loaded from disk.
+    pub fn audit(&self) -> Result<(), AuditError> {
+        let mut nonce = self.recovered_nonce;
+        let mut value_transfer = 0;
+        for (index, message) in self.messages.iter().enumerate() {
+            let userop = message.userop();
+            if userop.sender != self.address {
+                return Err(AuditError::Sender(index));
+            }
+            if !self.countersigned(userop) {
+                return Err(AuditError::Signature(index));
+            }
+            if nonce.is_some_and(|nonce| userop.nonce <= nonce) {
+                return Err(AuditError::Nonce(index));
+            }
+            nonce = Some(userop.nonce);
+
+            let call = proto::decode_call(&userop.call_data).ok_or(AuditError::Calldata(index))?;
+            match (message, call) {
+                (
+                    Message::Transfer(message),
+                    Call::Transfer {
+                        value_transfer: called,
+                    },
+                ) => {
+                    if message.value_transfer != called {
+                        return Err(AuditError::ValueTransfer(index));
+                    }
+                    if !message.items.is_empty() {
+                        let total = message
+                            .items
+                            .iter()
+                            .try_fold(Wei::ZERO, |total, item| total.checked_add(item.amount));
+                        let delta = called
+                            .checked_sub(value_transfer)
+                            .map(|delta| SignedWei(delta).unsigned_abs());
+                        if total.is_none() || total != delta {
+                            return Err(AuditError::Items(index));
+                        }
+                    }
+                    value_transfer = called;
+                }
+                (
+                    Message::Withdrawal(message),
+                    Call::Withdrawal {
+                        value_transfer: called,
+                        withdraw_a,
+                        withdraw_b,
+                    },
+                ) => {
+                    let (withdraw_us, withdraw_them) = match self.us {
+                        Party::A => (Wei(withdraw_a), Wei(withdraw_b)),
+                        Party::B => (Wei(withdraw_b), Wei(withdraw_a)),
+                    };
+                    if called != value_transfer
+                        || message.withdraw_us != withdraw_us
+                        || message.withdraw_them != withdraw_them
+                    {
+                        return Err(AuditError::ValueTransfer(index));
+                    }
+                    // a failed withdrawal reopens the channel, starting from zero again
+                    value_transfer = 0;
+                }
+                _ => return Err(AuditError::Calldata(index)),
+            }
+        }
+
+        if let Some(pending) = &self.pending_message {
+            let userop = pending.userop();
+            // only a fee bump reuses the nonce of the withdrawal it replaces
+            let bump = matches!(
+                (pending, self.messages.last()),
+                (Message::Withdrawal(_), Some(Message::Withdrawal(_)))
+            );
+            let stale =
+                nonce.is_some_and(|nonce| userop.nonce < nonce || (userop.nonce == nonce && !bump));
+            if userop.sender != self.address || stale {
+                return Err(AuditError::Pending);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,52 @@
+//! Unattended countersigning, e.g. for a merchant accepting payments: only transfers that
+//! strictly increase our balance are signed, everything else is rejected.
+use crate::amount::Wei;
+use crate::chain::ChainClient;
+use crate::protocol::OutgoingMessage;
+use crate::{Channel, Error, Summary};
+use std::sync::Arc;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Rejection {
+    #[error("not a transfer to us")]
+    NotIncoming,
+    #[error("transfer of {amount} is below the minimum of {minimum}")]
+    BelowMinimum { amount: Wei, minimum: Wei },
+}
+
+#[derive(Default, Clone)]
+pub struct AutoSigner {
+    /// smallest incoming transfer worth signing
+    pub minimum: Wei,
+}
+
+impl AutoSigner {
+    /// Validates an incoming message and signs it if it pays us, returning the response for the
+    /// counterparty.
+    pub async fn handle<C: ChainClient + ?Sized>(
+        &self,
+        channel: &mut Channel,
+        message: &str,
+        client: Arc<C>,
+    ) -> Result<OutgoingMessage, Error> {
+        let (message, summary) = channel.receive_message(message, client.clone()).await?;
+        match summary {
+            Summary::Transfer {
+                amount,
+                incoming: true,
+                ..
+            } => {
+                if amount < self.minimum {
+                    return Err(Rejection::BelowMinimum {
+                        amount,
+                        minimum: self.minimum,
+                    }
+                    .into());
+                }
+            }
+            _ => return Err(Rejection::NotIncoming.into()),
+        }
+        channel.sign_message(message, client).await
+    }
+}
@@ -0,0 +1,19 @@
+//! Encrypted backups of the key of a channel, in the keystore format of Ethereum wallets, so they
+//! can also be opened with other tools.
+use crate::{Channel, Error};
+use ethers::signers::{LocalWallet, WalletError};
+use rand::rngs::OsRng;
+use std::path::{Path, PathBuf};
+
+impl Channel {
+    /// Writes our key, encrypted with the password, to `<dir>/<name>.key.json`.
+    pub fn backup_key(&self, dir: &Path, name: &str, password: &str) -> Result<PathBuf, Error> {
+        if self.is_watch_only() {
+            return Err(Error::WatchOnly);
+        }
+        let file = format!("{name}.key.json");
+        std::fs::create_dir_all(dir).map_err(WalletError::from)?;
+        LocalWallet::encrypt_keystore(dir, &mut OsRng, self.key.as_bytes(), password, Some(&file))?;
+        Ok(dir.join(file))
+    }
+}
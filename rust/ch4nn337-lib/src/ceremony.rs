@@ -0,0 +1,136 @@
+//! Opening a channel between two machines, each generating and keeping only its own key.
+//!
+//! Party A [`Channel::propose`]s the channel and sends the [`Proposal`] to B, who
+//! [`Channel::accept`]s it and answers with an [`Acceptance`]. A then [`Channel::confirm`]s the
+//! channel with it. Until confirmed, the channel of A is [`ChannelState::Proposed`], as its
+//! counterparty and address are not known yet. Both also agree on the message protocol version,
+//! the newest one both support.
+use crate::entropy::{CryptoRng, OsRng, RngCore};
+use crate::protocol::{negotiate, PROTOCOL_VERSION};
+use crate::{Channel, ChannelState, Error, Party};
+use ch4nn337_sys::channel_address::channel_address;
+use ethers::core::k256::ecdsa::{SigningKey, VerifyingKey};
+use ethers::types::{Address, U256};
+use ethers::utils::public_key_to_address;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Proposal {
+    pub chain_id: U256,
+    pub entry_point: Address,
+    pub factory: Address,
+    pub salt: U256,
+    /// SEC1 encoded public key of party A
+    pub key_a: Vec<u8>,
+    /// newest message protocol version A supports, see [`protocol`](crate::protocol)
+    #[serde(default)]
+    pub protocol: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Acceptance {
+    /// the channel address as computed by B
+    pub channel: Address,
+    /// SEC1 encoded public key of party B
+    pub key_b: Vec<u8>,
+    /// message protocol version B chose, the newest one both support
+    #[serde(default)]
+    pub protocol: u32,
+}
+
+pub(crate) fn address_of(key: &[u8]) -> Result<Address, Error> {
+    let key = VerifyingKey::from_sec1_bytes(key).map_err(|_| Error::IllegalProposal)?;
+    Ok(public_key_to_address(&key))
+}
+
+impl Channel {
+    /// Starts opening a channel as party A.
+    pub fn propose(chain_id: U256, entry_point: Address, factory: Address) -> (Channel, Proposal) {
+        Channel::propose_with_rng(chain_id, entry_point, factory, &mut OsRng)
+    }
+
+    /// Like [`Channel::propose`], drawing the key and the salt from the given generator.
+    pub fn propose_with_rng<R: RngCore + CryptoRng>(
+        chain_id: U256,
+        entry_point: Address,
+        factory: Address,
+        rng: &mut R,
+    ) -> (Channel, Proposal) {
+        let key = SigningKey::random(&mut *rng);
+        let salt = rng.gen::<[u8; 32]>().into();
+        let mut channel = Channel::new(
+            chain_id,
+            entry_point,
+            factory,
+            Party::A,
+            &key,
+            Address::zero(),
+            salt,
+        );
+        channel.address = Address::zero();
+        channel.state = ChannelState::Proposed;
+        let proposal = Proposal {
+            chain_id,
+            entry_point,
+            factory,
+            salt,
+            key_a: key.verifying_key().to_sec1_bytes().to_vec(),
+            protocol: PROTOCOL_VERSION,
+        };
+        (channel, proposal)
+    }
+
+    /// Accepts a proposal as party B. The contracts of the proposal should be checked with
+    /// [`Channel::verify_contracts`] before funds go in.
+    pub fn accept(proposal: &Proposal) -> Result<(Channel, Acceptance), Error> {
+        Channel::accept_with_rng(proposal, &mut OsRng)
+    }
+
+    /// Like [`Channel::accept`], drawing the key from the given generator.
+    pub fn accept_with_rng<R: RngCore + CryptoRng>(
+        proposal: &Proposal,
+        rng: &mut R,
+    ) -> Result<(Channel, Acceptance), Error> {
+        let party_a = address_of(&proposal.key_a)?;
+        let key = SigningKey::random(rng);
+        let mut channel = Channel::new(
+            proposal.chain_id,
+            proposal.entry_point,
+            proposal.factory,
+            Party::B,
+            &key,
+            party_a,
+            proposal.salt,
+        );
+        channel.counterparty_key = Some(proposal.key_a.clone());
+        channel.protocol = negotiate(proposal.protocol);
+        let acceptance = Acceptance {
+            channel: channel.address,
+            key_b: key.verifying_key().to_sec1_bytes().to_vec(),
+            protocol: channel.protocol,
+        };
+        Ok((channel, acceptance))
+    }
+
+    /// Completes opening the channel as party A with the acceptance of B.
+    pub fn confirm(&mut self, acceptance: &Acceptance) -> Result<(), Error> {
+        self.check_state(&[ChannelState::Proposed])?;
+        let party_b = address_of(&acceptance.key_b)?;
+        let address = channel_address(self.factory, self.our_address()?, party_b, self.salt);
+        if address != acceptance.channel {
+            return Err(Error::IllegalProposal);
+        }
+        if acceptance.protocol > PROTOCOL_VERSION {
+            return Err(Error::UnsupportedVersion(acceptance.protocol));
+        }
+        self.protocol = acceptance.protocol;
+        self.counterparty = party_b;
+        self.counterparty_key = Some(acceptance.key_b.clone());
+        self.address = address;
+        info!(channel = ?address, "channel confirmed");
+        self.transition(ChannelState::Open);
+        Ok(())
+    }
+}
@@ -0,0 +1,273 @@
+//! Everything the channel reads from or sends to the chain and the bundler goes through
+//! [`ChainClient`], so the rest of the crate does not depend on a particular client library.
+//!
+//! Every ethers [`Middleware`] is a [`ChainClient`]. Sending transactions, i.e. deploying the
+//! factory or the channel, adding to the deposit and closing a dispute, still needs a signing
+//! middleware.
+use crate::retry::RetryPolicy;
+use crate::BoxError;
+use async_trait::async_trait;
+use ethers::abi::{AbiDecode, AbiEncode, AbiError};
+use ethers::providers::{Middleware, MiddlewareError};
+use ethers::types::transaction::eip2718::TypedTransaction;
+use ethers::types::userop::UserOp;
+use ethers::types::{
+    Address, Block, BlockId, BlockNumber, Bytes, EIP1186ProofResponse, Filter, Log, Transaction,
+    TransactionRequest, H256, U256, U64,
+};
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use thiserror::Error;
+
+pub(crate) const LATEST: BlockId = BlockId::Number(BlockNumber::Latest);
+
+#[derive(Error, Debug)]
+pub enum ChainError {
+    #[error("execution reverted: {0}")]
+    Revert(Bytes),
+    #[error("invalid return data: {0}")]
+    Decode(#[from] AbiError),
+    #[error(transparent)]
+    Client(BoxError),
+}
+
+impl ChainError {
+    pub fn client(err: impl std::error::Error + Send + Sync + 'static) -> ChainError {
+        ChainError::Client(Box::new(err))
+    }
+
+    pub fn is_revert(&self) -> bool {
+        matches!(self, ChainError::Revert(_))
+    }
+}
+
+/// The block the channel state is read at. Reading below the head keeps a reorg from showing
+/// balances or disputes that are about to disappear.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BlockPolicy {
+    #[default]
+    Latest,
+    Safe,
+    Finalized,
+    /// the block with this many blocks on top of it
+    Confirmations(u64),
+}
+
+impl BlockPolicy {
+    /// The block to read at now. Resolved once per operation, so all its reads see the same state.
+    pub(crate) async fn resolve<C: ChainClient + ?Sized>(
+        &self,
+        client: &C,
+        retry: &RetryPolicy,
+    ) -> Result<BlockId, ChainError> {
+        Ok(match self {
+            BlockPolicy::Latest => LATEST,
+            BlockPolicy::Safe => BlockNumber::Safe.into(),
+            BlockPolicy::Finalized => BlockNumber::Finalized.into(),
+            BlockPolicy::Confirmations(depth) => {
+                let head = retry.run(|| client.block_number(), |_| true).await?;
+                head.saturating_sub((*depth).into()).into()
+            }
+        })
+    }
+}
+
+impl Display for BlockPolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BlockPolicy::Latest => write!(f, "latest"),
+            BlockPolicy::Safe => write!(f, "safe"),
+            BlockPolicy::Finalized => write!(f, "finalized"),
+            BlockPolicy::Confirmations(depth) => write!(f, "{depth}"),
+        }
+    }
+}
+
+impl FromStr for BlockPolicy {
+    type Err = std::num::ParseIntError;
+
+    /// Parses `latest`, `safe`, `finalized` or a number of confirmations.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "latest" => BlockPolicy::Latest,
+            "safe" => BlockPolicy::Safe,
+            "finalized" => BlockPolicy::Finalized,
+            depth => BlockPolicy::Confirmations(depth.parse()?),
+        })
+    }
+}
+
+/// What the bundler reports about an included userop.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct UserOperationReceipt {
+    /// whether the call of the userop succeeded
+    pub success: bool,
+    pub actual_gas_cost: U256,
+    pub receipt: TransactionReceipt,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionReceipt {
+    pub transaction_hash: H256,
+    pub block_number: U64,
+}
+
+/// The node and bundler the channel talks to.
+#[async_trait]
+pub trait ChainClient: Send + Sync {
+    async fn chain_id(&self) -> Result<U256, ChainError>;
+
+    async fn block_number(&self) -> Result<U64, ChainError>;
+
+    async fn block(&self, block: BlockId) -> Result<Option<Block<H256>>, ChainError>;
+
+    async fn transaction(&self, hash: H256) -> Result<Option<Transaction>, ChainError>;
+
+    async fn get_code(&self, address: Address, block: BlockId) -> Result<Bytes, ChainError>;
+
+    async fn get_balance(&self, address: Address, block: BlockId) -> Result<U256, ChainError>;
+
+    async fn get_proof(
+        &self,
+        address: Address,
+        slots: Vec<H256>,
+        block: BlockId,
+    ) -> Result<EIP1186ProofResponse, ChainError>;
+
+    /// Calls the contract at the block. A revert is reported as [`ChainError::Revert`] with the
+    /// revert data.
+    async fn call(&self, to: Address, data: Bytes, block: BlockId) -> Result<Bytes, ChainError>;
+
+    async fn logs(&self, filter: &Filter) -> Result<Vec<Log>, ChainError>;
+
+    /// Hands the userop to the bundler.
+    async fn send_user_operation(
+        &self,
+        userop: &UserOp,
+        entry_point: Address,
+    ) -> Result<(), ChainError>;
+
+    /// Whether the bundler knows the userop with the hash, pending or included.
+    async fn user_operation_known(&self, hash: H256) -> Result<bool, ChainError>;
+
+    /// The receipt of the userop with the hash, once included.
+    async fn user_operation_receipt(
+        &self,
+        hash: H256,
+    ) -> Result<Option<UserOperationReceipt>, ChainError>;
+}
+
+/// Calls a view function at the block, retrying failures that are not reverts.
+pub(crate) async fn view<C, R>(
+    client: &C,
+    retry: &RetryPolicy,
+    to: Address,
+    call: impl AbiEncode,
+    block: BlockId,
+) -> Result<R, ChainError>
+where
+    C: ChainClient + ?Sized,
+    R: AbiDecode,
+{
+    let data = Bytes::from(call.encode());
+    let output = retry
+        .run(
+            || client.call(to, data.clone(), block),
+            |err| !err.is_revert(),
+        )
+        .await?;
+    Ok(R::decode(output)?)
+}
+
+fn middleware_error<E: MiddlewareError + 'static>(err: E) -> ChainError {
+    match err.as_error_response().and_then(|err| err.as_revert_data()) {
+        Some(data) => ChainError::Revert(data),
+        None => ChainError::client(err),
+    }
+}
+
+#[async_trait]
+impl<M: Middleware + 'static> ChainClient for M {
+    async fn chain_id(&self) -> Result<U256, ChainError> {
+        self.get_chainid().await.map_err(middleware_error)
+    }
+
+    async fn block_number(&self) -> Result<U64, ChainError> {
+        self.get_block_number().await.map_err(middleware_error)
+    }
+
+    async fn block(&self, block: BlockId) -> Result<Option<Block<H256>>, ChainError> {
+        self.get_block(block).await.map_err(middleware_error)
+    }
+
+    async fn transaction(&self, hash: H256) -> Result<Option<Transaction>, ChainError> {
+        self.get_transaction(hash).await.map_err(middleware_error)
+    }
+
+    async fn get_code(&self, address: Address, block: BlockId) -> Result<Bytes, ChainError> {
+        Middleware::get_code(self, address, Some(block))
+            .await
+            .map_err(middleware_error)
+    }
+
+    async fn get_balance(&self, address: Address, block: BlockId) -> Result<U256, ChainError> {
+        Middleware::get_balance(self, address, Some(block))
+            .await
+            .map_err(middleware_error)
+    }
+
+    async fn get_proof(
+        &self,
+        address: Address,
+        slots: Vec<H256>,
+        block: BlockId,
+    ) -> Result<EIP1186ProofResponse, ChainError> {
+        Middleware::get_proof(self, address, slots, Some(block))
+            .await
+            .map_err(middleware_error)
+    }
+
+    async fn call(&self, to: Address, data: Bytes, block: BlockId) -> Result<Bytes, ChainError> {
+        let transaction: TypedTransaction = TransactionRequest::new().to(to).data(data).into();
+        Middleware::call(self, &transaction, Some(block))
+            .await
+            .map_err(middleware_error)
+    }
+
+    async fn logs(&self, filter: &Filter) -> Result<Vec<Log>, ChainError> {
+        self.get_logs(filter).await.map_err(middleware_error)
+    }
+
+    async fn send_user_operation(
+        &self,
+        userop: &UserOp,
+        entry_point: Address,
+    ) -> Result<(), ChainError> {
+        Middleware::send_user_operation(self, userop.clone(), entry_point)
+            .await
+            .map(|_| ())
+            .map_err(middleware_error)
+    }
+
+    async fn user_operation_known(&self, hash: H256) -> Result<bool, ChainError> {
+        let userop: Option<serde_json::Value> = self
+            .provider()
+            .request("eth_getUserOperationByHash", [hash])
+            .await
+            .map_err(middleware_error)?;
+        Ok(userop.is_some())
+    }
+
+    async fn user_operation_receipt(
+        &self,
+        hash: H256,
+    ) -> Result<Option<UserOperationReceipt>, ChainError> {
+        self.provider()
+            .request("eth_getUserOperationReceipt", [hash])
+            .await
+            .map_err(middleware_error)
+    }
+}
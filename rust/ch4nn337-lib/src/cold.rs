@@ -0,0 +1,166 @@
+//! Cold signing: an online machine keeps a watch-only copy of the channel, which builds and
+//! validates userops but has no key, and an offline machine holding the key signs them.
+//!
+//! Userops built on a watch-only channel are left unsigned, [`Channel::signing_request`] exports
+//! them for the offline machine, which answers with [`Channel::sign_request`]. The signature is
+//! then merged with [`Channel::apply_signature`].
+//!
+//! An observer copy from [`Channel::into_observer`] goes further: it tracks balances, messages
+//! and disputes, e.g. for monitoring or accounting, but every signing method fails with
+//! [`Error::ReadOnly`], so it can not even prepare userops for an offline key.
+use crate::chain::ChainClient;
+use crate::operation::bounded;
+use crate::protocol::OutgoingMessage;
+use crate::secret::KeyBytes;
+use crate::Error::*;
+use crate::{Channel, Error, Message};
+use ch4nn337_sys::signature::verify_signer;
+use ethers::types::userop::UserOp;
+use ethers::types::{Address, Bytes, H256, U256};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, OnceLock};
+
+/// Everything the offline machine needs to sign a userop.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SigningRequest {
+    pub channel: Address,
+    pub chain_id: U256,
+    pub entry_point: Address,
+    pub userop: UserOp,
+    /// hash of the userop, as computed by the online machine
+    pub hash: H256,
+}
+
+impl Channel {
+    /// Drops the key material, for keeping the channel on the online machine.
+    pub fn into_watch_only(mut self) -> Channel {
+        self.address_us = self.our_address().ok();
+        self.key = KeyBytes::default();
+        self.signer = OnceLock::new();
+        self
+    }
+
+    /// Drops the key material and the ability to sign, for handing the channel to someone who
+    /// must only watch it.
+    pub fn into_observer(self) -> Channel {
+        let mut channel = self.into_watch_only();
+        channel.observer = true;
+        channel.pending_message = None;
+        channel.unsigned_message = None;
+        channel
+    }
+
+    pub fn is_observer(&self) -> bool {
+        self.observer
+    }
+
+    /// Fails with [`Error::ReadOnly`] on observers, and with [`Error::WatchOnly`] on watch-only
+    /// channels unless the userop may be left for signing `offline`.
+    pub(crate) fn check_signer(&self, offline: bool) -> Result<(), Error> {
+        if self.observer {
+            Err(ReadOnly)
+        } else if !offline && self.is_watch_only() {
+            Err(WatchOnly)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn request_for(&self, userop: &UserOp) -> SigningRequest {
+        SigningRequest {
+            channel: self.address,
+            chain_id: self.chain_id,
+            entry_point: self.entry_point,
+            userop: userop.clone(),
+            hash: self.user_op_hash(userop),
+        }
+    }
+
+    /// Validates a message of the counterparty like [`Channel::sign_message`] would and keeps it
+    /// until the signature is applied.
+    pub fn request_signature(&mut self, message: Message) -> Result<SigningRequest, Error> {
+        self.check_signer(true)?;
+        self.check_signable(&message)?;
+        let hash = self.user_op_hash(message.userop());
+        self.learn_counterparty_key(hash, &message.userop().signature);
+        let request = self.request_for(message.userop());
+        self.unsigned_message = Some(message);
+        Ok(request)
+    }
+
+    /// The userop waiting for our offline signature, if any. A message of the counterparty takes
+    /// precedence over our own pending message.
+    pub fn signing_request(&self) -> Option<SigningRequest> {
+        if let Some(message) = &self.unsigned_message {
+            return Some(self.request_for(message.userop()));
+        }
+        self.pending_message
+            .as_ref()
+            .filter(|message| message.userop().signature.is_empty())
+            .map(|message| self.request_for(message.userop()))
+    }
+
+    /// Signs a request on the offline machine. The hash is computed anew, so the online machine
+    /// can not have us sign something other than the userop it shows.
+    pub async fn sign_request(&self, request: &SigningRequest) -> Result<Bytes, Error> {
+        self.check_signer(false)?;
+        if request.channel != self.address
+            || request.userop.sender != self.address
+            || request.chain_id != self.chain_id
+            || request.entry_point != self.entry_point
+            || request.hash != self.user_op_hash(&request.userop)
+        {
+            return Err(IllegalSigningRequest);
+        }
+        self.sign(&request.userop).await
+    }
+
+    /// Merges the signature from the offline machine. Returns the message to hand to the
+    /// counterparty, like [`Channel::sign_message`] or the `request_*` methods would have.
+    pub async fn apply_signature<C: ChainClient + ?Sized>(
+        &mut self,
+        signature: Bytes,
+        client: Arc<C>,
+    ) -> Result<OutgoingMessage, Error> {
+        bounded(self.operation.timeout, async {
+            self.check_signer(true)?;
+            if let Some(message) = &self.unsigned_message {
+                self.check_signed_by_us(message.userop(), &signature)?;
+                // checked again, the world may have moved on while signing
+                let outflow = self.check_signable(message)?;
+                let userop = self
+                    .countersigned_userop(message, signature, client.clone())
+                    .await?;
+                // only taken once nothing can fail before it is recorded
+                let message = self.unsigned_message.take().expect("checked above");
+                return self
+                    .record_countersigned(message, userop, outflow, client)
+                    .await;
+            }
+
+            let Some(pending) = self
+                .pending_message
+                .as_ref()
+                .filter(|message| message.userop().signature.is_empty())
+            else {
+                return Err(NotWaiting);
+            };
+            self.check_signed_by_us(pending.userop(), &signature)?;
+            let userop = self
+                .pending_message
+                .as_mut()
+                .map(|message| {
+                    message.userop_mut().signature = signature;
+                    message.userop().clone()
+                })
+                .expect("checked above");
+            Ok(self.outgoing(&userop))
+        })
+        .await
+    }
+
+    fn check_signed_by_us(&self, userop: &UserOp, signature: &Bytes) -> Result<(), Error> {
+        verify_signer(signature, self.user_op_hash(userop), self.our_address()?)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,66 @@
+//! A book of known counterparties, so they can be referred to by name instead of by address.
+use crate::Channel;
+use ethers::types::{Address, U256};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ContactError {
+    #[error("a contact named {0} already exists")]
+    DuplicateLabel(String),
+    #[error("{0:?} is already known as {1}")]
+    DuplicateAddress(Address, String),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Contact {
+    pub label: String,
+    pub address: Address,
+    /// where messages for this counterparty are sent
+    pub endpoint: Option<String>,
+    /// chain id of the network they prefer to open channels on
+    pub chain_id: Option<U256>,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct ContactBook {
+    contacts: Vec<Contact>,
+}
+
+impl ContactBook {
+    pub fn add(&mut self, contact: Contact) -> Result<(), ContactError> {
+        if self.get(&contact.label).is_some() {
+            return Err(ContactError::DuplicateLabel(contact.label));
+        }
+        if let Some(known) = self.by_address(contact.address) {
+            return Err(ContactError::DuplicateAddress(
+                contact.address,
+                known.label.clone(),
+            ));
+        }
+        self.contacts.push(contact);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, label: &str) -> Option<Contact> {
+        let index = self.contacts.iter().position(|c| c.label == label)?;
+        Some(self.contacts.remove(index))
+    }
+
+    pub fn get(&self, label: &str) -> Option<&Contact> {
+        self.contacts.iter().find(|c| c.label == label)
+    }
+
+    pub fn by_address(&self, address: Address) -> Option<&Contact> {
+        self.contacts.iter().find(|c| c.address == address)
+    }
+
+    /// The contact the channel's counterparty is known as.
+    pub fn counterparty(&self, channel: &Channel) -> Option<&Contact> {
+        self.by_address(channel.their_address())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Contact> {
+        self.contacts.iter()
+    }
+}
@@ -0,0 +1,122 @@
+//! Checks that the contracts a channel relies on run the code we expect, so a malicious factory
+//! or implementation handed to us with the channel parameters is noticed before funds go in.
+use crate::chain::{self, ChainClient, LATEST};
+use crate::operation::bounded;
+use crate::{Channel, Error};
+use ch4nn337_sys::aa_channel_factory::{AaChannelCall, AaChannelReturn};
+use ch4nn337_sys::artifacts::{
+    AACHANNELFACTORY_DEPLOYED_BYTECODE, AACHANNEL_DEPLOYED_BYTECODE, ERC1967PROXY_DEPLOYED_BYTECODE,
+};
+use ethers::types::{Address, H256};
+use std::sync::Arc;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum CodeError {
+    #[error("no code at {0:?}")]
+    NotDeployed(Address),
+    #[error("factory at {0:?} is not the expected implementation")]
+    Factory(Address),
+    #[error("channel implementation at {0:?} is not the expected implementation")]
+    Implementation(Address),
+    #[error("channel at {0:?} is not the expected proxy")]
+    Channel(Address),
+}
+
+impl Channel {
+    /// Compares the code of the factory, the channel implementation and, if deployed, the channel
+    /// against the bytecode embedded in `ch4nn337-sys`.
+    ///
+    /// There is no EntryPoint artifact in the tree, so the entry point is only checked to exist
+    /// and to be the one the channel implementation trusts.
+    pub async fn verify_contracts<C: ChainClient + ?Sized>(
+        &self,
+        client: Arc<C>,
+    ) -> Result<(), Error> {
+        bounded(self.operation.timeout, async {
+            let code = |address| {
+                let client = client.clone();
+                async move { Ok::<_, Error>(client.get_code(address, LATEST).await?) }
+            };
+
+            if code(self.entry_point).await?.is_empty() {
+                return Err(CodeError::NotDeployed(self.entry_point).into());
+            }
+
+            let factory = code(self.factory).await?;
+            if factory.is_empty() {
+                return Err(CodeError::NotDeployed(self.factory).into());
+            }
+            let AaChannelReturn(implementation) = chain::view(
+                client.as_ref(),
+                &self.retry,
+                self.factory,
+                AaChannelCall,
+                LATEST,
+            )
+            .await?;
+            if !matches(
+                &factory,
+                &AACHANNELFACTORY_DEPLOYED_BYTECODE,
+                implementation.into(),
+            ) {
+                return Err(CodeError::Factory(self.factory).into());
+            }
+
+            // the entry point is an immutable of the implementation
+            if !matches(
+                &code(implementation).await?,
+                &AACHANNEL_DEPLOYED_BYTECODE,
+                self.entry_point.into(),
+            ) {
+                return Err(CodeError::Implementation(implementation).into());
+            }
+
+            let channel = code(self.address).await?;
+            if !channel.is_empty() && channel != ERC1967PROXY_DEPLOYED_BYTECODE {
+                return Err(CodeError::Channel(self.address).into());
+            }
+            Ok::<_, Error>(())
+        })
+        .await
+    }
+}
+
+/// Reads the immutable out of deployed code, if it is otherwise equal to the compiled code.
+pub(crate) fn immutable(deployed: &[u8], compiled: &[u8]) -> Option<H256> {
+    if deployed.len() != compiled.len() {
+        return None;
+    }
+    let i = deployed.iter().zip(compiled).position(|(a, b)| a != b)?;
+    (i.saturating_sub(31)..=i)
+        .filter(|&start| start + 32 <= deployed.len())
+        .map(|start| H256::from_slice(&deployed[start..start + 32]))
+        .find(|&immutable| matches(deployed, compiled, immutable))
+}
+
+/// Whether deployed code equals the compiled code with its immutable filled in. The compiled code
+/// has zero words where the immutable goes.
+fn matches(deployed: &[u8], compiled: &[u8], immutable: H256) -> bool {
+    if deployed.len() != compiled.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < deployed.len() {
+        if deployed[i] == compiled[i] {
+            i += 1;
+            continue;
+        }
+        // the immutable may start with zero bytes, so the mismatch can be within its word
+        let word = (i.saturating_sub(31)..=i).find(|&start| {
+            let end = start + 32;
+            end <= deployed.len()
+                && compiled[start..end].iter().all(|&byte| byte == 0)
+                && deployed[start..end] == immutable[..]
+        });
+        match word {
+            Some(start) => i = start + 32,
+            None => return false,
+        }
+    }
+    true
+}
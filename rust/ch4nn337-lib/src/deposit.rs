@@ -0,0 +1,135 @@
+//! The deposit of a channel at the entry point. A deployed channel forwards all its funds to the
+//! entry point, which pays the gas of every userop from that deposit. Fees therefore reduce the
+//! deposit below the sum of both balances, and the contract splits the difference evenly on
+//! withdrawal.
+use crate::amount::Wei;
+use crate::chain::{self, ChainClient, LATEST};
+use crate::operation::bounded;
+use crate::{Channel, Error, Party};
+use ch4nn337_sys::i_entry_point::{BalanceOfCall, BalanceOfReturn, IEntryPoint};
+use ethers::providers::{Middleware, ProviderError};
+use ethers::types::userop::UserOp;
+use ethers::types::{TransactionRequest, H256, U256};
+use std::sync::Arc;
+use tracing::{info, instrument};
+
+impl Channel {
+    /// The deposit of the channel at the entry point. Before deployment, funds sent to the channel
+    /// stay at its address, so the deposit is usually zero.
+    pub async fn entry_point_deposit<C: ChainClient + ?Sized>(
+        &self,
+        client: Arc<C>,
+    ) -> Result<U256, Error> {
+        let call = BalanceOfCall {
+            account: self.address,
+        };
+        let BalanceOfReturn(deposit) =
+            chain::view(client.as_ref(), &self.retry, self.entry_point, call, LATEST).await?;
+        Ok(deposit)
+    }
+
+    /// Tops up the deposit, paid by the client, which has to be able to sign and pay for the
+    /// transaction. The top up is credited to neither party, whatever is left of it at withdrawal
+    /// is split evenly.
+    #[instrument(skip_all, fields(channel = ?self.address, amount = %amount))]
+    pub async fn add_deposit<M: Middleware + 'static>(
+        &self,
+        amount: U256,
+        client: Arc<M>,
+    ) -> Result<(), Error> {
+        bounded(self.operation.timeout, async {
+            IEntryPoint::new(self.entry_point, client)
+                .deposit_to(self.address)
+                .value(amount)
+                .send()
+                .await?
+                .interval(self.operation.poll_interval)
+                .confirmations(self.operation.confirmations)
+                .await?;
+            info!("added deposit");
+            Ok::<_, Error>(())
+        })
+        .await
+    }
+
+    /// Sends funds to the channel, which credits them to party A whether it is deployed or not.
+    /// The client has to be able to sign and pay for the transaction. Returns the hash of the
+    /// transaction once it has the configured confirmations.
+    #[instrument(skip_all, fields(channel = ?self.address, amount = %amount))]
+    pub async fn fund<M: Middleware + 'static>(
+        &self,
+        amount: U256,
+        client: Arc<M>,
+    ) -> Result<H256, Error> {
+        bounded(self.operation.timeout, async {
+            let pending = client
+                .send_transaction(TransactionRequest::pay(self.address, amount), None)
+                .await
+                .map_err(|err| Error::MiddlewareError(Box::new(err)))?;
+            let hash = pending.tx_hash();
+            pending
+                .interval(self.operation.poll_interval)
+                .confirmations(self.operation.confirmations)
+                .await?
+                .ok_or_else(|| ProviderError::CustomError("funding transaction dropped".into()))?;
+            info!(transaction = ?hash, "funded channel");
+            Ok::<_, Error>(hash)
+        })
+        .await
+    }
+
+    /// What a withdrawal pays out to us and to them, once the entry point took the maximum cost of
+    /// the withdrawal userop from the deposit. `None` if the fees exceed the withdrawal, in which
+    /// case the withdrawal reverts.
+    pub(crate) async fn withdrawal_payout<C: ChainClient + ?Sized>(
+        &self,
+        userop: &UserOp,
+        withdraw_a: u128,
+        withdraw_b: u128,
+        client: Arc<C>,
+    ) -> Result<Option<(Wei, Wei)>, Error> {
+        let mut deposit = self.entry_point_deposit(client.clone()).await?;
+        if !self.is_deployed(&client).await? {
+            // moved to the entry point when the channel is deployed with the withdrawal
+            deposit += self
+                .retry
+                .run(|| client.get_balance(self.address, LATEST), |_| true)
+                .await?;
+        }
+        let deposit = deposit.saturating_sub(max_cost(userop)).low_u128();
+        Ok(
+            fair_distribute(withdraw_a, withdraw_b, deposit).map(|(a, b)| match self.us {
+                Party::A => (Wei(a), Wei(b)),
+                Party::B => (Wei(b), Wei(a)),
+            }),
+        )
+    }
+}
+
+/// The prefund the entry point takes from the deposit before executing the userop.
+fn max_cost(userop: &UserOp) -> U256 {
+    (userop.call_gas_limit + userop.verification_gas_limit + userop.pre_verificaiton_gas)
+        * userop.max_fee_per_gas
+}
+
+/// Mirrors `AAChannel._fairDistribute`: a shortfall of the deposit is taken from both withdrawals
+/// in equal parts, a surplus is added to both in equal parts.
+fn fair_distribute(withdraw_a: u128, withdraw_b: u128, deposit: u128) -> Option<(u128, u128)> {
+    let total = withdraw_a + withdraw_b;
+    if total > deposit {
+        let shortfall = total - deposit;
+        if shortfall >= total {
+            return None;
+        }
+        let share_a = shortfall / 2;
+        let share_b = shortfall - share_a;
+        // the contract reverts if a share exceeds its withdrawal
+        Some((
+            withdraw_a.checked_sub(share_a)?,
+            withdraw_b.checked_sub(share_b)?,
+        ))
+    } else {
+        let surplus = (deposit - total) / 2;
+        Some((withdraw_a + surplus, withdraw_b + surplus))
+    }
+}
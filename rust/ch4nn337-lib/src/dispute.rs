@@ -0,0 +1,91 @@
+//! Disputes, for when the counterparty stops cooperating. Either party can start one by
+//! submitting the latest transfer signed by both. Once the dispute timed out, anyone can close it,
+//! which pays out the balances of the disputed state.
+use crate::chain::ChainClient;
+use crate::operation::bounded;
+use crate::{now, Channel, ChannelState, Error, Message};
+use ch4nn337_sys::aa_channel::AAChannel;
+use ethers::providers::Middleware;
+use ethers::types::userop::UserOp;
+use std::sync::Arc;
+use tracing::{info, instrument};
+
+impl Channel {
+    /// The latest transfer signed by both parties. A withdrawal after it settled the channel.
+    fn disputable(&self) -> Option<&UserOp> {
+        match self.latest_settleable()? {
+            Message::Transfer(transfer) => Some(&transfer.userop),
+            Message::Withdrawal(_) => None,
+        }
+    }
+
+    /// Starts a dispute with the latest transfer, handing it to the bundler.
+    #[instrument(skip_all, fields(channel = ?self.address))]
+    pub async fn start_dispute<C: ChainClient + ?Sized>(
+        &mut self,
+        client: Arc<C>,
+    ) -> Result<(), Error> {
+        bounded(self.operation.timeout, async {
+            self.check_state(&[ChannelState::Open, ChannelState::PendingWithdrawal])?;
+            let userop = self.disputable().ok_or(Error::NothingToDispute)?.clone();
+            self.simulate(&userop, client.clone()).await?;
+            info!(nonce = %userop.nonce, "submitting dispute");
+            self.retry
+                .run(|| self.submit(&userop, &client), |_| true)
+                .await?;
+            // if dropped before this, update_state notices the dispute on-chain
+            self.transition(ChannelState::Disputed);
+            Ok::<_, Error>(())
+        })
+        .await
+    }
+
+    /// Closes a timed out dispute. The client has to be able to sign and pay for the transaction.
+    #[instrument(skip_all, fields(channel = ?self.address))]
+    pub async fn close_dispute<M: Middleware + 'static>(
+        &mut self,
+        client: Arc<M>,
+    ) -> Result<(), Error> {
+        bounded(self.operation.timeout, async {
+            self.check_state(&[ChannelState::Disputed])?;
+            AAChannel::new(self.address, client)
+                .close_dispute()
+                .send()
+                .await?
+                .interval(self.operation.poll_interval)
+                .confirmations(self.operation.confirmations)
+                .await?;
+            info!("closed dispute");
+            self.transition(ChannelState::Closed);
+            Ok::<_, Error>(())
+        })
+        .await
+    }
+
+    /// When we last completed a message with the counterparty, unknown if only messages from
+    /// older versions are recorded.
+    pub fn last_contact(&self) -> Option<u64> {
+        self.messages.iter().filter_map(Message::signed_at).max()
+    }
+
+    /// Whether to dispute on our own: the counterparty has been silent for `unreachable_after`
+    /// seconds, and the latest transfer leaves us a balance to claim. Only open channels
+    /// without pending messages qualify, so a counterparty merely slow to answer a withdrawal
+    /// is not disputed.
+    pub async fn should_dispute<C: ChainClient + ?Sized>(
+        &self,
+        unreachable_after: u64,
+        client: Arc<C>,
+    ) -> Result<bool, Error> {
+        if self.state != ChannelState::Open
+            || self.pending_message.is_some()
+            || self.disputable().is_none()
+        {
+            return Ok(false);
+        }
+        let silent = self
+            .last_contact()
+            .is_some_and(|last| now().saturating_sub(last) >= unreachable_after);
+        Ok(silent && !self.get_sorted_balances(client).await?.0.is_zero())
+    }
+}
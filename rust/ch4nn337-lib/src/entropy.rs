@@ -0,0 +1,14 @@
+//! Randomness for keys and salts. The plain constructors of [`Channel`](crate::Channel) draw from
+//! [`OsRng`], their `_with_rng` variants take any cryptographically secure generator instead, e.g.
+//! a hardware RNG or one mixing in entropy the environment requires.
+//!
+//! With the `deterministic` feature, [`seeded`] gives a reproducible generator for tests and
+//! fixtures. Never use it for real channels: anyone who knows the seed knows the keys.
+pub use rand::rngs::OsRng;
+pub use rand::{CryptoRng, RngCore};
+
+/// A generator yielding the same keys and salts for the same seed.
+#[cfg(feature = "deterministic")]
+pub fn seeded(seed: u64) -> rand::rngs::StdRng {
+    rand::SeedableRng::seed_from_u64(seed)
+}
@@ -0,0 +1,191 @@
+//! End-to-end encryption of messages between the two parties of a channel. The key is derived by
+//! ECDH over the channel keys, so anything carrying a sealed message learns nothing about it.
+use crate::Channel;
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use ethers::core::k256::ecdsa::{self, RecoveryId, VerifyingKey};
+use ethers::core::k256::elliptic_curve::point::AffineCoordinates;
+use ethers::core::k256::ProjectivePoint;
+use ethers::types::{Signature, H256};
+use ethers::utils::{hash_message, hex, keccak256, public_key_to_address};
+use rand::rngs::OsRng;
+use rand::Rng;
+use thiserror::Error;
+
+const NONCE_LENGTH: usize = 12;
+
+#[derive(Error, Debug)]
+pub enum EnvelopeError {
+    #[error("public key of the counterparty is unknown")]
+    UnknownKey,
+    #[error("channel is watch-only")]
+    WatchOnly,
+    #[error("malformed envelope")]
+    Malformed,
+    #[error("unable to decrypt envelope")]
+    Decryption,
+}
+
+impl Channel {
+    /// Remembers the counterparty's public key, recovered from their signature of a userop, for
+    /// channels that were stored without it.
+    pub(crate) fn learn_counterparty_key(&mut self, hash: H256, signature: &[u8]) {
+        if self.counterparty_key.is_some() {
+            return;
+        }
+        let Some(key) = Signature::try_from(signature)
+            .ok()
+            .and_then(|signature| recover_key(hash, &signature))
+        else {
+            return;
+        };
+        if public_key_to_address(&key) == self.counterparty {
+            self.counterparty_key = Some(key.to_sec1_bytes().to_vec());
+        }
+    }
+
+    fn cipher(&self) -> Result<ChaCha20Poly1305, EnvelopeError> {
+        let wallet = self.wallet().ok_or(EnvelopeError::WatchOnly)?;
+        let their_key = self
+            .counterparty_key
+            .as_deref()
+            .ok_or(EnvelopeError::UnknownKey)?;
+        let their_key =
+            VerifyingKey::from_sec1_bytes(their_key).map_err(|_| EnvelopeError::UnknownKey)?;
+        let shared = (ProjectivePoint::from(*their_key.as_affine())
+            * *wallet.signer().as_nonzero_scalar())
+        .to_affine();
+        // bind the key to the channel, in case the keys are ever reused
+        let key = keccak256([shared.x().as_slice(), self.address.as_bytes()].concat());
+        Ok(ChaCha20Poly1305::new(Key::from_slice(&key)))
+    }
+
+    /// Encrypts a message so only the counterparty can read it.
+    pub fn seal(&self, message: &str) -> Result<String, EnvelopeError> {
+        let nonce: [u8; NONCE_LENGTH] = OsRng.gen();
+        let ciphertext = self
+            .cipher()?
+            .encrypt(Nonce::from_slice(&nonce), message.as_bytes())
+            .expect("encryption does not fail");
+        Ok(hex::encode([nonce.as_slice(), &ciphertext].concat()))
+    }
+
+    /// Decrypts a message sealed by the counterparty.
+    pub fn unseal(&self, envelope: &str) -> Result<String, EnvelopeError> {
+        let envelope = hex::decode(envelope.trim()).map_err(|_| EnvelopeError::Malformed)?;
+        if envelope.len() < NONCE_LENGTH {
+            return Err(EnvelopeError::Malformed);
+        }
+        let (nonce, ciphertext) = envelope.split_at(NONCE_LENGTH);
+        let message = self
+            .cipher()?
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| EnvelopeError::Decryption)?;
+        String::from_utf8(message).map_err(|_| EnvelopeError::Malformed)
+    }
+}
+
+fn recover_key(hash: H256, signature: &Signature) -> Option<VerifyingKey> {
+    let mut bytes = [0; 64];
+    signature.r.to_big_endian(&mut bytes[..32]);
+    signature.s.to_big_endian(&mut bytes[32..]);
+    let recovery = RecoveryId::from_byte(u8::try_from(signature.v.checked_sub(27)?).ok()?)?;
+    VerifyingKey::recover_from_prehash(
+        hash_message(hash).as_bytes(),
+        &ecdsa::Signature::from_slice(&bytes).ok()?,
+        recovery,
+    )
+    .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Party;
+    use ethers::core::k256::ecdsa::SigningKey;
+    use ethers::signers::{Signer, Wallet};
+    use ethers::types::{Address, U256};
+
+    /// Both sides of a channel that know each other's public key.
+    fn pair() -> (Channel, Channel) {
+        let key_a = SigningKey::random(&mut OsRng);
+        let key_b = SigningKey::random(&mut OsRng);
+        let channel = |us, key: &SigningKey, other: &SigningKey| {
+            let mut channel = Channel::new(
+                5.into(),
+                Address::zero(),
+                Address::zero(),
+                us,
+                key,
+                Wallet::from(other.clone()).address(),
+                U256::zero(),
+            );
+            channel.counterparty_key = Some(other.verifying_key().to_sec1_bytes().to_vec());
+            channel
+        };
+        (
+            channel(Party::A, &key_a, &key_b),
+            channel(Party::B, &key_b, &key_a),
+        )
+    }
+
+    #[test]
+    fn sealed_by_one_side_is_unsealed_by_the_other() {
+        let (a, b) = pair();
+        let sealed = a.seal("hello").unwrap();
+        assert_eq!(b.unseal(&sealed).unwrap(), "hello");
+        assert_eq!(a.unseal(&b.seal("hi").unwrap()).unwrap(), "hi");
+        // fresh nonce every time
+        assert_ne!(a.seal("hello").unwrap(), sealed);
+    }
+
+    #[test]
+    fn tampered_envelopes_are_rejected() {
+        let (a, b) = pair();
+        let mut envelope = hex::decode(a.seal("hello").unwrap()).unwrap();
+        *envelope.last_mut().unwrap() ^= 1;
+        assert!(matches!(
+            b.unseal(&hex::encode(&envelope)),
+            Err(EnvelopeError::Decryption)
+        ));
+        // nor does the right ciphertext under another nonce
+        *envelope.last_mut().unwrap() ^= 1;
+        envelope[0] ^= 1;
+        assert!(matches!(
+            b.unseal(&hex::encode(&envelope)),
+            Err(EnvelopeError::Decryption)
+        ));
+    }
+
+    #[test]
+    fn envelopes_of_other_channels_are_rejected() {
+        let (a, _) = pair();
+        let (_, d) = pair();
+        assert!(matches!(
+            d.unseal(&a.seal("hello").unwrap()),
+            Err(EnvelopeError::Decryption)
+        ));
+    }
+
+    #[test]
+    fn malformed_envelopes_are_rejected() {
+        let (_, b) = pair();
+        for envelope in ["", "zz", "00", "00".repeat(NONCE_LENGTH - 1).as_str()] {
+            assert!(
+                matches!(b.unseal(envelope), Err(EnvelopeError::Malformed)),
+                "{envelope}"
+            );
+        }
+    }
+
+    #[test]
+    fn sealing_needs_both_keys() {
+        let (mut a, b) = pair();
+        assert!(matches!(
+            b.into_watch_only().seal("hello"),
+            Err(EnvelopeError::WatchOnly)
+        ));
+        a.counterparty_key = None;
+        assert!(matches!(a.seal("hello"), Err(EnvelopeError::UnknownKey)));
+    }
+}
@@ -0,0 +1,102 @@
+//! Export of the [`history`](crate::history) of a channel for bookkeeping. Amounts are exported in
+//! wei in CSV and in ETH in OFX, where the channel is a bank account in ETH.
+use crate::amount::format_amount;
+use crate::history::{Entry, EntryKind};
+use ethers::types::Address;
+use std::fmt::Write;
+
+/// One line per entry, with a header. Times are unix timestamps.
+pub fn to_csv(entries: &[Entry]) -> String {
+    let mut csv = "time,kind,amount_wei,counterparty,memo,userop,transaction\n".to_string();
+    for entry in entries {
+        writeln!(
+            csv,
+            "{},{:?},{},{:?},{},{:?},{}",
+            entry.time.map(|time| time.to_string()).unwrap_or_default(),
+            entry.kind,
+            entry.amount,
+            entry.counterparty,
+            escape_csv(entry.memo.as_deref().unwrap_or_default()),
+            entry.userop,
+            entry
+                .transaction
+                .map(|transaction| format!("{transaction:?}"))
+                .unwrap_or_default(),
+        )
+        .unwrap();
+    }
+    csv
+}
+
+fn escape_csv(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// An OFX 2 bank statement of the channel, identified by its address.
+pub fn to_ofx(entries: &[Entry], channel: Address) -> String {
+    let mut transactions = String::new();
+    for entry in entries {
+        let kind = match (entry.kind, entry.amount) {
+            (EntryKind::Transfer, amount) if amount < 0 => "DEBIT",
+            (EntryKind::Transfer, _) => "CREDIT",
+            _ => "OTHER",
+        };
+        let amount = format_amount(entry.amount.unsigned_abs());
+        let amount = amount.trim_end_matches(" ETH");
+        let sign = if entry.amount < 0 { "-" } else { "" };
+        let memo = entry
+            .memo
+            .as_deref()
+            .map(|memo| format!("<MEMO>{}</MEMO>", escape_xml(memo)))
+            .unwrap_or_default();
+        write!(
+            transactions,
+            "<STMTTRN><TRNTYPE>{kind}</TRNTYPE><DTPOSTED>{}</DTPOSTED><TRNAMT>{sign}{amount}</TRNAMT>\
+             <FITID>{:?}</FITID><NAME>{:?}</NAME>{memo}</STMTTRN>\n",
+            ofx_date(entry.time.unwrap_or(0)),
+            entry.userop,
+            entry.counterparty,
+        )
+        .unwrap();
+    }
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <?OFX OFXHEADER=\"200\" VERSION=\"220\" SECURITY=\"NONE\" OLDFILEUID=\"NONE\" NEWFILEUID=\"NONE\"?>\n\
+         <OFX><BANKMSGSRSV1><STMTTRNRS><TRNUID>0</TRNUID>\
+         <STATUS><CODE>0</CODE><SEVERITY>INFO</SEVERITY></STATUS>\
+         <STMTRS><CURDEF>ETH</CURDEF><BANKACCTFROM><BANKID>ch4nn337</BANKID><ACCTID>{channel:?}</ACCTID>\
+         <ACCTTYPE>CHECKING</ACCTTYPE></BANKACCTFROM><BANKTRANLIST>\n{transactions}</BANKTRANLIST>\
+         </STMTRS></STMTTRNRS></BANKMSGSRSV1></OFX>\n"
+    )
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Formats a unix timestamp as `YYYYMMDDHHMMSS` in UTC.
+fn ofx_date(time: u64) -> String {
+    let (days, seconds) = (time / 86400, time % 86400);
+    // civil from days, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}{month:02}{day:02}{:02}{:02}{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
@@ -0,0 +1,174 @@
+//! A transport spreading requests over several RPC endpoints. Requests go to the first endpoint
+//! that answers, except for the reads our balances depend on: those are sent to every endpoint
+//! and the answers have to agree, so a single malicious RPC cannot lie about the channel state.
+//! At least a quorum of endpoints has to answer them, so the others failing does not leave a
+//! single one to be trusted.
+//!
+//! Endpoints at different block heights can disagree on `latest` reads, which surfaces as
+//! [`FailoverError::Disagreement`] and should be retried.
+use async_trait::async_trait;
+use ethers::providers::{JsonRpcClient, JsonRpcError, ProviderError, RpcError};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::fmt::Debug;
+use thiserror::Error;
+use tracing::warn;
+
+const CROSS_CHECKED: &[&str] = &[
+    "eth_call",
+    "eth_getBalance",
+    "eth_getCode",
+    "eth_getStorageAt",
+];
+
+#[derive(Error, Debug)]
+pub enum FailoverError {
+    #[error("no endpoints configured")]
+    NoEndpoints,
+    #[error("{0}")]
+    Provider(ProviderError),
+    #[error("endpoints disagree on {0}")]
+    Disagreement(String),
+    #[error("only {answers} endpoints answered {method}, {quorum} have to agree")]
+    NoQuorum {
+        method: String,
+        answers: usize,
+        quorum: usize,
+    },
+    #[error("{0}")]
+    Serde(#[from] serde_json::Error),
+}
+
+impl RpcError for FailoverError {
+    fn as_error_response(&self) -> Option<&JsonRpcError> {
+        match self {
+            FailoverError::Provider(err) => err.as_error_response(),
+            _ => None,
+        }
+    }
+
+    fn as_serde_error(&self) -> Option<&serde_json::Error> {
+        match self {
+            FailoverError::Provider(err) => err.as_serde_error(),
+            FailoverError::Serde(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<FailoverError> for ProviderError {
+    fn from(src: FailoverError) -> Self {
+        ProviderError::JsonRpcClientError(Box::new(src))
+    }
+}
+
+#[derive(Debug)]
+pub struct FailoverClient<C> {
+    clients: Vec<C>,
+    /// endpoints that have to agree on cross-checked reads
+    quorum: usize,
+}
+
+impl<C: JsonRpcClient> FailoverClient<C> {
+    /// Cross-checked reads need two agreeing endpoints, or the only one if just one is given.
+    pub fn new(clients: Vec<C>) -> Self {
+        let quorum = clients.len().min(2);
+        FailoverClient { clients, quorum }
+    }
+
+    /// Sets the number of endpoints that have to agree on cross-checked reads.
+    pub fn with_quorum(mut self, quorum: usize) -> Self {
+        self.quorum = quorum;
+        self
+    }
+}
+
+#[async_trait]
+impl<C: JsonRpcClient> JsonRpcClient for FailoverClient<C>
+where
+    C::Error: 'static,
+{
+    type Error = FailoverError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, Self::Error>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        let params = serde_json::to_value(params)?;
+        let cross_check = CROSS_CHECKED.contains(&method);
+        let mut agreed: Option<Value> = None;
+        let mut answers = 0;
+        let mut error = FailoverError::NoEndpoints;
+        for client in &self.clients {
+            match client.request::<_, Value>(method, &params).await {
+                Ok(response) if !cross_check => return Ok(serde_json::from_value(response)?),
+                Ok(response) => match &agreed {
+                    Some(agreed) if *agreed != response => {
+                        return Err(FailoverError::Disagreement(method.to_string()))
+                    }
+                    _ => {
+                        agreed = Some(response);
+                        answers += 1;
+                    }
+                },
+                Err(err) => {
+                    warn!("endpoint failed on {method}: {err}");
+                    error = FailoverError::Provider(err.into());
+                }
+            }
+        }
+        match agreed {
+            Some(response) if answers >= self.quorum => Ok(serde_json::from_value(response)?),
+            Some(_) => Err(FailoverError::NoQuorum {
+                method: method.to_string(),
+                answers,
+                quorum: self.quorum,
+            }),
+            None => Err(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::providers::MockProvider;
+    use ethers::types::U256;
+
+    fn answering(balance: u64) -> MockProvider {
+        let provider = MockProvider::new();
+        provider.push(U256::from(balance)).unwrap();
+        provider
+    }
+
+    async fn balance(client: &FailoverClient<MockProvider>) -> Result<U256, FailoverError> {
+        client.request("eth_getBalance", ()).await
+    }
+
+    #[tokio::test]
+    async fn cross_checked_reads_need_a_quorum() {
+        let client = FailoverClient::new(vec![answering(1), answering(1)]);
+        assert_eq!(balance(&client).await.unwrap(), U256::one());
+
+        // the endpoint without a response fails, which leaves only one answer
+        let client = FailoverClient::new(vec![answering(1), MockProvider::new()]);
+        assert!(matches!(
+            balance(&client).await,
+            Err(FailoverError::NoQuorum { answers: 1, .. })
+        ));
+
+        let client = FailoverClient::new(vec![answering(1), answering(2), answering(1)]);
+        assert!(matches!(
+            balance(&client).await,
+            Err(FailoverError::Disagreement(_))
+        ));
+
+        let client = FailoverClient::new(vec![answering(1), answering(1), MockProvider::new()])
+            .with_quorum(3);
+        assert!(balance(&client).await.is_err());
+        let client = FailoverClient::new(vec![answering(1)]);
+        assert_eq!(balance(&client).await.unwrap(), U256::one());
+    }
+}
@@ -0,0 +1,106 @@
+//! A [`Transport`] over plain directories: outgoing messages are written to an outbox, incoming
+//! ones are read from an inbox. Whatever moves the files between the two machines, be it
+//! syncthing, an email gateway or a USB stick, only ever sees sealed messages.
+//!
+//! Files are moved into the [`READ`] directory of the inbox once received, so every file is
+//! received once no matter in which order or how close together they arrive.
+use crate::transport::Transport;
+use crate::{now_millis, Channel};
+use async_trait::async_trait;
+use ethers::types::Address;
+use ethers::utils::hex;
+use rand::rngs::OsRng;
+use rand::Rng;
+use std::io;
+use std::path::PathBuf;
+use tracing::warn;
+
+const EXTENSION: &str = "ch4nn337";
+/// directory in the inbox received files are moved to
+pub const READ: &str = "read";
+
+pub struct FileDrop {
+    outbox: PathBuf,
+    inbox: PathBuf,
+}
+
+impl FileDrop {
+    pub fn new(outbox: impl Into<PathBuf>, inbox: impl Into<PathBuf>) -> FileDrop {
+        FileDrop {
+            outbox: outbox.into(),
+            inbox: inbox.into(),
+        }
+    }
+}
+
+/// Files are named after the recipient and the time they were written, so one directory can
+/// serve several channels and messages are received in the order they were sent.
+fn file_name(recipient: Address, millis: u64) -> String {
+    let nonce: [u8; 4] = OsRng.gen();
+    format!(
+        "{}-{millis:020}-{}.{EXTENSION}",
+        hex::encode(recipient),
+        hex::encode(nonce)
+    )
+}
+
+fn parse_file_name(name: &str) -> Option<(Address, u64)> {
+    let name = name.strip_suffix(EXTENSION)?.strip_suffix('.')?;
+    let mut parts = name.split('-');
+    let recipient = parts.next()?.parse().ok()?;
+    let millis = parts.next()?.parse().ok()?;
+    Some((recipient, millis))
+}
+
+#[async_trait]
+impl Transport for FileDrop {
+    type Error = io::Error;
+
+    async fn send(&self, channel: &Channel, message: &str) -> Result<(), io::Error> {
+        let sealed = channel
+            .seal(message)
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+        tokio::fs::create_dir_all(&self.outbox).await?;
+        let name = file_name(channel.their_address(), now_millis());
+        // write under a temporary name, so a syncing tool never picks up a partial file
+        let partial = self.outbox.join(format!(".{name}.partial"));
+        tokio::fs::write(&partial, sealed).await?;
+        tokio::fs::rename(partial, self.outbox.join(name)).await
+    }
+
+    /// The cursor is not used, received files are moved out of the inbox instead.
+    async fn receive(
+        &self,
+        channel: &Channel,
+        since: u64,
+    ) -> Result<(Vec<String>, u64), io::Error> {
+        let us = channel
+            .our_address()
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+        let mut files = vec![];
+        let mut entries = tokio::fs::read_dir(&self.inbox).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name();
+            let Some((recipient, millis)) = name.to_str().and_then(parse_file_name) else {
+                continue;
+            };
+            if recipient == us {
+                files.push((millis, name));
+            }
+        }
+        files.sort();
+
+        let read = self.inbox.join(READ);
+        tokio::fs::create_dir_all(&read).await?;
+        let mut messages = vec![];
+        for (_, name) in files {
+            let path = self.inbox.join(&name);
+            match channel.unseal(&tokio::fs::read_to_string(&path).await?) {
+                Ok(message) => messages.push(message),
+                Err(err) => warn!(?path, "skipping dropped message: {err}"),
+            }
+            tokio::fs::rename(&path, read.join(name)).await?;
+        }
+        Ok((messages, since))
+    }
+}
@@ -0,0 +1,146 @@
+//! Transfers that rely on funding not confirmed yet. A new channel is usually paid from right after
+//! party A sent the funds, and waiting for the funding to confirm before even looking at the
+//! transfer adds latency. Instead, the transfer is validated against the balances plus the pending
+//! funding transaction and held until the funding confirms, then released for signing.
+use crate::amount::Wei;
+use crate::chain::ChainClient;
+use crate::operation::bounded;
+use crate::{Channel, Error, Message, Party, Summary};
+use ethers::types::H256;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use thiserror::Error;
+use tracing::{info, instrument, warn};
+
+#[derive(Error, Debug)]
+pub enum FundingError {
+    #[error("funding transaction {0:?} not found")]
+    NotFound(H256),
+    #[error("funding transaction does not pay the channel")]
+    WrongRecipient,
+    #[error("funding only credits party A")]
+    NotCredited,
+    #[error("only incoming transfers can wait for funding")]
+    NotIncoming,
+    #[error("balance and funding do not cover the transfer")]
+    Uncovered,
+}
+
+/// A transfer of the counterparty waiting for their funding to confirm.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FundingHold {
+    /// the funding transaction the counterparty referenced
+    pub transaction: H256,
+    /// the transfer as received
+    pub message: String,
+}
+
+impl Channel {
+    /// Validates an incoming transfer like [`Channel::receive_message`], but counts the value of a
+    /// pending funding transaction of the counterparty towards their balance. The transfer is held
+    /// until [`Channel::release_funded`] finds the funding confirmed, replacing any transfer held
+    /// before, as only one can be signed next.
+    #[instrument(skip_all, fields(channel = ?self.address, funding = ?transaction))]
+    pub async fn hold_for_funding<C: ChainClient + ?Sized>(
+        &mut self,
+        message: &str,
+        transaction: H256,
+        client: Arc<C>,
+    ) -> Result<Summary, Error> {
+        bounded(self.operation.timeout, async {
+            let (_, summary) = self.receive_message(message, client.clone()).await?;
+            let Summary::Transfer {
+                amount,
+                incoming: true,
+                ..
+            } = summary
+            else {
+                return Err(FundingError::NotIncoming.into());
+            };
+            // plain payments to the channel are credited to A
+            if self.us == Party::A {
+                return Err(FundingError::NotCredited.into());
+            }
+
+            let funding = self
+                .retry
+                .run(|| client.transaction(transaction), |_| true)
+                .await?
+                .ok_or(FundingError::NotFound(transaction))?;
+            if funding.to != Some(self.address) {
+                return Err(FundingError::WrongRecipient.into());
+            }
+            let (_, theirs) = self.get_sorted_balances(client).await?;
+            let funding = Wei::try_from(funding.value)?;
+            // counted even if the funding is already included, so the check does not depend on
+            // the block the balances are read at
+            if theirs.checked_add(funding).unwrap_or(Wei(u128::MAX)) < Wei(amount) {
+                return Err(FundingError::Uncovered.into());
+            }
+
+            info!("holding transfer until the funding confirms");
+            self.funding_hold = Some(FundingHold {
+                transaction,
+                message: message.to_string(),
+            });
+            Ok(summary)
+        })
+        .await
+    }
+
+    pub fn funding_hold(&self) -> Option<&FundingHold> {
+        self.funding_hold.as_ref()
+    }
+
+    /// Releases the held transfer once its funding has the configured confirmations and the
+    /// balances cover it, returning it validated anew for [`Channel::sign_message`]. A transfer
+    /// that is no longer valid, or whose funding was dropped or did not cover it, is discarded.
+    #[instrument(skip_all, fields(channel = ?self.address))]
+    pub async fn release_funded<C: ChainClient + ?Sized>(
+        &mut self,
+        client: Arc<C>,
+    ) -> Result<Option<(Message, Summary)>, Error> {
+        bounded(self.operation.timeout, async {
+            let Some(hold) = &self.funding_hold else {
+                return Ok(None);
+            };
+            let funding = self
+                .retry
+                .run(|| client.transaction(hold.transaction), |_| true)
+                .await?;
+            let Some(funding) = funding else {
+                warn!("funding transaction dropped, discarding held transfer");
+                self.funding_hold = None;
+                return Ok(None);
+            };
+            let Some(block) = funding.block_number else {
+                return Ok(None);
+            };
+            let head = self.retry.run(|| client.block_number(), |_| true).await?;
+            let depth = (head + 1).saturating_sub(block);
+            if depth.as_usize() < self.operation.confirmations {
+                return Ok(None);
+            }
+
+            let received = self.receive_message(&hold.message, client.clone()).await;
+            let (message, summary) = match received {
+                Ok(received) => received,
+                Err(err) => {
+                    warn!("discarding held transfer: {err}");
+                    self.funding_hold = None;
+                    return Ok(None);
+                }
+            };
+            let (_, theirs) = self.get_sorted_balances(client).await?;
+            if let Summary::Transfer { amount, .. } = summary {
+                if theirs < amount {
+                    // the funding may still be below the block the balances are read at
+                    return Ok(None);
+                }
+            }
+            self.funding_hold = None;
+            Ok(Some((message, summary)))
+        })
+        .await
+    }
+}
@@ -0,0 +1,80 @@
+//! Accounting of the gas userops of the channel paid from its deposit. The bundler reports the
+//! actual cost of an included userop, which is attributed to the parties according to the
+//! [`GasSplit`] they agreed on and deducted from their balances.
+use crate::{Channel, Party};
+use ethers::types::H256;
+use serde::{Deserialize, Serialize};
+
+/// How the gas of a userop is divided between the parties.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum GasSplit {
+    /// both parties pay half, which is what the contract does on withdrawal
+    #[default]
+    Even,
+    /// the party submitting the userop pays, to be settled with the next transfer as the
+    /// contract still splits evenly
+    Submitter,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GasCharge {
+    /// hash of the userop
+    pub userop: H256,
+    pub transaction: H256,
+    /// actual cost of the userop as reported by the bundler
+    pub cost: u128,
+    pub paid_a: u128,
+    pub paid_b: u128,
+}
+
+impl Channel {
+    pub fn gas_split(&self) -> GasSplit {
+        self.gas_split
+    }
+
+    /// Sets the split agreed on with the counterparty. It applies to gas charged from now on.
+    pub fn set_gas_split(&mut self, split: GasSplit) {
+        self.gas_split = split;
+    }
+
+    pub fn gas_charges(&self) -> &[GasCharge] {
+        &self.gas_charges
+    }
+
+    /// Gas paid by A and B so far.
+    pub fn gas_paid(&self) -> (u128, u128) {
+        self.gas_charges.iter().fold((0, 0), |(a, b), charge| {
+            (a + charge.paid_a, b + charge.paid_b)
+        })
+    }
+
+    /// Records the cost of an included userop, unless it was recorded already.
+    pub(crate) fn record_gas(
+        &mut self,
+        userop: H256,
+        transaction: H256,
+        cost: u128,
+        submitter: Option<Party>,
+    ) {
+        if self
+            .gas_charges
+            .iter()
+            .any(|charge| charge.userop == userop)
+        {
+            return;
+        }
+        let (paid_a, paid_b) = match (self.gas_split, submitter) {
+            (GasSplit::Submitter, Some(Party::A)) => (cost, 0),
+            (GasSplit::Submitter, Some(Party::B)) => (0, cost),
+            // the submitter of withdrawals from before the split was recorded is unknown
+            _ => (cost / 2, cost - cost / 2),
+        };
+        self.gas_charges.push(GasCharge {
+            userop,
+            transaction,
+            cost,
+            paid_a,
+            paid_b,
+        });
+    }
+}
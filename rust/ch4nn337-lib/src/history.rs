@@ -0,0 +1,233 @@
+//! The on-chain history of a channel, read from the `UserOperationEvent`s the entry point emits
+//! for it, for cross-checking against the off-chain history. The channel itself emits no events,
+//! so the call of every userop is decoded from the bundle transaction. Closing a dispute is a
+//! plain call to the channel and does not show up.
+//!
+//! [`Channel::history`] merges both into a single list of [`Entry`]s, e.g. for bookkeeping.
+use crate::amount::SignedWei;
+use crate::chain::{ChainClient, ChainError};
+use crate::operation::bounded;
+use crate::{Channel, Error, Message, Party};
+use ch4nn337_sys::aa_channel::AAChannelCalls;
+use ch4nn337_sys::i_entry_point::{IEntryPointCalls, UserOperationEventFilter};
+use ethers::abi::AbiDecode;
+use ethers::contract::{parse_log, EthEvent};
+use ethers::types::{Address, BlockId, BlockNumber, Filter, H256, U256};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum Action {
+    /// started or updated a dispute
+    Dispute { value_transfer: i128 },
+    Withdrawal {
+        value_transfer: i128,
+        withdraw_a: u128,
+        withdraw_b: u128,
+    },
+    /// any other call, or a userop that could not be found in its transaction
+    Other,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct OnChainEvent {
+    pub block: u64,
+    pub transaction: H256,
+    /// hash of the userop
+    pub userop: H256,
+    pub nonce: U256,
+    /// whether the call of the userop succeeded
+    pub success: bool,
+    pub gas_cost: U256,
+    pub action: Action,
+    /// whether the userop is in our off-chain history
+    pub known: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EntryKind {
+    Transfer,
+    Withdrawal,
+    Dispute,
+}
+
+/// A transfer or settlement, from our point of view.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Entry {
+    /// unix time the message was signed or the userop included, unknown for messages from older
+    /// versions
+    pub time: Option<u64>,
+    pub kind: EntryKind,
+    /// change of our balance for transfers, what we receive for withdrawals, zero for disputes
+    pub amount: i128,
+    pub counterparty: Address,
+    pub memo: Option<String>,
+    /// hash of the userop
+    pub userop: H256,
+    /// the transaction including the userop, if it was included
+    pub transaction: Option<H256>,
+}
+
+impl Channel {
+    /// Our off-chain history merged with the userops included on-chain since the given block,
+    /// oldest first. Userops included on-chain without being in our history are added as well.
+    pub async fn history<C: ChainClient + ?Sized>(
+        &self,
+        from_block: u64,
+        client: Arc<C>,
+    ) -> Result<Vec<Entry>, Error> {
+        let onchain = self.onchain_history(from_block, client.clone()).await?;
+        let included: HashMap<H256, H256> = onchain
+            .iter()
+            .map(|event| (event.userop, event.transaction))
+            .collect();
+
+        let mut entries = vec![];
+        let mut value_transfer = 0;
+        for message in &self.messages {
+            let userop = self.user_op_hash(message.userop());
+            let (kind, amount, memo) = match message {
+                Message::Transfer(transfer) => {
+                    let delta = transfer.value_transfer - value_transfer;
+                    value_transfer = transfer.value_transfer;
+                    let memos: Vec<_> = transfer
+                        .items
+                        .iter()
+                        .filter_map(|item| item.memo.as_deref())
+                        .collect();
+                    let amount = match self.us {
+                        Party::A => -delta,
+                        Party::B => delta,
+                    };
+                    let memo = (!memos.is_empty()).then(|| memos.join("; "));
+                    (EntryKind::Transfer, amount, memo)
+                }
+                Message::Withdrawal(withdrawal) => {
+                    value_transfer = 0;
+                    (
+                        EntryKind::Withdrawal,
+                        SignedWei::try_from(withdrawal.withdraw_us)?.0,
+                        None,
+                    )
+                }
+            };
+            entries.push(Entry {
+                time: message.signed_at(),
+                kind,
+                amount,
+                counterparty: self.counterparty,
+                memo,
+                userop,
+                transaction: included.get(&userop).copied(),
+            });
+        }
+
+        for event in onchain.into_iter().filter(|event| !event.known) {
+            let kind = match event.action {
+                Action::Withdrawal { .. } => EntryKind::Withdrawal,
+                _ => EntryKind::Dispute,
+            };
+            let block = self
+                .retry
+                .run(
+                    || client.block(BlockId::from(BlockNumber::from(event.block))),
+                    |_| true,
+                )
+                .await?;
+            entries.push(Entry {
+                time: block.map(|block| block.timestamp.as_u64()),
+                kind,
+                amount: 0,
+                counterparty: self.counterparty,
+                memo: None,
+                userop: event.userop,
+                transaction: Some(event.transaction),
+            });
+        }
+        entries.sort_by_key(|entry| entry.time.unwrap_or(0));
+        Ok(entries)
+    }
+
+    /// Userops of the channel included since the given block, oldest first.
+    pub async fn onchain_history<C: ChainClient + ?Sized>(
+        &self,
+        from_block: u64,
+        client: Arc<C>,
+    ) -> Result<Vec<OnChainEvent>, Error> {
+        bounded(self.operation.timeout, async {
+            let known: HashSet<H256> = self
+                .messages
+                .iter()
+                .map(|message| self.user_op_hash(message.userop()))
+                .collect();
+            let filter = Filter::new()
+                .address(self.entry_point)
+                .topic0(UserOperationEventFilter::signature())
+                .topic2(H256::from(self.address))
+                .from_block(from_block);
+            let logs = self.retry.run(|| client.logs(&filter), |_| true).await?;
+
+            let mut history = vec![];
+            for log in logs {
+                let (Some(block), Some(transaction)) = (log.block_number, log.transaction_hash)
+                else {
+                    // pending
+                    continue;
+                };
+                let event: UserOperationEventFilter =
+                    parse_log(log).map_err(|err| ChainError::Decode(err.into()))?;
+                let action = self.action(transaction, event.nonce, &client).await?;
+                let userop = H256(event.user_op_hash);
+                history.push(OnChainEvent {
+                    block: block.as_u64(),
+                    transaction,
+                    userop,
+                    nonce: event.nonce,
+                    success: event.success,
+                    gas_cost: event.actual_gas_cost,
+                    action,
+                    known: known.contains(&userop),
+                });
+            }
+            Ok::<_, Error>(history)
+        })
+        .await
+    }
+
+    /// Decodes what the userop with the nonce did from the bundle transaction including it.
+    async fn action<C: ChainClient + ?Sized>(
+        &self,
+        transaction: H256,
+        nonce: U256,
+        client: &Arc<C>,
+    ) -> Result<Action, Error> {
+        let transaction = self
+            .retry
+            .run(|| client.transaction(transaction), |_| true)
+            .await?;
+        let Some(transaction) = transaction else {
+            return Ok(Action::Other);
+        };
+        let Ok(IEntryPointCalls::HandleOps(call)) = IEntryPointCalls::decode(&transaction.input)
+        else {
+            return Ok(Action::Other);
+        };
+        let call = call
+            .ops
+            .iter()
+            .find(|op| op.sender == self.address && op.nonce == nonce)
+            .and_then(|op| AAChannelCalls::decode(&op.call_data).ok());
+        Ok(match call {
+            Some(AAChannelCalls::Dispute(call)) => Action::Dispute {
+                value_transfer: call.value_transfer,
+            },
+            Some(AAChannelCalls::CoopWithdraw(call)) => Action::Withdrawal {
+                value_transfer: call.value_transfer,
+                withdraw_a: call.withdraw_a,
+                withdraw_b: call.withdraw_b,
+            },
+            _ => Action::Other,
+        })
+    }
+}
@@ -0,0 +1,99 @@
+//! Read-only view of a channel from the chain alone, for anyone who knows the channel address but
+//! has neither its key nor its local state, e.g. support, auditors or a counterparty checking a
+//! claim.
+use crate::chain::{self, ChainClient, LATEST};
+use crate::retry::RetryPolicy;
+use crate::Error;
+use ch4nn337_sys::aa_channel::{
+    BalanceACall, BalanceAReturn, BalanceBCall, BalanceBReturn, DisputeStartNonceCall,
+    DisputeStartNonceReturn, DisputeTimestampCall, DisputeTimestampReturn, DisputeValueCall,
+    DisputeValueReturn, NonceCall, NonceReturn, PartyACall, PartyAReturn, PartyBCall, PartyBReturn,
+};
+pub use ch4nn337_sys::channel_address::channel_address;
+use ethers::types::{Address, U256};
+use serde::Serialize;
+use std::sync::Arc;
+
+#[derive(Serialize, Debug)]
+pub struct ChannelReport {
+    pub address: Address,
+    pub deployed: bool,
+    /// ether held by the address itself, which belongs to party A once the channel is deployed
+    pub balance: U256,
+    /// only known once the channel is deployed
+    pub parties: Option<(Address, Address)>,
+    pub balance_a: u128,
+    pub balance_b: u128,
+    /// highest nonce the channel has validated
+    pub nonce: u128,
+    pub dispute: Option<DisputeReport>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct DisputeReport {
+    pub start_nonce: u128,
+    /// value transfer the dispute currently settles on, positive from A to B
+    pub value_transfer: i128,
+    /// unix time at which the dispute can be closed
+    pub timeout: u64,
+}
+
+/// Reads the state of the channel at the address from the chain.
+pub async fn inspect<C: ChainClient + ?Sized>(
+    address: Address,
+    client: Arc<C>,
+) -> Result<ChannelReport, Error> {
+    let retry = RetryPolicy::default();
+    let balance = retry
+        .run(|| client.get_balance(address, LATEST), |_| true)
+        .await?;
+    let code = retry
+        .run(|| client.get_code(address, LATEST), |_| true)
+        .await?;
+    if code.0.is_empty() {
+        return Ok(ChannelReport {
+            address,
+            deployed: false,
+            balance,
+            parties: None,
+            balance_a: 0,
+            balance_b: 0,
+            nonce: 0,
+            dispute: None,
+        });
+    }
+
+    let client = client.as_ref();
+    let PartyAReturn(party_a) = chain::view(client, &retry, address, PartyACall, LATEST).await?;
+    let PartyBReturn(party_b) = chain::view(client, &retry, address, PartyBCall, LATEST).await?;
+    let BalanceAReturn(balance_a) =
+        chain::view(client, &retry, address, BalanceACall, LATEST).await?;
+    let BalanceBReturn(balance_b) =
+        chain::view(client, &retry, address, BalanceBCall, LATEST).await?;
+    let NonceReturn(nonce) = chain::view(client, &retry, address, NonceCall, LATEST).await?;
+    let DisputeTimestampReturn(timeout) =
+        chain::view(client, &retry, address, DisputeTimestampCall, LATEST).await?;
+    let dispute = if timeout == 0 {
+        None
+    } else {
+        let DisputeStartNonceReturn(start_nonce) =
+            chain::view(client, &retry, address, DisputeStartNonceCall, LATEST).await?;
+        let DisputeValueReturn(value_transfer) =
+            chain::view(client, &retry, address, DisputeValueCall, LATEST).await?;
+        Some(DisputeReport {
+            start_nonce,
+            value_transfer,
+            timeout,
+        })
+    };
+    Ok(ChannelReport {
+        address,
+        deployed: true,
+        balance,
+        parties: Some((party_a, party_b)),
+        balance_a,
+        balance_b,
+        nonce,
+        dispute,
+    })
+}
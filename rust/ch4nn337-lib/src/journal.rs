@@ -0,0 +1,88 @@
+//! Write-ahead journal of operations with effects outside the channel. An [`Intent`] is recorded
+//! before such an operation starts and removed once the channel is saved afterwards, so an intent
+//! found on start belongs to an operation that was interrupted by a crash. [`Channel::resume`]
+//! then finishes it or rolls it back.
+use crate::chain::ChainClient;
+use crate::operation::bounded;
+use crate::protocol::OutgoingMessage;
+use crate::store::StoreError;
+use crate::{Channel, ChannelState, Error, Message, WithdrawalStatus};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::{info, instrument};
+
+#[derive(Serialize, Deserialize)]
+pub enum Intent {
+    /// about to sign a withdrawal of the counterparty and hand it to the bundler
+    SignWithdrawal { message: Message },
+}
+
+/// How an interrupted operation was dealt with.
+pub enum Recovery {
+    /// the saved channel shows the operation as done
+    Completed,
+    /// the operation was finished, the message has to be handed to the counterparty
+    Resumed(OutgoingMessage),
+    /// nothing of the operation left this machine, so it can simply be started again
+    RolledBack,
+}
+
+/// Holds at most one intent per channel.
+#[async_trait]
+pub trait Journal {
+    /// Records an intent, replacing any earlier one of the channel.
+    async fn begin(&self, name: &str, intent: &Intent) -> Result<(), StoreError>;
+
+    /// Removes the intent of the channel, if any.
+    async fn complete(&self, name: &str) -> Result<(), StoreError>;
+
+    /// Intents of interrupted operations, with the names of their channels.
+    async fn pending(&self) -> Result<Vec<(String, Intent)>, StoreError>;
+}
+
+impl Channel {
+    /// Finishes or rolls back an operation that was interrupted, on the channel as it was saved
+    /// last. The channel has to be saved afterwards before the intent is completed.
+    #[instrument(skip_all, fields(channel = ?self.address))]
+    pub async fn resume<C: ChainClient + ?Sized>(
+        &mut self,
+        intent: Intent,
+        client: Arc<C>,
+    ) -> Result<Recovery, Error> {
+        bounded(self.operation.timeout, async {
+            let Intent::SignWithdrawal { message } = intent;
+            let hash = self.user_op_hash(message.userop());
+            if self.processed_messages.contains(&hash) {
+                let submitted = self.withdrawal.as_ref().is_some_and(|withdrawal| {
+                    withdrawal.hash == hash && withdrawal.status == WithdrawalStatus::Submitted
+                });
+                if self.state != ChannelState::PendingWithdrawal || !submitted {
+                    return Ok(Recovery::Completed);
+                }
+                // saved, but maybe not submitted
+                return Ok(Recovery::Resumed(self.resubmit_withdrawal(client).await?));
+            }
+
+            let known = self
+                .retry
+                .run(|| client.user_operation_known(hash), |_| true)
+                .await?;
+            if !known {
+                info!("rolling back unsubmitted withdrawal");
+                return Ok(Recovery::RolledBack);
+            }
+            self.check_signer(false)?;
+            // the bundler got it, so it was checked and signed before, and signing again yields
+            // the same signature
+            info!("recording withdrawal submitted before the interruption");
+            let signature = self.sign(message.userop()).await?;
+            let userop = self.merge_signature(&message, signature)?;
+            let message = self
+                .record_countersigned(message, userop, None, client)
+                .await?;
+            Ok(Recovery::Resumed(message))
+        })
+        .await
+    }
+}
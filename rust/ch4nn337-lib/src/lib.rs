@@ -1,23 +1,112 @@
+use crate::amount::{AmountError, SignedWei, Wei};
+use crate::audit::AuditError;
+use crate::autosign::Rejection;
+use crate::chain::{BlockPolicy, ChainClient, ChainError, LATEST};
+use crate::contracts::CodeError;
+use crate::entropy::{CryptoRng, OsRng, RngCore};
+use crate::funding::{FundingError, FundingHold};
+use crate::gas::{GasCharge, GasSplit};
+use crate::offline::{KnownState, OfflineError};
+use crate::operation::{bounded, OperationConfig};
+use crate::policy::{Policy, Violation};
+use crate::protocol::OutgoingMessage;
+use crate::receipt::ReceiptError;
+use crate::retry::RetryPolicy;
+use crate::revert::Revert;
+use crate::secret::KeyBytes;
+use crate::snapshot::{AccountState, SnapshotError};
+use crate::store::StoreError;
+use crate::sync::SyncError;
+use crate::validation::{ConstantError, SignatureError, ValidationPolicy};
+use crate::verify::ProofError;
 use crate::Error::*;
-use ch4nn337_sys::aa_channel::{AAChannel, AAChannelCalls, CoopWithdrawCall, DisputeCall};
-use ch4nn337_sys::aa_channel_factory::{AAChannelFactory, CreateAccountCall};
-use ethers::abi;
-use ethers::abi::{AbiDecode, AbiEncode, Tokenizable};
-use ethers::contract::ContractError;
+use ch4nn337_sys::aa_channel::{
+    AAChannelCalls, BalanceACall, BalanceAReturn, BalanceBCall, BalanceBReturn, CoopWithdrawCall,
+    DisputeCall, DisputeStartNonceCall, DisputeStartNonceReturn, DisputeTimestampCall,
+    DisputeTimestampReturn, DisputeValueCall, DisputeValueReturn,
+};
+use ch4nn337_sys::aa_channel_factory::{
+    AAChannelFactory, GetAddressCall, GetAddressReturn, AACHANNELFACTORY_ABI,
+};
+use ch4nn337_sys::artifacts::AACHANNELFACTORY_BYTECODE;
+use ch4nn337_sys::channel_address::channel_address;
+use ch4nn337_sys::i_entry_point::{
+    FailedOp, IEntryPointErrors, SimulateValidationCall, UserOperation,
+};
+use ch4nn337_sys::signature::{encode_pair, verify_pair, verify_signer};
+use ethers::abi::{AbiDecode, AbiEncode};
+use ethers::contract::{ContractError, ContractFactory, EthError};
 use ethers::core::k256::ecdsa;
 use ethers::core::k256::ecdsa::{signature, RecoveryId, SigningKey, VerifyingKey};
-use ethers::providers::Middleware;
-use ethers::signers::{Signer, Wallet};
+use ethers::providers::{Middleware, ProviderError};
+use ethers::signers::{Signer, Wallet, WalletError};
 use ethers::types::userop::UserOp;
-use ethers::types::{Address, Bytes, Signature, U256};
+use ethers::types::{Address, BlockId, Bytes, H256, U256};
 use ethers::utils::keccak256;
-use rand::rngs::OsRng;
 use rand::Rng;
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::convert::Into;
 use std::num::NonZeroU128;
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
 use thiserror::Error;
+use tracing::{debug, field, info, instrument, warn, Span};
+
+pub mod advert;
+pub mod amount;
+pub mod audit;
+pub mod autosign;
+pub mod backup;
+pub mod ceremony;
+pub mod chain;
+pub mod cold;
+pub mod contacts;
+pub mod contracts;
+pub mod deposit;
+pub mod dispute;
+pub mod entropy;
+pub mod envelope;
+pub mod export;
+pub mod failover;
+pub mod filedrop;
+pub mod funding;
+pub mod gas;
+pub mod history;
+pub mod inspect;
+pub mod journal;
+#[cfg(feature = "mock")]
+pub mod mock;
+#[cfg(feature = "nostr")]
+pub mod nostr;
+pub mod offline;
+pub mod operation;
+pub mod policy;
+pub mod preview;
+#[cfg(feature = "price")]
+pub mod price;
+pub mod proto;
+pub mod protocol;
+pub mod qr;
+pub mod receipt;
+pub mod recover;
+pub mod relay;
+pub mod retry;
+pub mod revert;
+pub mod schema;
+mod secret;
+pub mod shared;
+pub mod snapshot;
+pub mod store;
+pub mod stream;
+pub mod sync;
+pub mod test_vectors;
+pub mod transport;
+mod userop;
+pub mod validation;
+pub mod verify;
+#[cfg(feature = "ws")]
+pub mod watch;
 
 const CALL_GAS_LIMIT_DISPUTE: u64 = 200000;
 const CALL_GAS_LIMIT_COOP: u64 = 200000;
@@ -25,13 +114,22 @@ const VERIFICATION_GAS_LIMIT: u64 = 1500000;
 const PRE_VERIFICATION_GAS: u64 = 200000;
 const MAX_FEE_PER_GAS: u128 = 100_000_000;
 const PRIORITY_FEE: u64 = 100_000_000; // 0.1 gwei
+const FEE_BUMP_PERCENT: u64 = 25; // bundlers require at least 10% to replace an op
+
+/// Errors of the client are boxed, so the error type does not depend on the middleware.
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
 
 #[derive(Error, Debug)]
-pub enum Error<M: Middleware> {
-    #[error("{0}")]
-    MiddlewareError(M::Error),
+pub enum Error {
+    #[error(transparent)]
+    MiddlewareError(BoxError),
+    #[error(transparent)]
+    ContractError(BoxError),
+    /// the channel contract reverted for a known reason
+    #[error("reverted: {0}")]
+    Reverted(Revert),
     #[error("{0}")]
-    ContractError(#[from] ContractError<M>),
+    ProviderError(#[from] ProviderError),
     #[error("insufficient balance")]
     InsufficientBalance,
     #[error("already awaiting a signature")]
@@ -44,14 +142,111 @@ pub enum Error<M: Middleware> {
     IllegalNonce,
     #[error("illegal initcode")]
     IllegalInitcode,
-    #[error("illegal constant")]
-    IllegalConstant,
+    #[error("illegal constant: {0}")]
+    IllegalConstant(#[from] ConstantError),
     #[error("illegal calldata")]
     IllegalCalldata,
     #[error("illegal value transfer")]
     IllegalValueTransfer,
-    #[error("illegal signature")]
-    IllegalSignature,
+    #[error("illegal signature: {0}")]
+    IllegalSignature(#[from] SignatureError),
+    #[error("duplicate message")]
+    DuplicateMessage,
+    #[error("simulation failed: {0}")]
+    SimulationFailed(String),
+    #[error("not awaiting a response")]
+    NotWaiting,
+    #[error("illegal response")]
+    IllegalResponse,
+    #[error("policy violation: {0}")]
+    PolicyViolation(#[from] Violation),
+    #[error("rejected: {0}")]
+    Rejected(#[from] Rejection),
+    #[error("verification failed: {0}")]
+    ProofError(#[from] ProofError),
+    #[error("no withdrawal awaiting inclusion")]
+    NoPendingWithdrawal,
+    #[error("channel is closed")]
+    ChannelClosed,
+    #[error("not allowed while the channel is {0:?}")]
+    InvalidState(ChannelState),
+    #[error("empty batch")]
+    EmptyBatch,
+    #[error("channel is watch-only, sign offline")]
+    WatchOnly,
+    #[error("channel is a read-only observer")]
+    ReadOnly,
+    #[error("watch-only channel does not know our address")]
+    MissingAddress,
+    #[error("signing failed: {0}")]
+    Signing(WalletError),
+    #[error("signing request does not match the channel")]
+    IllegalSigningRequest,
+    #[error("unexpected contract: {0}")]
+    CodeError(#[from] CodeError),
+    #[error("{0}")]
+    Store(#[from] StoreError),
+    #[error("operation timed out")]
+    Timeout,
+    #[error("{0}")]
+    Receipt(#[from] ReceiptError),
+    #[error("no channel with the counterparty found on-chain")]
+    ChannelNotFound,
+    #[error("illegal channel proposal or acceptance")]
+    IllegalProposal,
+    #[error("key backup failed: {0}")]
+    Backup(#[from] WalletError),
+    #[error("channel still holds funds")]
+    FundsLeft,
+    #[error("dispute is open")]
+    DisputeOpen,
+    #[error("no transfer to dispute with")]
+    NothingToDispute,
+    #[error("unsupported protocol version {0}, the counterparty has to downgrade or we upgrade")]
+    UnsupportedVersion(u32),
+    #[error("{0}")]
+    Amount(#[from] AmountError),
+    #[error("operation {0} was cancelled or used for a different request")]
+    OperationReused(String),
+    #[error("{0}")]
+    Funding(#[from] FundingError),
+    #[error("audit failed: {0}")]
+    Audit(#[from] AuditError),
+    #[error("{0}")]
+    Sync(#[from] SyncError),
+    #[error("{0}")]
+    Snapshot(#[from] SnapshotError),
+    #[error("{0}")]
+    Offline(#[from] OfflineError),
+}
+
+impl<M: Middleware + 'static> From<ContractError<M>> for Error {
+    fn from(err: ContractError<M>) -> Self {
+        match err
+            .decode_revert::<String>()
+            .as_deref()
+            .and_then(Revert::from_reason)
+        {
+            Some(revert) => Reverted(revert),
+            None => ContractError(Box::new(err)),
+        }
+    }
+}
+
+impl From<ChainError> for Error {
+    fn from(err: ChainError) -> Self {
+        match err {
+            ChainError::Revert(ref data) => match String::decode_with_selector(data)
+                .as_deref()
+                .and_then(Revert::from_reason)
+            {
+                Some(revert) => Reverted(revert),
+                None => ContractError(Box::new(err)),
+            },
+            ChainError::Client(err) => MiddlewareError(err),
+            err => ContractError(Box::new(err)),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Eq, PartialEq, Copy, Clone)]
@@ -60,171 +255,486 @@ pub enum Party {
     B,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct TransferMessage {
     userop: UserOp,
     value_transfer: i128,
+    /// the payments netted into this transfer, only known for transfers we requested
+    #[serde(default)]
+    items: Vec<TransferItem>,
+    /// unix time the message was fully signed, unknown for messages from older versions
+    #[serde(default)]
+    signed_at: Option<u64>,
 }
 
-#[derive(Serialize, Deserialize)]
+/// A single payment within a transfer.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TransferItem {
+    pub amount: Wei,
+    pub memo: Option<String>,
+}
+
+impl TransferMessage {
+    pub fn items(&self) -> &[TransferItem] {
+        &self.items
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct WithdrawalMessage {
     userop: UserOp,
-    withdraw_us: u128,
-    withdraw_them: u128,
+    withdraw_us: Wei,
+    withdraw_them: Wei,
+    /// unix time the message was fully signed, unknown for messages from older versions
+    #[serde(default)]
+    signed_at: Option<u64>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub enum Message {
     Transfer(TransferMessage),
     Withdrawal(WithdrawalMessage),
 }
 
+impl Message {
+    pub fn userop(&self) -> &UserOp {
+        match self {
+            Message::Transfer(message) => &message.userop,
+            Message::Withdrawal(message) => &message.userop,
+        }
+    }
+
+    pub fn signed_at(&self) -> Option<u64> {
+        match self {
+            Message::Transfer(message) => message.signed_at,
+            Message::Withdrawal(message) => message.signed_at,
+        }
+    }
+
+    fn userop_mut(&mut self) -> &mut UserOp {
+        match self {
+            Message::Transfer(message) => &mut message.userop,
+            Message::Withdrawal(message) => &mut message.userop,
+        }
+    }
+}
+
+/// What signing an incoming message would mean for us, for presenting it to the user.
+pub enum Summary {
+    Transfer {
+        amount: Wei,
+        /// whether the amount is transferred to us
+        incoming: bool,
+        our_balance: Wei,
+        their_balance: Wei,
+    },
+    Withdrawal {
+        withdraw_us: Wei,
+        withdraw_them: Wei,
+        /// what we and they receive after fees, `None` if the fees exceed the withdrawal
+        payout: Option<(Wei, Wei)>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum ChannelState {
+    /// the counterparty has not accepted the channel yet
+    Proposed,
+    #[default]
+    Open,
+    /// a withdrawal was submitted and waits for inclusion
+    PendingWithdrawal,
+    /// a dispute is ongoing on-chain, so the channel can no longer be updated off-chain
+    Disputed,
+    /// the channel was fully withdrawn on-chain
+    Closed,
+}
+
+/// Progress of a cooperative withdrawal handed to the bundler.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WithdrawalStatus {
+    Submitted,
+    Included {
+        transaction: H256,
+    },
+    /// included, but the withdrawal itself reverted
+    Failed {
+        transaction: H256,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+struct SubmittedWithdrawal {
+    hash: H256,
+    status: WithdrawalStatus,
+    /// unknown for withdrawals submitted before it was recorded
+    #[serde(default)]
+    submitter: Option<Party>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct DisputeInfo {
     pub nonce: u128,
     pub timeout: u64,
-    pub withdrawal_ours: i128,
-    pub withdrawal_theirs: i128,
+    pub withdrawal_ours: SignedWei,
+    pub withdrawal_theirs: SignedWei,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct Channel {
+    /// format version, see [`schema`]
+    #[serde(default, deserialize_with = "schema::supported_version")]
+    version: u32,
+    /// message protocol version agreed on with the counterparty, see [`protocol`]
+    #[serde(default)]
+    protocol: u32,
     chain_id: U256,
     entry_point: Address,
     factory: Address,
     address: Address,
     us: Party,
-    key: Vec<u8>,
+    /// empty for watch-only channels
+    key: KeyBytes,
+    /// our address, for watch-only channels which can not derive it from the key
+    #[serde(default)]
+    address_us: Option<Address>,
+    /// a read-only copy, which can not sign even with an offline key
+    #[serde(default)]
+    observer: bool,
     counterparty: Address,
+    /// SEC1 encoded public key of the counterparty, for encrypting messages to them
+    #[serde(default)]
+    counterparty_key: Option<Vec<u8>>,
     salt: U256,
     messages: Vec<Message>,
     pending_message: Option<Message>,
+    /// a message from the counterparty waiting for our offline signature
+    #[serde(default)]
+    unsigned_message: Option<Message>,
+    /// id of the operation that created our latest request, with the hash of its userop
+    #[serde(default)]
+    last_operation: Option<(String, H256)>,
+    /// a transfer of the counterparty waiting for their funding to confirm
+    #[serde(default)]
+    funding_hold: Option<FundingHold>,
+    #[serde(default)]
+    processed_messages: HashSet<H256>,
+    #[serde(default)]
+    policy: Policy,
+    #[serde(default)]
+    validation: ValidationPolicy,
+    /// block the balances and disputes are read at
+    #[serde(default)]
+    block_policy: BlockPolicy,
+    #[serde(default)]
+    withdrawal: Option<SubmittedWithdrawal>,
+    #[serde(default)]
+    state: ChannelState,
+    /// last nonce seen on-chain, for channels recovered without their history
+    #[serde(default)]
+    recovered_nonce: Option<U256>,
+    #[serde(default)]
+    gas_split: GasSplit,
+    /// gas paid from the deposit, deducted from the balances
+    #[serde(default)]
+    gas_charges: Vec<GasCharge>,
+    /// the account as last read, for requesting transfers offline, see [`offline`], and for
+    /// reuse by reads within `cache_ttl`
+    #[serde(default)]
+    known_state: Option<KnownState>,
+    /// seconds reads reuse `known_state` for, zero to always read the chain
+    #[serde(default)]
+    cache_ttl: u64,
+    /// whether the pending message was requested offline and not checked on-chain since
+    #[serde(default)]
+    unreconciled: bool,
+    #[serde(skip)]
+    retry: RetryPolicy,
+    #[serde(skip)]
+    operation: OperationConfig,
+    #[serde(skip)]
+    trusted_block: Option<H256>,
+    /// signer built from `key` on first use, `None` inside for watch-only channels
+    #[serde(skip)]
+    signer: OnceLock<Option<Wallet<SigningKey>>>,
+}
+
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("time went backwards")
+        .as_secs()
+}
+
+pub(crate) fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("time went backwards")
+        .as_millis() as u64
+}
+
+/// Deploys a channel factory for the entry point, which deploys the AAChannel implementation its
+/// channels are proxies of. The client has to be able to send transactions. The bytecode is the
+/// one of [`artifacts`](ch4nn337_sys::artifacts), compiled from source with the `solc` feature.
+#[instrument(skip_all, fields(entry_point = ?entry_point))]
+pub async fn deploy_factory<M: Middleware + 'static>(
+    entry_point: Address,
+    client: Arc<M>,
+) -> Result<Address, Error> {
+    let factory = ContractFactory::new(
+        AACHANNELFACTORY_ABI.clone(),
+        AACHANNELFACTORY_BYTECODE.clone(),
+        client,
+    )
+    .deploy(entry_point)?
+    .send()
+    .await?;
+    info!(address = ?factory.address(), "deployed channel factory");
+    Ok(factory.address())
 }
 
 impl Channel {
-    pub async fn open<M: Middleware>(
+    pub async fn open<C: ChainClient + ?Sized>(
+        chain_id: U256,
+        entry_point: Address,
+        factory: Address,
+        client: Arc<C>,
+    ) -> Result<(Channel, Channel), Error> {
+        Channel::open_with_rng(chain_id, entry_point, factory, &mut OsRng, client).await
+    }
+
+    /// Like [`Channel::open`], drawing the keys and the salt from the given generator.
+    #[instrument(skip_all)]
+    pub async fn open_with_rng<C: ChainClient + ?Sized, R: RngCore + CryptoRng>(
         chain_id: U256,
         entry_point: Address,
         factory: Address,
-        client: Arc<M>,
-    ) -> Result<(Channel, Channel), ContractError<M>> {
-        let key_a = SigningKey::random(&mut OsRng);
-        let key_b = SigningKey::random(&mut OsRng);
+        rng: &mut R,
+        client: Arc<C>,
+    ) -> Result<(Channel, Channel), Error> {
+        let key_a = SigningKey::random(&mut *rng);
+        let key_b = SigningKey::random(&mut *rng);
         let wallet_a = Wallet::from(key_a.clone());
         let wallet_b = Wallet::from(key_b.clone());
-        let salt = OsRng.gen::<[u8; 32]>().into();
+        let salt = rng.gen::<[u8; 32]>().into();
         let address_a = wallet_a.address();
         let address_b = wallet_b.address();
 
-        let address = AAChannelFactory::new(factory, client.clone())
-            .get_address(address_a, address_b, salt)
-            .call()
-            .await?
-            .0
-            .into();
-
-        Ok((
-            Channel {
-                chain_id,
-                entry_point,
-                factory,
-                address,
-                us: Party::A,
-                key: key_a.to_bytes().as_slice().to_vec(),
-                counterparty: address_b,
-                salt,
-                messages: vec![],
-                pending_message: None,
-            },
-            Channel {
-                chain_id,
-                entry_point,
-                factory,
-                address,
-                us: Party::B,
-                key: key_b.to_bytes().as_slice().to_vec(),
-                counterparty: address_a,
-                salt,
-                messages: vec![],
-                pending_message: None,
-            },
-        ))
+        let address = channel_address(factory, address_a, address_b, salt);
+        // the factory only serves as a sanity check, so channels can be opened offline
+        let call = GetAddressCall {
+            party_a: address_a,
+            party_b: address_b,
+            salt,
+        };
+        match chain::view(
+            client.as_ref(),
+            &RetryPolicy::default(),
+            factory,
+            call,
+            LATEST,
+        )
+        .await
+        {
+            Ok(GetAddressReturn(expected)) if expected != address => {
+                return Err(CodeError::Factory(factory).into())
+            }
+            Ok(_) => {}
+            Err(err) if err.is_revert() => return Err(err.into()),
+            Err(err) => warn!("unable to check the channel address with the factory: {err}"),
+        }
+
+        let mut a = Channel::new(
+            chain_id,
+            entry_point,
+            factory,
+            Party::A,
+            &key_a,
+            address_b,
+            salt,
+        );
+        a.counterparty_key = Some(key_b.verifying_key().to_sec1_bytes().to_vec());
+        let mut b = Channel::new(
+            chain_id,
+            entry_point,
+            factory,
+            Party::B,
+            &key_b,
+            address_a,
+            salt,
+        );
+        b.counterparty_key = Some(key_a.verifying_key().to_sec1_bytes().to_vec());
+        Ok((a, b))
+    }
+
+    /// An open channel without history, with the address derived from the parties.
+    pub(crate) fn new(
+        chain_id: U256,
+        entry_point: Address,
+        factory: Address,
+        us: Party,
+        key: &SigningKey,
+        counterparty: Address,
+        salt: U256,
+    ) -> Channel {
+        let address_us = Wallet::from(key.clone()).address();
+        let (party_a, party_b) = match us {
+            Party::A => (address_us, counterparty),
+            Party::B => (counterparty, address_us),
+        };
+        Channel {
+            version: schema::CURRENT_VERSION,
+            protocol: protocol::PROTOCOL_VERSION,
+            chain_id,
+            entry_point,
+            factory,
+            address: channel_address(factory, party_a, party_b, salt),
+            us,
+            key: KeyBytes::from(key),
+            address_us: Some(address_us),
+            observer: false,
+            counterparty,
+            counterparty_key: None,
+            salt,
+            messages: vec![],
+            pending_message: None,
+            unsigned_message: None,
+            last_operation: None,
+            funding_hold: None,
+            processed_messages: HashSet::new(),
+            policy: Policy::default(),
+            validation: ValidationPolicy::default(),
+            block_policy: BlockPolicy::default(),
+            withdrawal: None,
+            state: ChannelState::Open,
+            recovered_nonce: None,
+            gas_split: GasSplit::default(),
+            gas_charges: vec![],
+            known_state: None,
+            cache_ttl: 0,
+            unreconciled: false,
+            retry: RetryPolicy::default(),
+            operation: OperationConfig::default(),
+            trusted_block: None,
+            signer: OnceLock::new(),
+        }
     }
 
     pub fn address(&self) -> Address {
         self.address
     }
 
-    pub fn our_address(&self) -> Address {
-        self.wallet().address()
+    pub fn our_address(&self) -> Result<Address, Error> {
+        self.address_us
+            .or_else(|| self.wallet().map(Signer::address))
+            .ok_or(MissingAddress)
     }
 
     pub fn their_address(&self) -> Address {
         self.counterparty
     }
 
-    fn init_code(&self) -> Bytes {
-        let (party_a, party_b) = self.parties();
-        self.factory
-            .to_fixed_bytes()
-            .into_iter()
-            .chain(AbiEncode::encode(CreateAccountCall {
-                party_a,
-                party_b,
-                salt: self.salt,
-            }))
-            .collect()
+    fn init_code(&self) -> Result<Bytes, Error> {
+        let (party_a, party_b) = self.parties()?;
+        Ok(proto::init_code(self.factory, party_a, party_b, self.salt))
     }
 
-    fn key(&self) -> SigningKey {
-        SigningKey::from_slice(&self.key).expect("pls")
+    /// Whether the channel holds no key and its userops are signed offline, see [`cold`].
+    pub fn is_watch_only(&self) -> bool {
+        self.key.is_empty()
     }
 
-    fn wallet(&self) -> Wallet<SigningKey> {
-        Wallet::from(self.key())
+    /// Our signer, `None` for watch-only channels.
+    fn wallet(&self) -> Option<&Wallet<SigningKey>> {
+        self.signer
+            .get_or_init(|| self.key.signing_key().map(Wallet::from))
+            .as_ref()
     }
 
-    fn parties(&self) -> (Address, Address) {
-        let us = self.wallet().address();
-        match self.us {
+    fn parties(&self) -> Result<(Address, Address), Error> {
+        let us = self.our_address()?;
+        Ok(match self.us {
             Party::A => (us, self.counterparty),
             Party::B => (self.counterparty, us),
-        }
+        })
     }
 
-    pub async fn get_balances<M: Middleware>(
+    #[instrument(skip_all, fields(channel = ?self.address))]
+    pub async fn get_balances<C: ChainClient + ?Sized>(
         &self,
-        client: Arc<M>,
-    ) -> Result<(u128, u128), Error<M>> {
-        let mut balance_a;
-        let mut balance_b;
-        if self.is_deployed(&client).await.map_err(MiddlewareError)? {
-            let channel = AAChannel::new(self.address, client.clone());
-            balance_a = channel.balance_a().call().await?;
-            balance_b = channel.balance_b().call().await?;
-        } else {
-            balance_a = client
-                .get_balance(self.address, None)
-                .await
-                .map_err(MiddlewareError)?
-                .low_u128();
-            balance_b = 0;
-        }
-        let value_transfer = self.get_value_transfer();
-        balance_a = (balance_a as i128 - value_transfer) as u128;
-        balance_b += (balance_b as i128 - value_transfer) as u128;
-        Ok((balance_a, balance_b))
+        client: Arc<C>,
+    ) -> Result<(Wei, Wei), Error> {
+        bounded(self.operation.timeout, async {
+            let (balance_a, balance_b): (Wei, Wei) = if let Some(block) = self.trusted_block {
+                let account =
+                    verify::verified_account(client.as_ref(), &self.retry, self.address, block)
+                        .await?;
+                account_balances(&account)?
+            } else if let Some(account) = self.cached_account() {
+                account_balances(account)?
+            } else {
+                let block = self
+                    .block_policy
+                    .resolve(client.as_ref(), &self.retry)
+                    .await?;
+                if self.deployed_at(&client, block).await? {
+                    let BalanceAReturn(a) = self.view(&client, BalanceACall, block).await?;
+                    let BalanceBReturn(b) = self.view(&client, BalanceBCall, block).await?;
+                    (a.into(), b.into())
+                } else {
+                    let balance = self
+                        .retry
+                        .run(|| client.get_balance(self.address, block), |_| true)
+                        .await?;
+                    (balance.try_into()?, Wei::ZERO)
+                }
+            };
+            Ok::<_, Error>(self.adjusted_balances(balance_a, balance_b))
+        })
+        .await
     }
 
-    pub async fn get_sorted_balances<M: Middleware>(
+    pub async fn get_sorted_balances<C: ChainClient + ?Sized>(
         &self,
-        client: Arc<M>,
-    ) -> Result<(u128, u128), Error<M>> {
-        let (balance_a, balance_b) = self.get_balances(client).await?;
-        Ok(if self.us == Party::A {
-            (balance_a, balance_b)
-        } else {
-            (balance_b, balance_a)
-        })
+        client: Arc<C>,
+    ) -> Result<(Wei, Wei), Error> {
+        let balances = self.get_balances(client).await?;
+        Ok(self.sorted(balances))
+    }
+
+    /// Our and their balance in an account read with [`snapshot::read`], like
+    /// [`Channel::get_sorted_balances`] returns them.
+    pub fn balances_at(&self, account: &AccountState) -> Result<(Wei, Wei), Error> {
+        let (balance_a, balance_b) = account_balances(account)?;
+        Ok(self.sorted(self.adjusted_balances(balance_a, balance_b)))
+    }
+
+    /// The on-chain balances of A and B minus what was transferred and paid for gas since.
+    fn adjusted_balances(&self, mut balance_a: Wei, mut balance_b: Wei) -> (Wei, Wei) {
+        // positive value transfers flow from A to B
+        let value_transfer = SignedWei(self.get_value_transfer());
+        balance_a = balance_a.saturating_add_signed(value_transfer.saturating_neg());
+        balance_b = balance_b.saturating_add_signed(value_transfer);
+        let (gas_a, gas_b) = self.gas_paid();
+        balance_a = balance_a.saturating_sub(Wei(gas_a));
+        balance_b = balance_b.saturating_sub(Wei(gas_b));
+        (balance_a, balance_b)
+    }
+
+    fn sorted<T>(&self, (a, b): (T, T)) -> (T, T) {
+        match self.us {
+            Party::A => (a, b),
+            Party::B => (b, a),
+        }
+    }
+
+    /// How much our balance changes if the value transfer is updated to the given value.
+    fn our_delta(&self, value_transfer: i128) -> i128 {
+        let delta = value_transfer - self.get_value_transfer();
+        match self.us {
+            Party::A => -delta,
+            Party::B => delta,
+        }
     }
 
     fn get_value_transfer(&self) -> i128 {
@@ -234,20 +744,122 @@ impl Channel {
         })
     }
 
-    pub async fn is_deployed<M: Middleware>(&self, client: &Arc<M>) -> Result<bool, M::Error> {
-        client
-            .get_code(self.address, None)
-            .await
-            .map(|code| !code.0.is_empty())
+    /// Deploys the channel contract through the factory. The client has to be able to sign and
+    /// pay for the transaction.
+    #[instrument(skip_all, fields(channel = ?self.address))]
+    pub async fn deploy<M: Middleware + 'static>(&self, client: Arc<M>) -> Result<(), Error> {
+        bounded(self.operation.timeout, async {
+            let (party_a, party_b) = self.parties()?;
+            let call = AAChannelFactory::new(self.factory, client)
+                .create_account(party_a, party_b, self.salt);
+            call.send()
+                .await?
+                .interval(self.operation.poll_interval)
+                .confirmations(self.operation.confirmations)
+                .await?;
+            Ok::<_, Error>(())
+        })
+        .await
+    }
+
+    pub async fn is_deployed<C: ChainClient + ?Sized>(
+        &self,
+        client: &Arc<C>,
+    ) -> Result<bool, Error> {
+        // a channel can not be undeployed, so only that is taken from the cache
+        if self
+            .cached_account()
+            .is_some_and(|account| account.deployed)
+        {
+            return Ok(true);
+        }
+        self.deployed_at(client, LATEST).await
+    }
+
+    async fn deployed_at<C: ChainClient + ?Sized>(
+        &self,
+        client: &Arc<C>,
+        block: BlockId,
+    ) -> Result<bool, Error> {
+        let code = self
+            .retry
+            .run(|| client.get_code(self.address, block), |_| true)
+            .await?;
+        Ok(!code.0.is_empty())
+    }
+
+    /// Calls a view function of the channel contract, retrying failures that are not reverts.
+    async fn view<C: ChainClient + ?Sized, R: AbiDecode>(
+        &self,
+        client: &Arc<C>,
+        call: impl AbiEncode,
+        block: BlockId,
+    ) -> Result<R, Error> {
+        Ok(chain::view(client.as_ref(), &self.retry, self.address, call, block).await?)
+    }
+
+    pub fn set_retry_policy(&mut self, retry: RetryPolicy) {
+        self.retry = retry;
+    }
+
+    pub fn set_operation_config(&mut self, operation: OperationConfig) {
+        self.operation = operation;
+    }
+
+    pub fn block_policy(&self) -> BlockPolicy {
+        self.block_policy
+    }
+
+    /// Reads balances and disputes at an older block, so validating messages does not rely on
+    /// state a reorg may undo. Storage proofs against a trusted block take precedence.
+    pub fn set_block_policy(&mut self, block_policy: BlockPolicy) {
+        self.block_policy = block_policy;
+    }
+
+    /// Lets reads reuse the account as last read with [`Channel::refresh`] for this many seconds
+    /// instead of reading the chain, zero to turn it off. Channels reading against a trusted
+    /// block always read the chain.
+    pub fn set_cache_ttl(&mut self, seconds: u64) {
+        self.cache_ttl = seconds;
+    }
+
+    pub fn cache_ttl(&self) -> u64 {
+        self.cache_ttl
+    }
+
+    /// Whether reads currently reuse the account as last read instead of reading the chain.
+    pub fn is_cached(&self) -> bool {
+        self.cached_account().is_some()
+    }
+
+    /// Forgets the account as last read, e.g. after the channel was settled or funded.
+    pub fn invalidate_cache(&mut self) {
+        self.known_state = None;
+    }
+
+    /// The account as last read, if reads may still reuse it.
+    fn cached_account(&self) -> Option<&AccountState> {
+        let known = self.known_state.as_ref()?;
+        let fresh = self.trusted_block.is_none() && known.age() < self.cache_ttl;
+        fresh.then_some(&known.account)
+    }
+
+    /// Verifies all reads of the channel state with storage proofs against the given block
+    /// instead of trusting the RPC. The block hash has to come from a trusted source.
+    pub fn set_trusted_block(&mut self, block: Option<H256>) {
+        self.trusted_block = block;
     }
 
     pub fn last_nonce(&self) -> U256 {
         self.messages
             .last()
-            .map_or(U256::zero(), |message| match message {
-                Message::Transfer(message) => message.userop.nonce,
-                Message::Withdrawal(message) => message.userop.nonce,
-            })
+            .map_or(
+                self.recovered_nonce.unwrap_or_default(),
+                |message| match message {
+                    Message::Transfer(message) => message.userop.nonce,
+                    Message::Withdrawal(message) => message.userop.nonce,
+                },
+            )
     }
 
     /* // this is the actual impl, but it does not work. thanks ERC-4337.
@@ -270,7 +882,7 @@ impl Channel {
     }
     */
     pub fn next_outgoing_nonce(&self) -> U256 {
-        if !self.messages.is_empty() {
+        if !self.messages.is_empty() || self.recovered_nonce.is_some() {
             self.last_nonce() + 1
         } else {
             U256::zero()
@@ -281,285 +893,1046 @@ impl Channel {
         self.next_outgoing_nonce()
     }
 
-    async fn sign(&self, userop: &UserOp) -> Bytes {
-        self.wallet()
-            .sign_message(
-                &userop
-                    .get_user_op_hash(self.entry_point, self.chain_id)
-                    .expect("should be fine")
-                    .0,
-            )
-            .await
-            .unwrap()
-            .to_vec()
-            .into()
+    fn user_op_hash(&self, userop: &UserOp) -> H256 {
+        proto::user_op_hash(userop, self.entry_point, self.chain_id)
     }
 
-    pub async fn request_transfer<M: Middleware>(
+    /// Signs a userop, or leaves it unsigned on watch-only channels.
+    async fn sign(&self, userop: &UserOp) -> Result<Bytes, Error> {
+        let hash = self.user_op_hash(userop);
+        let span = Span::current();
+        span.record("nonce", field::display(userop.nonce));
+        span.record("hash", field::debug(hash));
+        let Some(wallet) = self.wallet() else {
+            debug!("leaving user operation for offline signing");
+            return Ok(Bytes::new());
+        };
+        debug!("signing user operation");
+        let signature = wallet.sign_message(&hash.0).await.map_err(Signing)?;
+        Ok(signature.to_vec().into())
+    }
+
+    pub async fn request_transfer<C: ChainClient + ?Sized>(
         &mut self,
         wei: NonZeroU128,
-        client: Arc<M>,
-    ) -> Result<String, Error<M>> {
-        if self.pending_message.is_some() {
-            return Err(Error::AlreadyWaiting);
-        }
-        if self.get_sorted_balances(client).await?.1 < wei.get() {
-            return Err(Error::InsufficientBalance);
-        }
+        client: Arc<C>,
+    ) -> Result<OutgoingMessage, Error> {
+        self.request_transfer_batch(vec![(wei, None)], client).await
+    }
 
-        let current = self.get_value_transfer();
-        let wei = i128::try_from(wei.get()).unwrap();
-        let next = match self.us {
-            Party::A => current - wei,
-            Party::B => current + wei,
-        };
+    /// Requests a transfer of `wei` in place of our pending message, which is only dropped once
+    /// the new request succeeds.
+    pub async fn request_replacement<C: ChainClient + ?Sized>(
+        &mut self,
+        wei: NonZeroU128,
+        client: Arc<C>,
+    ) -> Result<OutgoingMessage, Error> {
+        let unreconciled = self.unreconciled;
+        let previous = self.pending_message.take();
+        let result = self.request_transfer(wei, client).await;
+        if result.is_err() {
+            self.pending_message = previous;
+            self.unreconciled = unreconciled;
+        }
+        result
+    }
 
-        let mut userop = UserOp {
-            sender: self.address,
-            nonce: self.next_outgoing_nonce().into(),
-            init_code: self.init_code(),
-            call_data: DisputeCall {
-                value_transfer: next,
+    /// Nets several payments into a single transfer, so only one state update has to be signed.
+    /// The payments and their memos are kept in our history.
+    #[instrument(skip_all, fields(channel = ?self.address, items = payments.len(), nonce = field::Empty, hash = field::Empty))]
+    pub async fn request_transfer_batch<C: ChainClient + ?Sized>(
+        &mut self,
+        payments: Vec<(NonZeroU128, Option<String>)>,
+        client: Arc<C>,
+    ) -> Result<OutgoingMessage, Error> {
+        bounded(self.operation.timeout, async {
+            self.check_signer(true)?;
+            let mut wei = 0u128;
+            for (amount, _) in &payments {
+                wei = wei.checked_add(amount.get()).ok_or(InsufficientBalance)?;
             }
-            .encode()
-            .into(),
-            call_gas_limit: CALL_GAS_LIMIT_DISPUTE.into(),
-            verification_gas_limit: VERIFICATION_GAS_LIMIT.into(),
-            pre_verificaiton_gas: PRE_VERIFICATION_GAS.into(),
-            max_fee_per_gas: MAX_FEE_PER_GAS.into(),
-            max_priority_fee_per_gas: PRIORITY_FEE.into(),
-            paymaster_and_data: Bytes::new(),
-            signature: Bytes::new(),
-        };
+            let wei = NonZeroU128::new(wei).ok_or(EmptyBatch)?;
+            let now = now();
+            let (userop, next) = self.transfer_userop(wei, now, client).await?;
+            self.sign_transfer(userop, next, payments, now).await
+        })
+        .await
+    }
 
-        userop.signature = self.sign(&userop).await;
+    /// Signs the userop of a transfer and keeps it as our pending message.
+    pub(crate) async fn sign_transfer(
+        &mut self,
+        mut userop: UserOp,
+        value_transfer: i128,
+        payments: Vec<(NonZeroU128, Option<String>)>,
+        now: u64,
+    ) -> Result<OutgoingMessage, Error> {
+        userop.signature = self.sign(&userop).await?;
 
+        let items: Vec<_> = payments
+            .into_iter()
+            .map(|(amount, memo)| TransferItem {
+                amount: Wei(amount.get()),
+                memo,
+            })
+            .collect();
+        let wei = Wei(items.iter().map(|item| item.amount.0).sum());
         self.pending_message = Some(Message::Transfer(TransferMessage {
             userop: userop.clone(),
-            value_transfer: next,
+            value_transfer,
+            items,
+            signed_at: None,
         }));
+        self.policy.record_outflow(wei, now);
+        self.unreconciled = false;
 
-        Ok(serde_json::to_string(&userop)?)
+        Ok(self.outgoing(&userop))
     }
 
-    pub async fn request_full_withdraw<M: Middleware>(
+    #[instrument(skip_all, fields(channel = ?self.address, nonce = field::Empty, hash = field::Empty))]
+    pub async fn request_full_withdraw<C: ChainClient + ?Sized>(
         &mut self,
-        client: Arc<M>,
-    ) -> Result<String, Error<M>> {
-        if self.pending_message.is_some() {
-            return Err(Error::AlreadyWaiting);
-        }
-        let (withdraw_a, withdraw_b) = self.get_balances(client).await?;
+        client: Arc<C>,
+    ) -> Result<OutgoingMessage, Error> {
+        bounded(self.operation.timeout, async {
+            self.check_signer(true)?;
+            let (mut userop, withdraw_a, withdraw_b) = self.withdrawal_userop(client).await?;
 
-        let mut userop = UserOp {
-            sender: self.address,
-            nonce: self.next_outgoing_nonce().into(),
-            init_code: self.init_code(),
-            call_data: CoopWithdrawCall {
-                value_transfer: self.get_value_transfer(),
-                withdraw_a,
-                withdraw_b,
+            userop.signature = self.sign(&userop).await?;
+
+            match self.us {
+                Party::A => {
+                    self.pending_message = Some(Message::Withdrawal(WithdrawalMessage {
+                        userop: userop.clone(),
+                        withdraw_us: Wei(withdraw_a),
+                        withdraw_them: Wei(withdraw_b),
+                        signed_at: None,
+                    }))
+                }
+                Party::B => {
+                    self.pending_message = Some(Message::Withdrawal(WithdrawalMessage {
+                        userop: userop.clone(),
+                        withdraw_us: Wei(withdraw_b),
+                        withdraw_them: Wei(withdraw_a),
+                        signed_at: None,
+                    }))
+                }
             }
-            .encode()
-            .into(),
-            call_gas_limit: CALL_GAS_LIMIT_COOP.into(),
-            verification_gas_limit: VERIFICATION_GAS_LIMIT.into(),
-            pre_verificaiton_gas: PRE_VERIFICATION_GAS.into(),
-            max_fee_per_gas: MAX_FEE_PER_GAS.into(),
-            max_priority_fee_per_gas: PRIORITY_FEE.into(),
-            paymaster_and_data: Bytes::new(),
-            signature: Bytes::new(),
-        };
 
-        userop.signature = self.sign(&userop).await;
+            Ok(self.outgoing(&userop))
+        })
+        .await
+    }
 
-        match self.us {
-            Party::A => {
-                self.pending_message = Some(Message::Withdrawal(WithdrawalMessage {
-                    userop: userop.clone(),
-                    withdraw_us: withdraw_a,
-                    withdraw_them: withdraw_b,
-                }))
-            }
-            Party::B => {
-                self.pending_message = Some(Message::Withdrawal(WithdrawalMessage {
-                    userop: userop.clone(),
-                    withdraw_us: withdraw_b,
-                    withdraw_them: withdraw_a,
-                }))
-            }
+    /// Like [`Channel::request_transfer_batch`], but keyed by an id chosen by the caller. If our
+    /// latest request was created with the same id, its message is returned again instead of
+    /// requesting anew, so a request can be retried when it is unclear whether it went through.
+    pub async fn request_transfer_batch_once<C: ChainClient + ?Sized>(
+        &mut self,
+        op_id: &str,
+        payments: Vec<(NonZeroU128, Option<String>)>,
+        client: Arc<C>,
+    ) -> Result<OutgoingMessage, Error> {
+        let same = |message: &Message| match message {
+            Message::Transfer(transfer) => transfer
+                .items
+                .iter()
+                .map(|item| item.amount)
+                .eq(payments.iter().map(|(amount, _)| Wei(amount.get()))),
+            Message::Withdrawal(_) => false,
+        };
+        if let Some(message) = self.replay(op_id, same)? {
+            return Ok(message);
         }
+        let message = self.request_transfer_batch(payments, client).await?;
+        self.last_operation = Some((op_id.to_string(), self.user_op_hash(message.userop())));
+        Ok(message)
+    }
 
-        Ok(serde_json::to_string(&userop)?)
+    /// Like [`Channel::request_full_withdraw`], but keyed by an id chosen by the caller, see
+    /// [`Channel::request_transfer_batch_once`].
+    pub async fn request_full_withdraw_once<C: ChainClient + ?Sized>(
+        &mut self,
+        op_id: &str,
+        client: Arc<C>,
+    ) -> Result<OutgoingMessage, Error> {
+        let same = |message: &Message| matches!(message, Message::Withdrawal(_));
+        if let Some(message) = self.replay(op_id, same)? {
+            return Ok(message);
+        }
+        let message = self.request_full_withdraw(client).await?;
+        self.last_operation = Some((op_id.to_string(), self.user_op_hash(message.userop())));
+        Ok(message)
     }
 
-    pub async fn receive_message<M: Middleware>(
+    /// The message of our latest request, if it was created with the operation id. Fails if that
+    /// request is gone or `same` does not accept it.
+    fn replay(
         &self,
-        userop: UserOp,
-        client: Arc<M>,
-    ) -> Result<Message, Error<M>> {
-        if self.address != userop.sender {
-            return Err(IllegalSender);
+        op_id: &str,
+        same: impl Fn(&Message) -> bool,
+    ) -> Result<Option<OutgoingMessage>, Error> {
+        let Some((id, hash)) = &self.last_operation else {
+            return Ok(None);
+        };
+        if id != op_id {
+            return Ok(None);
         }
+        // the hash does not cover the signatures, so it matches the countersigned message too
+        let message = self
+            .pending_message
+            .iter()
+            .chain(self.messages.iter().rev())
+            .find(|message| self.user_op_hash(message.userop()) == *hash);
+        match message {
+            Some(message) if same(message) => Ok(Some(self.outgoing(message.userop()))),
+            _ => Err(OperationReused(op_id.to_string())),
+        }
+    }
 
-        if self.next_incoming_nonce() != userop.nonce {
-            return Err(IllegalNonce);
+    /// Checks a transfer of `wei` to the counterparty and builds its unsigned userop, returning it
+    /// with the resulting value transfer.
+    pub(crate) async fn transfer_userop<C: ChainClient + ?Sized>(
+        &self,
+        wei: NonZeroU128,
+        now: u64,
+        client: Arc<C>,
+    ) -> Result<(UserOp, i128), Error> {
+        let (balance, _) = self.get_sorted_balances(client.clone()).await?;
+        let deployed = self.is_deployed(&client).await?;
+        self.transfer_userop_with(wei, now, balance, deployed)
+    }
+
+    /// Like [`Channel::transfer_userop`], with our balance and the deployment of the channel
+    /// already known.
+    pub(crate) fn transfer_userop_with(
+        &self,
+        wei: NonZeroU128,
+        now: u64,
+        balance: Wei,
+        deployed: bool,
+    ) -> Result<(UserOp, i128), Error> {
+        self.check_state(&[ChannelState::Open])?;
+        if self.pending_message.is_some() {
+            return Err(Error::AlreadyWaiting);
+        }
+        if balance < Wei(wei.get()) {
+            return Err(Error::InsufficientBalance);
         }
+        self.policy
+            .check_outflow(self.counterparty, Wei(wei.get()), now)?;
 
-        if userop.init_code != self.init_code() {
-            return Err(IllegalInitcode);
+        let current = SignedWei(self.get_value_transfer());
+        let wei = SignedWei::try_from(Wei(wei.get()))?;
+        let next = match self.us {
+            Party::A => current.checked_add(wei),
+            Party::B => current.checked_sub(wei),
         }
+        .ok_or(AmountError::Overflow)?
+        .0;
 
-        if userop.paymaster_and_data != Bytes::new()
-            || userop.max_priority_fee_per_gas != PRIORITY_FEE.into()
-            || userop.max_fee_per_gas != MAX_FEE_PER_GAS.into()
-            || userop.pre_verificaiton_gas != PRE_VERIFICATION_GAS.into()
-            || userop.verification_gas_limit != VERIFICATION_GAS_LIMIT.into()
-        {
-            return Err(IllegalConstant);
-        }
-
-        let signature =
-            Signature::try_from(userop.signature.as_ref()).map_err(|_| IllegalSignature)?;
-        let address = signature
-            .recover(
-                userop
-                    .get_user_op_hash(self.entry_point, self.chain_id)
-                    .unwrap()
-                    .0
-                    .to_vec(),
+        let userop = self
+            .userop(deployed)?
+            .call(
+                DisputeCall {
+                    value_transfer: next,
+                },
+                CALL_GAS_LIMIT_DISPUTE,
             )
-            .map_err(|_| IllegalSignature)?;
-        if address != self.counterparty {
-            return Err(IllegalSignature);
+            .build();
+        Ok((userop, next))
+    }
+
+    /// Builds the unsigned userop withdrawing both balances, returning it with the withdrawals of
+    /// A and B.
+    pub(crate) async fn withdrawal_userop<C: ChainClient + ?Sized>(
+        &self,
+        client: Arc<C>,
+    ) -> Result<(UserOp, u128, u128), Error> {
+        self.check_state(&[ChannelState::Open])?;
+        if self.pending_message.is_some() {
+            return Err(Error::AlreadyWaiting);
         }
+        let (Wei(withdraw_a), Wei(withdraw_b)) = self.get_balances(client.clone()).await?;
+        let deployed = self.is_deployed(&client).await?;
 
-        Ok(
-            match AAChannelCalls::decode(&userop.call_data).map_err(|_| IllegalCalldata)? {
-                AAChannelCalls::CoopWithdraw(CoopWithdrawCall {
-                    value_transfer,
+        let userop = self
+            .userop(deployed)?
+            .call(
+                CoopWithdrawCall {
+                    value_transfer: self.get_value_transfer(),
                     withdraw_a,
                     withdraw_b,
-                }) => {
-                    if userop.call_gas_limit != CALL_GAS_LIMIT_COOP.into() {
-                        return Err(IllegalConstant);
-                    }
-                    if value_transfer != self.get_value_transfer() {
-                        return Err(IllegalValueTransfer);
-                    }
-                    let (balance_a, balance_b) = self.get_balances(client).await?;
-                    if withdraw_a > balance_a || withdraw_b > balance_b {
-                        return Err(InsufficientBalance);
-                    }
+                },
+                CALL_GAS_LIMIT_COOP,
+            )
+            .build();
+        Ok((userop, withdraw_a, withdraw_b))
+    }
 
-                    match self.us {
-                        Party::A => Message::Withdrawal(WithdrawalMessage {
-                            userop,
-                            withdraw_us: withdraw_a,
-                            withdraw_them: withdraw_b,
-                        }),
-                        Party::B => Message::Withdrawal(WithdrawalMessage {
-                            userop,
-                            withdraw_us: withdraw_b,
-                            withdraw_them: withdraw_a,
-                        }),
-                    }
+    /// Validates a message of the counterparty, returning it for signing with what it would mean
+    /// for us. Messages of a newer protocol version fail with [`Error::UnsupportedVersion`].
+    #[instrument(skip_all, fields(channel = ?self.address, nonce = field::Empty, hash = field::Empty))]
+    pub async fn receive_message<C: ChainClient + ?Sized>(
+        &self,
+        message: &str,
+        client: Arc<C>,
+    ) -> Result<(Message, Summary), Error> {
+        bounded(self.operation.timeout, async {
+            let userop = protocol::decode_message(message)?;
+            // hashing encodes the whole userop, so it is only done once
+            let hash = self.user_op_hash(&userop);
+            Span::current().record("nonce", field::display(userop.nonce));
+            Span::current().record("hash", field::debug(hash));
+            self.check_state(&[ChannelState::Open, ChannelState::PendingWithdrawal])?;
+            if self.address != userop.sender {
+                return Err(IllegalSender);
+            }
+
+            if self.processed_messages.contains(&hash) {
+                return Err(DuplicateMessage);
+            }
+
+            // while a withdrawal is pending, only replacements for it are accepted
+            let bumped = self.bumped_withdrawal(&userop);
+            if bumped.is_none() {
+                self.check_state(&[ChannelState::Open])?;
+                if self.next_incoming_nonce() != userop.nonce {
+                    return Err(IllegalNonce);
                 }
-                AAChannelCalls::Dispute(DisputeCall { value_transfer }) => {
-                    if userop.call_gas_limit != CALL_GAS_LIMIT_DISPUTE.into() {
-                        return Err(IllegalConstant);
-                    }
-                    Message::Transfer(TransferMessage {
-                        userop,
+            }
+
+            // the gas fields are only looked at once the counterparty is known to have signed them
+            verify_signer(&userop.signature, hash, self.counterparty)?;
+
+            let deployed = self.is_deployed(&client).await?;
+
+            let (max_fee_per_gas, max_priority_fee_per_gas) = match bumped {
+                Some(previous) => bumped_fees(&previous.userop)?,
+                None => (MAX_FEE_PER_GAS.into(), PRIORITY_FEE.into()),
+            };
+            let validation = &self.validation;
+            if userop.paymaster_and_data != Bytes::new() {
+                return Err(
+                    ConstantError::PaymasterAndData(userop.paymaster_and_data.clone()).into(),
+                );
+            }
+            validation.priority_fee(userop.max_priority_fee_per_gas, max_priority_fee_per_gas)?;
+            validation.max_fee(userop.max_fee_per_gas, max_fee_per_gas)?;
+            validation
+                .pre_verification_gas(userop.pre_verificaiton_gas, PRE_VERIFICATION_GAS.into())?;
+            validation.verification_gas_limit(
+                userop.verification_gas_limit,
+                VERIFICATION_GAS_LIMIT.into(),
+            )?;
+
+            Ok(
+                match AAChannelCalls::decode(&userop.call_data).map_err(|_| IllegalCalldata)? {
+                    AAChannelCalls::CoopWithdraw(CoopWithdrawCall {
                         value_transfer,
-                    })
-                }
-                _ => return Err(IllegalCalldata),
+                        withdraw_a,
+                        withdraw_b,
+                    }) => {
+                        self.check_init_code(&userop, deployed, true)?;
+                        validation
+                            .call_gas_limit(userop.call_gas_limit, CALL_GAS_LIMIT_COOP.into())?;
+                        if bumped
+                            .is_some_and(|previous| previous.userop.call_data != userop.call_data)
+                        {
+                            return Err(IllegalCalldata);
+                        }
+                        if value_transfer != self.get_value_transfer() {
+                            return Err(IllegalValueTransfer);
+                        }
+                        let (balance_a, balance_b) = self.get_balances(client.clone()).await?;
+                        if Wei(withdraw_a) > balance_a || Wei(withdraw_b) > balance_b {
+                            return Err(InsufficientBalance);
+                        }
+                        let payout = self
+                            .withdrawal_payout(&userop, withdraw_a, withdraw_b, client)
+                            .await?;
+
+                        let (withdraw_us, withdraw_them) = match self.us {
+                            Party::A => (Wei(withdraw_a), Wei(withdraw_b)),
+                            Party::B => (Wei(withdraw_b), Wei(withdraw_a)),
+                        };
+                        (
+                            Message::Withdrawal(WithdrawalMessage {
+                                userop,
+                                withdraw_us,
+                                withdraw_them,
+                                signed_at: None,
+                            }),
+                            Summary::Withdrawal {
+                                withdraw_us,
+                                withdraw_them,
+                                payout,
+                            },
+                        )
+                    }
+                    AAChannelCalls::Dispute(DisputeCall { value_transfer }) => {
+                        self.check_init_code(&userop, deployed, false)?;
+                        if bumped.is_some() {
+                            return Err(IllegalNonce);
+                        }
+                        validation
+                            .call_gas_limit(userop.call_gas_limit, CALL_GAS_LIMIT_DISPUTE.into())?;
+                        let (ours, theirs) = self.get_sorted_balances(client).await?;
+                        let our_delta = SignedWei(self.our_delta(value_transfer));
+                        (
+                            Message::Transfer(TransferMessage {
+                                userop,
+                                value_transfer,
+                                items: vec![],
+                                signed_at: None,
+                            }),
+                            Summary::Transfer {
+                                amount: our_delta.unsigned_abs(),
+                                incoming: our_delta > SignedWei::ZERO,
+                                our_balance: ours.saturating_add_signed(our_delta),
+                                their_balance: theirs
+                                    .saturating_add_signed(our_delta.saturating_neg()),
+                            },
+                        )
+                    }
+                    _ => return Err(IllegalCalldata),
+                },
+            )
+        })
+        .await
+    }
+
+    #[instrument(skip_all, fields(channel = ?self.address, nonce = %message.userop().nonce, hash = field::Empty))]
+    pub async fn sign_message<C: ChainClient + ?Sized>(
+        &mut self,
+        message: Message,
+        client: Arc<C>,
+    ) -> Result<OutgoingMessage, Error> {
+        bounded(self.operation.timeout, async {
+            self.check_signer(false)?;
+            let outflow = self.check_signable(&message)?;
+            let hash = self.user_op_hash(message.userop());
+            self.learn_counterparty_key(hash, &message.userop().signature);
+
+            let signature = self.sign(message.userop()).await?;
+            self.countersign(message, signature, outflow, client).await
+        })
+        .await
+    }
+
+    /// Checks whether we may sign a message of the counterparty, returning what it pays out.
+    fn check_signable(&self, message: &Message) -> Result<Option<Wei>, Error> {
+        let outflow = match message {
+            Message::Transfer(msg) => match SignedWei(self.our_delta(msg.value_transfer)) {
+                delta if delta.is_negative() => Some(delta.unsigned_abs()),
+                _ => None,
             },
-        )
+            Message::Withdrawal(_) => None,
+        };
+        if let Some(outflow) = outflow {
+            self.policy
+                .check_outflow(self.counterparty, outflow, now())?;
+        }
+
+        if self.bumped_withdrawal(message.userop()).is_none() {
+            self.check_state(&[ChannelState::Open])?;
+        } else {
+            self.check_state(&[ChannelState::PendingWithdrawal])?;
+        }
+
+        if self
+            .processed_messages
+            .contains(&self.user_op_hash(message.userop()))
+        {
+            return Err(DuplicateMessage);
+        }
+        Ok(outflow)
     }
 
-    pub async fn sign_message<M: Middleware>(
+    /// Adds our signature to a message of the counterparty, submitting it if it is a withdrawal.
+    pub(crate) async fn countersign<C: ChainClient + ?Sized>(
+        &mut self,
+        message: Message,
+        signature: Bytes,
+        outflow: Option<Wei>,
+        client: Arc<C>,
+    ) -> Result<OutgoingMessage, Error> {
+        let userop = self
+            .countersigned_userop(&message, signature, client.clone())
+            .await?;
+        self.record_countersigned(message, userop, outflow, client)
+            .await
+    }
+
+    /// Adds our signature to a message of the counterparty and, for a withdrawal, simulates it.
+    /// Changes nothing, so it can be dropped at any point.
+    pub(crate) async fn countersigned_userop<C: ChainClient + ?Sized>(
+        &self,
+        message: &Message,
+        signature: Bytes,
+        client: Arc<C>,
+    ) -> Result<UserOp, Error> {
+        let userop = self.merge_signature(message, signature)?;
+        if matches!(message, Message::Withdrawal(_)) {
+            self.simulate(&userop, client).await?;
+        }
+        Ok(userop)
+    }
+
+    /// The userop of a message of the counterparty, signed by both parties.
+    pub(crate) fn merge_signature(
+        &self,
+        message: &Message,
+        signature: Bytes,
+    ) -> Result<UserOp, Error> {
+        let mut userop = message.userop().clone();
+        userop.signature = match self.us {
+            Party::A => encode_pair(signature, userop.signature.clone()),
+            Party::B => encode_pair(userop.signature.clone(), signature),
+        };
+        self.check_countersigned(&userop)?;
+        Ok(userop)
+    }
+
+    /// Records a countersigned message and submits it if it is a withdrawal. The message is
+    /// recorded before anything is sent, so if the submission fails or the future is dropped, the
+    /// withdrawal is still tracked and can be handed to the bundler again with
+    /// [`Channel::resubmit_withdrawal`].
+    pub(crate) async fn record_countersigned<C: ChainClient + ?Sized>(
         &mut self,
         mut message: Message,
-        client: Arc<M>,
-    ) -> Result<String, Error<M>> {
-        let userop = match &mut message {
-            Message::Transfer(msg) => &mut msg.userop,
-            Message::Withdrawal(msg) => &mut msg.userop,
+        userop: UserOp,
+        outflow: Option<Wei>,
+        client: Arc<C>,
+    ) -> Result<OutgoingMessage, Error> {
+        let hash = self.user_op_hash(&userop);
+        message.userop_mut().signature = userop.signature.clone();
+        if let Some(outflow) = outflow {
+            self.policy.record_outflow(outflow, now());
+        }
+        self.processed_messages.insert(hash);
+        let withdrawal = matches!(message, Message::Withdrawal(_));
+        self.push_message(message);
+        if withdrawal {
+            self.withdrawal = Some(SubmittedWithdrawal {
+                hash,
+                status: WithdrawalStatus::Submitted,
+                submitter: Some(self.us),
+            });
+            self.transition(ChannelState::PendingWithdrawal);
+            info!("submitting user operation");
+            self.retry
+                .run(|| self.submit(&userop, &client), |_| true)
+                .await?;
+        }
+        Ok(self.outgoing(&userop))
+    }
+
+    /// Hands our latest withdrawal to the bundler again, for when submitting it failed or was
+    /// interrupted after it was recorded. Returns the fully signed message, which the
+    /// counterparty may still be waiting for.
+    #[instrument(skip_all, fields(channel = ?self.address))]
+    pub async fn resubmit_withdrawal<C: ChainClient + ?Sized>(
+        &self,
+        client: Arc<C>,
+    ) -> Result<OutgoingMessage, Error> {
+        bounded(self.operation.timeout, async {
+            self.check_state(&[ChannelState::PendingWithdrawal])?;
+            let (Some(withdrawal), Some(Message::Withdrawal(message))) =
+                (&self.withdrawal, self.messages.last())
+            else {
+                return Err(NoPendingWithdrawal);
+            };
+            if withdrawal.status != WithdrawalStatus::Submitted
+                || withdrawal.hash != self.user_op_hash(&message.userop)
+            {
+                return Err(NoPendingWithdrawal);
+            }
+            info!("resubmitting user operation");
+            self.retry
+                .run(|| self.submit(&message.userop, &client), |_| true)
+                .await?;
+            Ok(self.outgoing(&message.userop))
+        })
+        .await
+    }
+
+    /// Hands a userop to the bundler. A failed submission may still have reached the bundler, so
+    /// before reporting an error we check whether it knows the op, which makes retrying safe.
+    async fn submit<C: ChainClient + ?Sized>(
+        &self,
+        userop: &UserOp,
+        client: &Arc<C>,
+    ) -> Result<(), ChainError> {
+        let Err(err) = client.send_user_operation(userop, self.entry_point).await else {
+            return Ok(());
         };
+        match client.user_operation_known(self.user_op_hash(userop)).await {
+            Ok(true) => Ok(()),
+            _ => Err(err),
+        }
+    }
 
-        let signature = self.sign(&userop).await;
-
-        let new_sig = match self.us {
-            Party::A => abi::encode(&[
-                signature.into_token(),
-                userop.signature.clone().into_token(),
-            ]),
-            Party::B => abi::encode(&[
-                userop.signature.clone().into_token(),
-                signature.into_token(),
-            ]),
+    /// Runs the entry point's validation of a fully signed userop against the current chain state,
+    /// so we do not submit an op that will never be included.
+    #[instrument(skip_all)]
+    async fn simulate<C: ChainClient + ?Sized>(
+        &self,
+        userop: &UserOp,
+        client: Arc<C>,
+    ) -> Result<(), Error> {
+        debug!("simulating validation");
+        let call = SimulateValidationCall {
+            user_op: user_operation(userop),
+        };
+        let data = Bytes::from(call.encode());
+        let result = self
+            .retry
+            .run(
+                || client.call(self.entry_point, data.clone(), LATEST),
+                |err| !err.is_revert(),
+            )
+            .await;
+        // simulateValidation reverts even if the validation succeeds
+        let revert = match result {
+            Ok(_) => return Err(SimulationFailed("entry point did not revert".to_string())),
+            Err(ChainError::Revert(revert)) => revert,
+            Err(err) => return Err(err.into()),
         };
+        match IEntryPointErrors::decode(&revert) {
+            Ok(IEntryPointErrors::ValidationResult(result)) => {
+                if result.return_info.2 {
+                    Err(SimulationFailed("signature validation failed".to_string()))
+                } else {
+                    Ok(())
+                }
+            }
+            Ok(IEntryPointErrors::FailedOp(FailedOp { reason, .. })) => {
+                Err(SimulationFailed(reason))
+            }
+            Ok(IEntryPointErrors::RevertString(reason)) => match Revert::from_reason(&reason) {
+                Some(revert) => Err(Reverted(revert)),
+                None => Err(SimulationFailed(reason)),
+            },
+            _ => Err(SimulationFailed(revert.to_string())),
+        }
+    }
 
-        userop.signature = new_sig.into();
-        let userop = userop.clone();
+    #[instrument(skip_all, fields(channel = ?self.address))]
+    pub async fn get_dispute_info<C: ChainClient + ?Sized>(
+        &self,
+        client: Arc<C>,
+    ) -> Result<Option<DisputeInfo>, Error> {
+        bounded(self.operation.timeout, async {
+            let account = if let Some(block) = self.trusted_block {
+                verify::verified_account(client.as_ref(), &self.retry, self.address, block).await?
+            } else if let Some(account) = self.cached_account() {
+                *account
+            } else {
+                let block = self
+                    .block_policy
+                    .resolve(client.as_ref(), &self.retry)
+                    .await?;
+                if !self.deployed_at(&client, block).await? {
+                    return Ok(None);
+                }
+                let DisputeTimestampReturn(timeout) =
+                    self.view(&client, DisputeTimestampCall, block).await?;
+                if timeout == 0 {
+                    return Ok(None);
+                }
+                let DisputeValueReturn(value) = self.view(&client, DisputeValueCall, block).await?;
+                let DisputeStartNonceReturn(nonce) =
+                    self.view(&client, DisputeStartNonceCall, block).await?;
+                let BalanceAReturn(a) = self.view(&client, BalanceACall, block).await?;
+                let BalanceBReturn(b) = self.view(&client, BalanceBCall, block).await?;
+                AccountState {
+                    deployed: true,
+                    balance_a: a,
+                    balance_b: b,
+                    dispute_start_nonce: nonce,
+                    dispute_value: value,
+                    dispute_timestamp: timeout,
+                    ..AccountState::default()
+                }
+            };
+            self.dispute_info_at(&account)
+        })
+        .await
+    }
 
-        if matches!(message, Message::Withdrawal(_)) {
-            client
-                .send_user_operation(userop.clone(), self.entry_point)
-                .await
-                .map_err(MiddlewareError)?;
+    /// The dispute in an account read with [`snapshot::read`], like
+    /// [`Channel::get_dispute_info`] returns it.
+    pub fn dispute_info_at(&self, account: &AccountState) -> Result<Option<DisputeInfo>, Error> {
+        if !account.deployed || account.dispute_timestamp == 0 {
+            return Ok(None);
         }
+        let withdrawals =
+            dispute_withdrawals(account.balance_a, account.balance_b, account.dispute_value)?;
+        let (withdrawal_ours, withdrawal_theirs) = self.sorted(withdrawals);
+        Ok(Some(DisputeInfo {
+            nonce: account.dispute_start_nonce,
+            timeout: account.dispute_timestamp,
+            withdrawal_ours,
+            withdrawal_theirs,
+        }))
+    }
 
-        self.messages.push(message);
-        Ok(serde_json::to_string(&userop)?)
+    /// Asks the bundler whether our submitted withdrawal has been included yet. Returns `None` if
+    /// no withdrawal was submitted.
+    #[instrument(skip_all, fields(channel = ?self.address))]
+    pub async fn withdrawal_status<C: ChainClient + ?Sized>(
+        &mut self,
+        client: Arc<C>,
+    ) -> Result<Option<WithdrawalStatus>, Error> {
+        bounded(self.operation.timeout, async {
+            let Some(withdrawal) = &mut self.withdrawal else {
+                return Ok(None);
+            };
+            if withdrawal.status == WithdrawalStatus::Submitted {
+                let receipt = self
+                    .retry
+                    .run(|| client.user_operation_receipt(withdrawal.hash), |_| true)
+                    .await?;
+                let receipt = match receipt {
+                    Some(receipt) if self.operation.confirmations > 1 => {
+                        let head = self.retry.run(|| client.block_number(), |_| true).await?;
+                        // wait for enough blocks on top, so a reorg does not undo the withdrawal
+                        let depth = (head + 1).saturating_sub(receipt.receipt.block_number);
+                        (depth.as_usize() >= self.operation.confirmations).then_some(receipt)
+                    }
+                    receipt => receipt,
+                };
+                if let Some(receipt) = receipt {
+                    let transaction = receipt.receipt.transaction_hash;
+                    withdrawal.status = if receipt.success {
+                        WithdrawalStatus::Included { transaction }
+                    } else {
+                        WithdrawalStatus::Failed { transaction }
+                    };
+                    info!(status = ?withdrawal.status, "withdrawal processed");
+                    // failed withdrawals are paid for as well
+                    let (hash, submitter) = (withdrawal.hash, withdrawal.submitter);
+                    self.record_gas(
+                        hash,
+                        transaction,
+                        receipt.actual_gas_cost.low_u128(),
+                        submitter,
+                    );
+                }
+            }
+            let status = self.withdrawal.as_ref().expect("checked above").status;
+            match status {
+                // all withdrawals are full withdrawals, so nothing is left in the channel
+                WithdrawalStatus::Included { .. } => self.transition(ChannelState::Closed),
+                WithdrawalStatus::Failed { .. }
+                    if self.state == ChannelState::PendingWithdrawal =>
+                {
+                    self.transition(ChannelState::Open)
+                }
+                _ => {}
+            }
+            Ok::<_, Error>(Some(status))
+        })
+        .await
     }
 
-    pub async fn get_dispute_info<M: Middleware>(
+    /// Brings the channel state up to date with the chain: tracks a submitted withdrawal and
+    /// notices disputes started or settled by the counterparty.
+    #[instrument(skip_all, fields(channel = ?self.address))]
+    pub async fn update_state<C: ChainClient + ?Sized>(
+        &mut self,
+        client: Arc<C>,
+    ) -> Result<ChannelState, Error> {
+        bounded(self.operation.timeout, async {
+            if self.state == ChannelState::Closed {
+                return Ok(self.state);
+            }
+            self.withdrawal_status(client.clone()).await?;
+            let disputed = self.get_dispute_info(client).await?.is_some();
+            match self.state {
+                ChannelState::Open | ChannelState::PendingWithdrawal if disputed => {
+                    self.transition(ChannelState::Disputed)
+                }
+                // closing the dispute pays out everything
+                ChannelState::Disputed if !disputed => self.transition(ChannelState::Closed),
+                _ => {}
+            }
+            Ok::<_, Error>(self.state)
+        })
+        .await
+    }
+
+    /// Checks that forgetting the channel loses nothing: it holds no funds and no dispute is open.
+    pub async fn check_disposable<C: ChainClient + ?Sized>(
         &self,
-        client: Arc<M>,
-    ) -> Result<Option<DisputeInfo>, Error<M>> {
-        if self.is_deployed(&client).await.map_err(MiddlewareError)? {
-            let channel = AAChannel::new(self.address, client);
-            let timeout = channel.dispute_timestamp().call().await?;
-            if timeout == 0 {
-                Ok(None)
-            } else {
-                let value = channel.dispute_value().call().await?;
-                let nonce = channel.dispute_start_nonce().call().await?;
-                let balance_a = channel.balance_a().call().await? as i128 - value;
-                let balance_b = channel.balance_b().call().await? as i128 + value;
-                Ok(Some(match self.us {
-                    Party::A => DisputeInfo {
-                        nonce,
-                        timeout,
-                        withdrawal_ours: balance_a,
-                        withdrawal_theirs: balance_b,
-                    },
-                    Party::B => DisputeInfo {
-                        nonce,
-                        timeout,
-                        withdrawal_ours: balance_b,
-                        withdrawal_theirs: balance_a,
-                    },
-                }))
+        client: Arc<C>,
+    ) -> Result<(), Error> {
+        bounded(self.operation.timeout, async {
+            if matches!(self.state, ChannelState::Proposed | ChannelState::Closed) {
+                return Ok(());
             }
-        } else {
-            Ok(None)
+            if self.get_dispute_info(client.clone()).await?.is_some() {
+                return Err(DisputeOpen);
+            }
+            let (balance_a, balance_b) = self.get_balances(client).await?;
+            if !balance_a.is_zero() || !balance_b.is_zero() {
+                return Err(FundsLeft);
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    /// Replaces our submitted withdrawal with one paying higher fees, for when it is stuck because
+    /// fees rose. Like any other request, the returned message has to be signed by the
+    /// counterparty, who then submits it in place of the old one.
+    #[instrument(skip_all, fields(channel = ?self.address))]
+    pub async fn bump_withdrawal_fee(&mut self) -> Result<OutgoingMessage, Error> {
+        self.check_signer(true)?;
+        self.check_state(&[ChannelState::PendingWithdrawal])?;
+        if self.pending_message.is_some() {
+            return Err(AlreadyWaiting);
+        }
+        let Some(Message::Withdrawal(previous)) = self.messages.last() else {
+            return Err(NoPendingWithdrawal);
+        };
+        if self.bumped_withdrawal(&previous.userop).is_none() {
+            return Err(NoPendingWithdrawal);
+        }
+        let (withdraw_us, withdraw_them) = (previous.withdraw_us, previous.withdraw_them);
+
+        let mut userop = previous.userop.clone();
+        (userop.max_fee_per_gas, userop.max_priority_fee_per_gas) = bumped_fees(&userop)?;
+        userop.signature = self.sign(&userop).await?;
+        info!(max_fee_per_gas = %userop.max_fee_per_gas, "bumping withdrawal fee");
+
+        self.pending_message = Some(Message::Withdrawal(WithdrawalMessage {
+            userop: userop.clone(),
+            withdraw_us,
+            withdraw_them,
+            signed_at: None,
+        }));
+        Ok(self.outgoing(&userop))
+    }
+
+    /// The withdrawal a userop would replace, if it reuses the nonce of our last message, which is
+    /// a withdrawal that has not been included yet.
+    fn bumped_withdrawal(&self, userop: &UserOp) -> Option<&WithdrawalMessage> {
+        let Some(Message::Withdrawal(previous)) = self.messages.last() else {
+            return None;
+        };
+        (self.state == ChannelState::PendingWithdrawal && previous.userop.nonce == userop.nonce)
+            .then_some(previous)
+    }
+
+    /// The newest message carrying valid signatures of both parties, which settles the channel
+    /// on-chain: a transfer through a dispute, a withdrawal directly.
+    pub fn latest_settleable(&self) -> Option<&Message> {
+        self.messages
+            .iter()
+            .rev()
+            .find(|message| self.countersigned(message.userop()))
+    }
+
+    /// Whether the userop carries valid signatures of both parties, which every message has to
+    /// before it is stored.
+    fn countersigned(&self, userop: &UserOp) -> bool {
+        self.check_countersigned(userop).is_ok()
+    }
+
+    /// Like [`Channel::countersigned`], telling which signature is wrong and why.
+    fn check_countersigned(&self, userop: &UserOp) -> Result<(), Error> {
+        let (party_a, party_b) = self.parties()?;
+        verify_pair(
+            &userop.signature,
+            self.user_op_hash(userop),
+            party_a,
+            party_b,
+        )?;
+        Ok(())
+    }
+
+    fn push_message(&mut self, mut message: Message) {
+        let signed_at = Some(now());
+        match &mut message {
+            Message::Transfer(message) => message.signed_at = signed_at,
+            Message::Withdrawal(message) => message.signed_at = signed_at,
+        }
+        if self.bumped_withdrawal(message.userop()).is_some() {
+            self.messages.pop();
+        }
+        self.messages.push(message);
+    }
+
+    pub fn state(&self) -> ChannelState {
+        self.state
+    }
+
+    fn check_state(&self, allowed: &[ChannelState]) -> Result<(), Error> {
+        match self.state {
+            state if allowed.contains(&state) => Ok(()),
+            ChannelState::Closed => Err(ChannelClosed),
+            state => Err(InvalidState(state)),
+        }
+    }
+
+    fn transition(&mut self, state: ChannelState) {
+        if self.state != state {
+            info!(from = ?self.state, to = ?state, "channel state changed");
+            self.state = state;
+            // disputes and withdrawals move the balances
+            self.invalidate_cache();
         }
     }
 
+    /// All fully signed messages, oldest first.
+    pub fn messages(&self) -> &[Message] {
+        &self.messages
+    }
+
     pub fn pending_message(&self) -> Option<&Message> {
         self.pending_message.as_ref()
     }
 
+    pub fn policy(&self) -> &Policy {
+        &self.policy
+    }
+
+    pub fn set_policy(&mut self, policy: Policy) {
+        self.policy.update(policy);
+    }
+
+    pub fn validation_policy(&self) -> &ValidationPolicy {
+        &self.validation
+    }
+
+    /// Relaxes the checks of the gas fields of incoming userops, see [`validation`].
+    pub fn set_validation_policy(&mut self, validation: ValidationPolicy) {
+        self.validation = validation;
+    }
+
     pub fn cancel_pending_message(&mut self) -> bool {
+        self.unreconciled = false;
         self.pending_message.take().is_some()
     }
 
-    // todo import countersigned message
-    // todo send dispute
-    // todo close dispute
+    /// Imports the counterparty's response to our pending message, which carries both signatures.
+    /// Apart from the signature, the response has to be identical to the pending message.
+    #[instrument(skip_all, fields(channel = ?self.address, nonce = field::Empty))]
+    pub fn receive_response(&mut self, message: &str) -> Result<(), Error> {
+        let userop = protocol::decode_message(message)?;
+        Span::current().record("nonce", field::display(userop.nonce));
+        self.check_state(&[ChannelState::Open, ChannelState::PendingWithdrawal])?;
+        let Some(pending) = &self.pending_message else {
+            return Err(NotWaiting);
+        };
+        // the hash would cover all other fields too, but comparing them does not rely on it
+        if !same_apart_from_signature(pending.userop(), &userop) {
+            return Err(IllegalResponse);
+        }
+        self.check_countersigned(&userop)?;
+
+        let mut message = self.pending_message.take().expect("checked above");
+        let withdrawal = matches!(message, Message::Withdrawal(_));
+        let hash = self.user_op_hash(&userop);
+        *message.userop_mut() = userop;
+        self.push_message(message);
+        if withdrawal {
+            // the counterparty submits the withdrawal before responding
+            self.withdrawal = Some(SubmittedWithdrawal {
+                hash,
+                status: WithdrawalStatus::Submitted,
+                submitter: Some(match self.us {
+                    Party::A => Party::B,
+                    Party::B => Party::A,
+                }),
+            });
+            self.transition(ChannelState::PendingWithdrawal);
+        }
+        Ok(())
+    }
+
     // todo send noop
 }
+
+/// What closing the dispute pays out to A and B: the balances with the disputed value transfer
+/// applied, negative if a balance does not cover it.
+fn account_balances(account: &AccountState) -> Result<(Wei, Wei), AmountError> {
+    Ok(if account.deployed {
+        (account.balance_a.into(), account.balance_b.into())
+    } else {
+        (account.balance.try_into()?, Wei::ZERO)
+    })
+}
+
+fn dispute_withdrawals(
+    balance_a: u128,
+    balance_b: u128,
+    value_transfer: i128,
+) -> Result<(SignedWei, SignedWei), AmountError> {
+    let value_transfer = SignedWei(value_transfer);
+    let balance_a = SignedWei::try_from(Wei(balance_a))?;
+    let balance_b = SignedWei::try_from(Wei(balance_b))?;
+    Ok((
+        balance_a
+            .checked_sub(value_transfer)
+            .ok_or(AmountError::Overflow)?,
+        balance_b
+            .checked_add(value_transfer)
+            .ok_or(AmountError::Overflow)?,
+    ))
+}
+
+fn bumped_fees(userop: &UserOp) -> Result<(U256, U256), Error> {
+    Ok((
+        bumped_fee(userop.max_fee_per_gas)?,
+        bumped_fee(userop.max_priority_fee_per_gas)?,
+    ))
+}
+
+fn bumped_fee(fee: U256) -> Result<U256, Error> {
+    let bumped = fee
+        .checked_mul((100 + FEE_BUMP_PERCENT).into())
+        .ok_or(AmountError::Overflow)?;
+    Ok(bumped / 100)
+}
+
+/// Whether the userops are identical apart from their signatures.
+fn same_apart_from_signature(a: &UserOp, b: &UserOp) -> bool {
+    let unsigned = |userop: &UserOp| UserOperation {
+        signature: Bytes::new(),
+        ..user_operation(userop)
+    };
+    unsigned(a) == unsigned(b)
+}
+
+fn user_operation(userop: &UserOp) -> UserOperation {
+    UserOperation {
+        sender: userop.sender,
+        nonce: userop.nonce,
+        init_code: userop.init_code.clone(),
+        call_data: userop.call_data.clone(),
+        call_gas_limit: userop.call_gas_limit,
+        verification_gas_limit: userop.verification_gas_limit,
+        pre_verification_gas: userop.pre_verificaiton_gas,
+        max_fee_per_gas: userop.max_fee_per_gas,
+        max_priority_fee_per_gas: userop.max_priority_fee_per_gas,
+        paymaster_and_data: userop.paymaster_and_data.clone(),
+        signature: userop.signature.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fee_bumps_overflowing_are_refused() {
+        assert_eq!(bumped_fee(100.into()).unwrap(), 125.into());
+        assert!(matches!(
+            bumped_fee(U256::MAX),
+            Err(Amount(AmountError::Overflow))
+        ));
+    }
+}
@@ -0,0 +1,280 @@
+//! An in-memory stand-in for the chain and bundler, so a [`Channel`](crate::Channel) can be driven
+//! through its whole message flow without a node.
+use async_trait::async_trait;
+use ch4nn337_sys::aa_channel::AAChannelCalls;
+use ch4nn337_sys::aa_channel_factory::{AAChannelFactoryCalls, GetAddressCall};
+use ch4nn337_sys::channel_address::channel_address;
+use ch4nn337_sys::i_entry_point::{
+    BalanceOfCall, IEntryPointCalls, IEntryPointErrors, ValidationResult,
+};
+use ch4nn337_sys::multicall::{
+    Aggregate3Call, Aggregate3Return, CallResult, GetEthBalanceCall, GetEthBalanceReturn,
+    MULTICALL3,
+};
+use ethers::abi::{self, AbiDecode, AbiEncode, Token};
+use ethers::providers::{JsonRpcClient, JsonRpcError, Provider, ProviderError, RpcError};
+use ethers::types::userop::UserOp;
+use ethers::types::{Address, Block, Bytes, H256, I256, U256, U64};
+use ethers::utils::keccak256;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+
+pub type MockMiddleware = Provider<MockClient>;
+
+#[derive(Error, Debug)]
+pub enum MockError {
+    #[error("{0}")]
+    Serde(#[from] serde_json::Error),
+    #[error("unsupported method {0}")]
+    UnsupportedMethod(String),
+    #[error("unsupported call to {0:?}")]
+    UnsupportedCall(Address),
+    #[error("{0}")]
+    Revert(JsonRpcError),
+}
+
+impl RpcError for MockError {
+    fn as_error_response(&self) -> Option<&JsonRpcError> {
+        match self {
+            MockError::Revert(err) => Some(err),
+            _ => None,
+        }
+    }
+
+    fn as_serde_error(&self) -> Option<&serde_json::Error> {
+        match self {
+            MockError::Serde(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<MockError> for ProviderError {
+    fn from(src: MockError) -> Self {
+        ProviderError::JsonRpcClientError(Box::new(src))
+    }
+}
+
+/// Storage of a deployed `AAChannel` contract.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MockChannelState {
+    pub balance_a: u128,
+    pub balance_b: u128,
+    pub dispute_start_nonce: u128,
+    pub dispute_value: i128,
+    pub dispute_timestamp: u64,
+}
+
+#[derive(Default)]
+struct MockChain {
+    balances: HashMap<Address, U256>,
+    channels: HashMap<Address, MockChannelState>,
+    user_operations: Vec<UserOp>,
+    bundler_stalled: bool,
+    head: U64,
+}
+
+/// Answers the handful of RPC methods the lib uses from shared in-memory state. Clones share the
+/// same chain, so one handle can be given to a [`Provider`] and the other kept to set up and
+/// inspect the chain.
+#[derive(Clone, Default)]
+pub struct MockClient {
+    chain: Arc<Mutex<MockChain>>,
+}
+
+impl Debug for MockClient {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MockClient").finish_non_exhaustive()
+    }
+}
+
+impl MockClient {
+    pub fn mocked() -> (MockMiddleware, MockClient) {
+        let client = MockClient::default();
+        (Provider::new(client.clone()), client)
+    }
+
+    pub fn set_balance(&self, address: Address, balance: U256) {
+        self.chain.lock().unwrap().balances.insert(address, balance);
+    }
+
+    pub fn deploy_channel(&self, address: Address, state: MockChannelState) {
+        self.chain.lock().unwrap().channels.insert(address, state);
+    }
+
+    pub fn channel(&self, address: Address) -> Option<MockChannelState> {
+        self.chain.lock().unwrap().channels.get(&address).copied()
+    }
+
+    /// Advances the head of the chain.
+    pub fn mine(&self, blocks: u64) {
+        self.chain.lock().unwrap().head += U64::from(blocks);
+    }
+
+    /// All user operations submitted to the bundler so far, oldest first.
+    pub fn user_operations(&self) -> Vec<UserOp> {
+        self.chain.lock().unwrap().user_operations.clone()
+    }
+
+    /// Makes the bundler hang on every submitted userop until released, to interrupt operations
+    /// while they submit.
+    pub fn stall_bundler(&self, stalled: bool) {
+        self.chain.lock().unwrap().bundler_stalled = stalled;
+    }
+
+    fn handle(&self, method: &str, params: Value) -> Result<Value, MockError> {
+        let mut chain = self.chain.lock().unwrap();
+        match method {
+            "eth_getCode" => {
+                let address: Address = serde_json::from_value(params[0].clone())?;
+                Ok(json!(if chain.channels.contains_key(&address) {
+                    Bytes::from(vec![0])
+                } else {
+                    Bytes::new()
+                }))
+            }
+            "eth_getBalance" => {
+                let address: Address = serde_json::from_value(params[0].clone())?;
+                Ok(json!(chain
+                    .balances
+                    .get(&address)
+                    .copied()
+                    .unwrap_or_default()))
+            }
+            "eth_blockNumber" => Ok(json!(chain.head)),
+            "eth_getBlockByNumber" => {
+                // every tag is the head, blocks only have a number
+                let number = serde_json::from_value(params[0].clone()).unwrap_or(chain.head);
+                Ok(json!(Block::<H256> {
+                    number: Some(number),
+                    ..Block::default()
+                }))
+            }
+            "eth_call" => {
+                let to: Address = serde_json::from_value(params[0]["to"].clone())?;
+                let data = params[0]
+                    .get("data")
+                    .or_else(|| params[0].get("input"))
+                    .cloned()
+                    .unwrap_or_default();
+                let data: Bytes = serde_json::from_value(data)?;
+                Ok(json!(chain.call(to, &data)?))
+            }
+            "eth_sendUserOperation" => {
+                let userop: UserOp = serde_json::from_value(params[0].clone())?;
+                chain.user_operations.push(userop);
+                Ok(json!(H256(keccak256(params[0].to_string()))))
+            }
+            _ => Err(MockError::UnsupportedMethod(method.to_string())),
+        }
+    }
+}
+
+impl MockChain {
+    fn call(&self, to: Address, data: &Bytes) -> Result<Bytes, MockError> {
+        if to == MULTICALL3 {
+            return self.multicall(data);
+        }
+        if let Some(channel) = self.channels.get(&to) {
+            let token = match AAChannelCalls::decode(data) {
+                Ok(AAChannelCalls::BalanceA(_)) => Token::Uint(channel.balance_a.into()),
+                Ok(AAChannelCalls::BalanceB(_)) => Token::Uint(channel.balance_b.into()),
+                Ok(AAChannelCalls::DisputeStartNonce(_)) => {
+                    Token::Uint(channel.dispute_start_nonce.into())
+                }
+                Ok(AAChannelCalls::DisputeValue(_)) => {
+                    Token::Int(I256::from(channel.dispute_value).into_raw())
+                }
+                Ok(AAChannelCalls::DisputeTimestamp(_)) => {
+                    Token::Uint(channel.dispute_timestamp.into())
+                }
+                _ => return Err(MockError::UnsupportedCall(to)),
+            };
+            return Ok(abi::encode(&[token]).into());
+        }
+        if let Ok(IEntryPointCalls::BalanceOf(BalanceOfCall { account })) =
+            IEntryPointCalls::decode(data)
+        {
+            // deployed channels keep their funds in the deposit
+            let deposit = self
+                .channels
+                .get(&account)
+                .map_or(0, |channel| channel.balance_a + channel.balance_b);
+            return Ok(abi::encode(&[Token::Uint(deposit.into())]).into());
+        }
+        if let Ok(IEntryPointCalls::SimulateValidation(_)) = IEntryPointCalls::decode(data) {
+            // the mocked entry point accepts every op
+            let result = IEntryPointErrors::ValidationResult(ValidationResult::default());
+            return Err(MockError::Revert(JsonRpcError {
+                code: 3,
+                message: "execution reverted".to_string(),
+                data: Some(json!(Bytes::from(result.encode()))),
+            }));
+        }
+        match AAChannelFactoryCalls::decode(data) {
+            Ok(AAChannelFactoryCalls::GetAddress(GetAddressCall {
+                party_a,
+                party_b,
+                salt,
+            })) => Ok(
+                // the address a real factory at this address would report
+                abi::encode(&[Token::Address(channel_address(to, party_a, party_b, salt))]).into(),
+            ),
+            _ => Err(MockError::UnsupportedCall(to)),
+        }
+    }
+
+    /// Multicall3 as deployed on every chain, supporting `aggregate3` and `getEthBalance`.
+    fn multicall(&self, data: &Bytes) -> Result<Bytes, MockError> {
+        if let Ok(GetEthBalanceCall { addr }) = GetEthBalanceCall::decode(data) {
+            let balance = self.balances.get(&addr).copied().unwrap_or_default();
+            return Ok(GetEthBalanceReturn { balance }.encode().into());
+        }
+        let Ok(Aggregate3Call { calls }) = Aggregate3Call::decode(data) else {
+            return Err(MockError::UnsupportedCall(MULTICALL3));
+        };
+        let return_data = calls
+            .into_iter()
+            .map(|call| {
+                // calls to accounts without code succeed without output
+                if call.target != MULTICALL3 && !self.channels.contains_key(&call.target) {
+                    return CallResult {
+                        success: true,
+                        return_data: Bytes::new(),
+                    };
+                }
+                match self.call(call.target, &call.call_data) {
+                    Ok(return_data) => CallResult {
+                        success: true,
+                        return_data,
+                    },
+                    Err(_) => CallResult::default(),
+                }
+            })
+            .collect();
+        Ok(Aggregate3Return { return_data }.encode().into())
+    }
+}
+
+#[async_trait]
+impl JsonRpcClient for MockClient {
+    type Error = MockError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, Self::Error>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        let stalled = self.chain.lock().unwrap().bundler_stalled;
+        if stalled && method == "eth_sendUserOperation" {
+            std::future::pending::<()>().await;
+        }
+        let response = self.handle(method, serde_json::to_value(params)?)?;
+        Ok(serde_json::from_value(response)?)
+    }
+}
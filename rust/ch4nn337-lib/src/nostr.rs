@@ -0,0 +1,109 @@
+//! A [`Transport`] over Nostr: messages are published as encrypted direct messages between the
+//! channel keys, which are valid Nostr keys as both use secp256k1. No infrastructure of our own
+//! is needed, any public Nostr relay will do.
+use crate::envelope::EnvelopeError;
+use crate::transport::Transport;
+use crate::Channel;
+use async_trait::async_trait;
+use nostr_sdk::prelude::*;
+use std::time::Duration;
+use thiserror::Error;
+use tracing::warn;
+
+#[derive(Error, Debug)]
+pub enum NostrError {
+    #[error("{0}")]
+    Key(#[from] nostr_sdk::secp256k1::Error),
+    #[error("{0}")]
+    Client(#[from] nostr_sdk::client::Error),
+    #[error("{0}")]
+    Envelope(#[from] EnvelopeError),
+}
+
+pub struct NostrTransport {
+    relays: Vec<String>,
+    /// how long to wait for relays to answer a query
+    timeout: Duration,
+}
+
+impl NostrTransport {
+    pub fn new(relays: Vec<String>) -> NostrTransport {
+        NostrTransport {
+            relays,
+            timeout: Duration::from_secs(10),
+        }
+    }
+
+    async fn client(&self, keys: &Keys) -> Result<Client, NostrError> {
+        let client = Client::new(keys);
+        for relay in &self.relays {
+            client.add_relay(relay.as_str(), None).await?;
+        }
+        client.connect().await;
+        Ok(client)
+    }
+}
+
+fn our_key(channel: &Channel) -> Result<SecretKey, NostrError> {
+    Ok(SecretKey::from_slice(channel.key.as_bytes())?)
+}
+
+fn their_key(channel: &Channel) -> Result<XOnlyPublicKey, NostrError> {
+    // drop the parity byte of the compressed key
+    let key = channel
+        .counterparty_key
+        .as_deref()
+        .and_then(|key| key.get(1..))
+        .ok_or(EnvelopeError::UnknownKey)?;
+    Ok(XOnlyPublicKey::from_slice(key)?)
+}
+
+#[async_trait]
+impl Transport for NostrTransport {
+    type Error = NostrError;
+
+    async fn send(&self, channel: &Channel, message: &str) -> Result<(), NostrError> {
+        let client = self.client(&Keys::new(our_key(channel)?)).await?;
+        client
+            .send_direct_msg(their_key(channel)?, channel.seal(message)?, None)
+            .await?;
+        client.disconnect().await?;
+        Ok(())
+    }
+
+    /// The cursor is a unix timestamp.
+    async fn receive(
+        &self,
+        channel: &Channel,
+        since: u64,
+    ) -> Result<(Vec<String>, u64), NostrError> {
+        let our_key = our_key(channel)?;
+        let keys = Keys::new(our_key);
+        let their_key = their_key(channel)?;
+        let client = self.client(&keys).await?;
+        let filter = Filter::new()
+            .kind(Kind::EncryptedDirectMessage)
+            .author(their_key)
+            .pubkey(keys.public_key())
+            .since(Timestamp::from(since));
+        let mut events = client
+            .get_events_of(vec![filter], Some(self.timeout))
+            .await?;
+        client.disconnect().await?;
+
+        events.sort_by_key(|event| event.created_at);
+        let mut next = since;
+        let mut messages = vec![];
+        for event in events {
+            next = next.max(event.created_at.as_u64() + 1);
+            let message = nip04::decrypt(&our_key, &their_key, &event.content)
+                .map_err(|err| err.to_string())
+                .and_then(|sealed| channel.unseal(&sealed).map_err(|err| err.to_string()));
+            match message {
+                Ok(message) => messages.push(message),
+                Err(err) => warn!("skipping direct message: {err}"),
+            }
+        }
+        Ok((messages, next))
+    }
+}
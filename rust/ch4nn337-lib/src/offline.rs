@@ -0,0 +1,197 @@
+//! Requesting transfers without a connection to the chain, e.g. on a plane. Our balance and
+//! whether the channel is deployed are taken from the account as last read with
+//! [`Channel::refresh`], which has to be younger than a given age.
+//!
+//! A transfer requested offline is only checked against that state. It stays unreconciled until
+//! [`Channel::reconcile`] checks it against the chain again, which drops it if our balance no
+//! longer covers it or the channel was deployed in the meantime, making its init code invalid.
+//! The counterparty checks it on-chain when receiving it anyway, so this is about us not relying
+//! on a transfer that will be refused.
+use crate::chain::ChainClient;
+use crate::operation::bounded;
+use crate::protocol::OutgoingMessage;
+use crate::snapshot::AccountState;
+use crate::{now, verify, Channel, Error, Message};
+use ch4nn337_sys::aa_channel::{
+    BalanceACall, BalanceAReturn, BalanceBCall, BalanceBReturn, DisputeStartNonceCall,
+    DisputeStartNonceReturn, DisputeTimestampCall, DisputeTimestampReturn, DisputeValueCall,
+    DisputeValueReturn,
+};
+use ethers::types::BlockId;
+use serde::{Deserialize, Serialize};
+use std::num::NonZeroU128;
+use std::sync::Arc;
+use thiserror::Error;
+use tracing::{info, instrument};
+
+#[derive(Error, Debug)]
+pub enum OfflineError {
+    #[error("channel state is unknown, refresh it while online")]
+    Unknown,
+    #[error("channel state was read {0}s ago, refresh it while online")]
+    Stale(u64),
+    #[error("block to read the channel at not found")]
+    UnknownBlock,
+    #[error("pending transfer would be refused on-chain and was dropped")]
+    Invalidated,
+}
+
+/// The channel account as last read, kept with the channel.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KnownState {
+    pub account: AccountState,
+    /// block the account was read at
+    pub block: u64,
+    /// unix time it was read
+    pub observed_at: u64,
+}
+
+impl KnownState {
+    /// Seconds since the account was read.
+    pub fn age(&self) -> u64 {
+        now().saturating_sub(self.observed_at)
+    }
+}
+
+impl Channel {
+    /// Reads the account of the channel and keeps it for requesting transfers offline. Reads
+    /// against the trusted block if one is set, see [`verify`].
+    #[instrument(skip_all, fields(channel = ?self.address))]
+    pub async fn refresh<C: ChainClient + ?Sized>(
+        &mut self,
+        client: Arc<C>,
+    ) -> Result<KnownState, Error> {
+        let known = bounded(self.operation.timeout, async {
+            let block = match self.trusted_block {
+                Some(hash) => BlockId::Hash(hash),
+                None => {
+                    self.block_policy
+                        .resolve(client.as_ref(), &self.retry)
+                        .await?
+                }
+            };
+            let number = self
+                .retry
+                .run(|| client.block(block), |_| true)
+                .await?
+                .and_then(|block| block.number)
+                .ok_or(OfflineError::UnknownBlock)?;
+            let account = match self.trusted_block {
+                Some(hash) => {
+                    verify::verified_account(client.as_ref(), &self.retry, self.address, hash)
+                        .await?
+                }
+                None => self.account_at(&client, number.into()).await?,
+            };
+            Ok::<_, Error>(KnownState {
+                account,
+                block: number.as_u64(),
+                observed_at: now(),
+            })
+        })
+        .await?;
+        self.known_state = Some(known);
+        Ok(known)
+    }
+
+    /// The account as last read with [`Channel::refresh`].
+    pub fn known_state(&self) -> Option<KnownState> {
+        self.known_state
+    }
+
+    /// Like [`Channel::request_transfer_batch`], but checked against the account as last read
+    /// instead of the chain, which must not be older than `max_age` seconds.
+    #[instrument(skip_all, fields(channel = ?self.address, items = payments.len()))]
+    pub async fn request_transfer_offline(
+        &mut self,
+        payments: Vec<(NonZeroU128, Option<String>)>,
+        max_age: u64,
+    ) -> Result<OutgoingMessage, Error> {
+        self.check_signer(true)?;
+        let known = self.known_state.ok_or(OfflineError::Unknown)?;
+        if known.age() > max_age {
+            return Err(OfflineError::Stale(known.age()).into());
+        }
+        let mut wei = 0u128;
+        for (amount, _) in &payments {
+            wei = wei
+                .checked_add(amount.get())
+                .ok_or(Error::InsufficientBalance)?;
+        }
+        let wei = NonZeroU128::new(wei).ok_or(Error::EmptyBatch)?;
+        let (balance, _) = self.balances_at(&known.account)?;
+        let now = now();
+        let (userop, next) =
+            self.transfer_userop_with(wei, now, balance, known.account.deployed)?;
+        let request = self.sign_transfer(userop, next, payments, now).await?;
+        self.unreconciled = true;
+        Ok(request)
+    }
+
+    /// Whether the pending message, if any, was checked on-chain.
+    pub fn is_reconciled(&self) -> bool {
+        !self.unreconciled || self.pending_message.is_none()
+    }
+
+    /// Refreshes the account and checks a pending transfer requested offline against it,
+    /// dropping it with [`OfflineError::Invalidated`] if it would be refused.
+    #[instrument(skip_all, fields(channel = ?self.address))]
+    pub async fn reconcile<C: ChainClient + ?Sized>(
+        &mut self,
+        client: Arc<C>,
+    ) -> Result<(), Error> {
+        let known = self.refresh(client).await?;
+        if self.is_reconciled() {
+            self.unreconciled = false;
+            return Ok(());
+        }
+        let Some(Message::Transfer(pending)) = &self.pending_message else {
+            self.unreconciled = false;
+            return Ok(());
+        };
+        let (balance, _) = self.balances_at(&known.account)?;
+        let wei = self.our_delta(pending.value_transfer).unsigned_abs();
+        let init_code = !pending.userop.init_code.is_empty();
+        self.unreconciled = false;
+        if balance.0 < wei || (init_code && known.account.deployed) {
+            info!("dropping transfer requested offline");
+            self.pending_message = None;
+            return Err(OfflineError::Invalidated.into());
+        }
+        Ok(())
+    }
+
+    /// Reads the account with plain calls at the block.
+    async fn account_at<C: ChainClient + ?Sized>(
+        &self,
+        client: &Arc<C>,
+        block: BlockId,
+    ) -> Result<AccountState, Error> {
+        let balance = self
+            .retry
+            .run(|| client.get_balance(self.address, block), |_| true)
+            .await?;
+        if !self.deployed_at(client, block).await? {
+            return Ok(AccountState {
+                balance,
+                ..AccountState::default()
+            });
+        }
+        let BalanceAReturn(balance_a) = self.view(client, BalanceACall, block).await?;
+        let BalanceBReturn(balance_b) = self.view(client, BalanceBCall, block).await?;
+        let DisputeStartNonceReturn(dispute_start_nonce) =
+            self.view(client, DisputeStartNonceCall, block).await?;
+        let DisputeValueReturn(dispute_value) = self.view(client, DisputeValueCall, block).await?;
+        let DisputeTimestampReturn(dispute_timestamp) =
+            self.view(client, DisputeTimestampCall, block).await?;
+        Ok(AccountState {
+            balance,
+            deployed: true,
+            balance_a,
+            balance_b,
+            dispute_start_nonce,
+            dispute_value,
+            dispute_timestamp,
+        })
+    }
+}
@@ -0,0 +1,40 @@
+//! Bounds for operations that touch the chain, so callers like servers are not stalled forever by
+//! a dead RPC.
+use crate::Error;
+use std::future::Future;
+use std::time::Duration;
+
+#[derive(Clone, Debug)]
+pub struct OperationConfig {
+    /// limit for a whole operation including its retries, none by default
+    pub timeout: Option<Duration>,
+    /// blocks that have to be on top of a transaction, including its own, before it counts
+    pub confirmations: usize,
+    /// how often pending transactions are polled
+    pub poll_interval: Duration,
+}
+
+impl Default for OperationConfig {
+    fn default() -> Self {
+        OperationConfig {
+            timeout: None,
+            confirmations: 1,
+            poll_interval: Duration::from_secs(7),
+        }
+    }
+}
+
+/// Runs an operation, failing with [`Error::Timeout`] if it does not finish in time. A timed out
+/// operation is dropped like any other future, so operations must not leave the channel
+/// inconsistent at an await point.
+pub(crate) async fn bounded<T>(
+    timeout: Option<Duration>,
+    operation: impl Future<Output = Result<T, Error>>,
+) -> Result<T, Error> {
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, operation)
+            .await
+            .map_err(|_| Error::Timeout)?,
+        None => operation.await,
+    }
+}
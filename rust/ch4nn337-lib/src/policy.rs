@@ -0,0 +1,75 @@
+//! Spending limits for our side of a channel, checked before we sign anything that pays the
+//! counterparty.
+use crate::amount::Wei;
+use ethers::types::Address;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+const DAY: u64 = 24 * 60 * 60;
+
+#[derive(Error, Debug)]
+pub enum Violation {
+    #[error("transfer of {amount} exceeds the limit of {limit}")]
+    MaxTransfer { amount: Wei, limit: Wei },
+    #[error("outflow within a day would reach {outflow}, exceeding the limit of {limit}")]
+    MaxDailyOutflow { outflow: Wei, limit: Wei },
+    #[error("counterparty {0:?} is not allowed")]
+    Counterparty(Address),
+}
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct Policy {
+    pub max_transfer: Option<Wei>,
+    pub max_daily_outflow: Option<Wei>,
+    /// if set, only these counterparties may be paid
+    pub allowed_counterparties: Option<Vec<Address>>,
+    /// timestamps and amounts of outgoing transfers within the last day
+    #[serde(default)]
+    outflows: Vec<(u64, Wei)>,
+}
+
+impl Policy {
+    pub fn daily_outflow(&self, now: u64) -> Wei {
+        self.outflows
+            .iter()
+            .filter(|(timestamp, _)| timestamp + DAY > now)
+            .fold(Wei::ZERO, |sum, (_, amount)| sum.saturating_add(*amount))
+    }
+
+    pub(crate) fn check_outflow(
+        &self,
+        counterparty: Address,
+        amount: Wei,
+        now: u64,
+    ) -> Result<(), Violation> {
+        if let Some(allowed) = &self.allowed_counterparties {
+            if !allowed.contains(&counterparty) {
+                return Err(Violation::Counterparty(counterparty));
+            }
+        }
+        if let Some(limit) = self.max_transfer {
+            if amount > limit {
+                return Err(Violation::MaxTransfer { amount, limit });
+            }
+        }
+        if let Some(limit) = self.max_daily_outflow {
+            let outflow = self.daily_outflow(now).saturating_add(amount);
+            if outflow > limit {
+                return Err(Violation::MaxDailyOutflow { outflow, limit });
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn record_outflow(&mut self, amount: Wei, now: u64) {
+        self.outflows.retain(|(timestamp, _)| timestamp + DAY > now);
+        self.outflows.push((now, amount));
+    }
+
+    /// Replaces the limits, keeping track of what was already spent today.
+    pub(crate) fn update(&mut self, policy: Policy) {
+        self.max_transfer = policy.max_transfer;
+        self.max_daily_outflow = policy.max_daily_outflow;
+        self.allowed_counterparties = policy.allowed_counterparties;
+    }
+}
@@ -0,0 +1,124 @@
+//! Dry runs of the state-changing operations. A [`Preview`] is built exactly like the real
+//! operation, but the userop is not signed and nothing is recorded in the channel, so it can be
+//! inspected before committing to it.
+use crate::amount::{AmountError, Wei};
+use crate::chain::ChainClient;
+use crate::operation::bounded;
+use crate::{now, Channel, Error, Party, Summary};
+use ch4nn337_sys::aa_channel::AAChannelCalls;
+use ch4nn337_sys::aa_channel_factory::{AAChannelFactory, CreateAccountCall};
+use ethers::abi::AbiDecode;
+use ethers::providers::Middleware;
+use ethers::types::userop::UserOp;
+use ethers::types::{Address, U256};
+use std::num::NonZeroU128;
+use std::sync::Arc;
+
+pub struct Preview {
+    /// the userop as it would be sent, without signature
+    pub userop: UserOp,
+    /// the decoded calldata of the userop
+    pub call: AAChannelCalls,
+    pub summary: Summary,
+}
+
+pub struct DeployPreview {
+    pub factory: Address,
+    pub call: CreateAccountCall,
+    /// estimated gas of the deployment transaction, `None` if the channel is deployed already
+    pub gas: Option<U256>,
+}
+
+impl Channel {
+    /// Previews [`Channel::request_transfer`] of `wei` to the counterparty.
+    pub async fn preview_transfer<C: ChainClient + ?Sized>(
+        &self,
+        wei: NonZeroU128,
+        client: Arc<C>,
+    ) -> Result<Preview, Error> {
+        bounded(self.operation.timeout, async {
+            let (our_balance, their_balance) = self.get_sorted_balances(client.clone()).await?;
+            let (userop, _) = self.transfer_userop(wei, now(), client).await?;
+            let amount = Wei(wei.get());
+            Ok::<_, Error>(Preview {
+                call: decode(&userop)?,
+                userop,
+                summary: Summary::Transfer {
+                    amount,
+                    incoming: false,
+                    our_balance: our_balance
+                        .checked_sub(amount)
+                        .ok_or(AmountError::Overflow)?,
+                    their_balance: their_balance
+                        .checked_add(amount)
+                        .ok_or(AmountError::Overflow)?,
+                },
+            })
+        })
+        .await
+    }
+
+    /// Previews [`Channel::request_full_withdraw`], including what the withdrawal pays out after
+    /// fees.
+    pub async fn preview_full_withdraw<C: ChainClient + ?Sized>(
+        &self,
+        client: Arc<C>,
+    ) -> Result<Preview, Error> {
+        bounded(self.operation.timeout, async {
+            let (userop, withdraw_a, withdraw_b) = self.withdrawal_userop(client.clone()).await?;
+            let payout = self
+                .withdrawal_payout(&userop, withdraw_a, withdraw_b, client)
+                .await?;
+            let (withdraw_us, withdraw_them) = match self.us {
+                Party::A => (Wei(withdraw_a), Wei(withdraw_b)),
+                Party::B => (Wei(withdraw_b), Wei(withdraw_a)),
+            };
+            Ok::<_, Error>(Preview {
+                call: decode(&userop)?,
+                userop,
+                summary: Summary::Withdrawal {
+                    withdraw_us,
+                    withdraw_them,
+                    payout,
+                },
+            })
+        })
+        .await
+    }
+
+    /// Previews [`Channel::deploy`].
+    pub async fn preview_deploy<M: Middleware + 'static>(
+        &self,
+        client: Arc<M>,
+    ) -> Result<DeployPreview, Error> {
+        bounded(self.operation.timeout, async {
+            let (party_a, party_b) = self.parties()?;
+            let deployed = self.is_deployed(&client).await?;
+            let call = AAChannelFactory::new(self.factory, client)
+                .create_account(party_a, party_b, self.salt);
+            let gas = if deployed {
+                None
+            } else {
+                Some(
+                    self.retry
+                        .run(|| call.estimate_gas(), |err| !err.is_revert())
+                        .await?,
+                )
+            };
+            Ok::<_, Error>(DeployPreview {
+                factory: self.factory,
+                call: CreateAccountCall {
+                    party_a,
+                    party_b,
+                    salt: self.salt,
+                },
+                gas,
+            })
+        })
+        .await
+    }
+}
+
+fn decode(userop: &UserOp) -> Result<AAChannelCalls, Error> {
+    AAChannelCalls::decode(&userop.call_data).map_err(|_| Error::IllegalCalldata)
+}
@@ -0,0 +1,181 @@
+//! Exchange rates for showing and requesting amounts in fiat currencies. Prices are fixed point
+//! numbers with [`PRICE_DECIMALS`] decimals, and so are fiat amounts.
+use async_trait::async_trait;
+use ethers::contract::abigen;
+use ethers::providers::Middleware;
+use ethers::types::{Address, U256};
+use std::fmt;
+use std::sync::Arc;
+use thiserror::Error;
+
+pub const PRICE_DECIMALS: u32 = 8;
+const WEI_PER_ETH: u128 = 1_000_000_000_000_000_000;
+
+abigen!(
+    AggregatorV3,
+    r#"[
+        function decimals() external view returns (uint8)
+        function latestRoundData() external view returns (uint80, int256, uint256, uint256, uint80)
+    ]"#
+);
+
+#[derive(Error, Debug)]
+pub enum PriceError {
+    #[error("{0}")]
+    Http(#[from] reqwest::Error),
+    #[error("{0}")]
+    Feed(String),
+    #[error("no price in the response")]
+    Missing,
+    #[error("invalid price")]
+    Invalid,
+}
+
+/// The price of one ETH in a currency.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Price {
+    pub currency: String,
+    pub per_eth: u128,
+}
+
+impl Price {
+    /// Converts a fiat amount to wei, rounding down.
+    pub fn to_wei(&self, fiat: u128) -> u128 {
+        (U256::from(fiat) * U256::from(WEI_PER_ETH) / U256::from(self.per_eth)).low_u128()
+    }
+
+    /// Converts wei to a fiat amount, rounding down.
+    pub fn to_fiat(&self, wei: u128) -> u128 {
+        (U256::from(wei) * U256::from(self.per_eth) / U256::from(WEI_PER_ETH)).low_u128()
+    }
+}
+
+impl fmt::Display for Price {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}/ETH", format_fiat(self.per_eth), self.currency)
+    }
+}
+
+/// Formats a fiat amount with cents, e.g. `5.00`.
+pub fn format_fiat(amount: u128) -> String {
+    let cents = amount / 10u128.pow(PRICE_DECIMALS - 2);
+    format!("{}.{:02}", cents / 100, cents % 100)
+}
+
+/// Parses an amount like `5.00USD` into the fiat amount and the currency.
+pub fn parse_fiat(amount: &str) -> Option<(u128, String)> {
+    let split = amount.find(|c: char| c.is_ascii_alphabetic())?;
+    let (number, currency) = amount.split_at(split);
+    let (whole, fraction) = number.split_once('.').unwrap_or((number, ""));
+    if fraction.len() > PRICE_DECIMALS as usize {
+        return None;
+    }
+    let scale = 10u128.pow(PRICE_DECIMALS);
+    let whole: u128 = whole.parse().ok()?;
+    let fraction: u128 = match fraction {
+        "" => 0,
+        fraction => {
+            fraction.parse::<u128>().ok()? * 10u128.pow(PRICE_DECIMALS - fraction.len() as u32)
+        }
+    };
+    Some((
+        whole.checked_mul(scale)?.checked_add(fraction)?,
+        currency.to_ascii_uppercase(),
+    ))
+}
+
+#[async_trait]
+pub trait PriceSource {
+    /// The current price of one ETH.
+    async fn price(&self) -> Result<Price, PriceError>;
+}
+
+/// Reads a Chainlink ETH price feed.
+pub struct ChainlinkFeed<M> {
+    feed: AggregatorV3<M>,
+    currency: String,
+}
+
+impl<M: Middleware> ChainlinkFeed<M> {
+    pub fn new(feed: Address, currency: impl Into<String>, client: Arc<M>) -> ChainlinkFeed<M> {
+        ChainlinkFeed {
+            feed: AggregatorV3::new(feed, client),
+            currency: currency.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl<M: Middleware + 'static> PriceSource for ChainlinkFeed<M> {
+    async fn price(&self) -> Result<Price, PriceError> {
+        let feed_error =
+            |err: ethers::contract::ContractError<M>| PriceError::Feed(err.to_string());
+        let decimals = self.feed.decimals().call().await.map_err(feed_error)?;
+        let (_, answer, _, _, _) = self
+            .feed
+            .latest_round_data()
+            .call()
+            .await
+            .map_err(feed_error)?;
+        if answer.is_negative() {
+            return Err(PriceError::Invalid);
+        }
+        let answer = answer.into_raw();
+        let per_eth = if u32::from(decimals) >= PRICE_DECIMALS {
+            answer / U256::exp10(usize::from(decimals) - PRICE_DECIMALS as usize)
+        } else {
+            answer * U256::exp10(PRICE_DECIMALS as usize - usize::from(decimals))
+        };
+        Ok(Price {
+            currency: self.currency.clone(),
+            per_eth: u128::try_from(per_eth).map_err(|_| PriceError::Invalid)?,
+        })
+    }
+}
+
+/// Fetches the price as JSON over HTTP, e.g. from
+/// `https://api.coingecko.com/api/v3/simple/price?ids=ethereum&vs_currencies=usd` with the
+/// pointer `/ethereum/usd`.
+pub struct HttpSource {
+    url: String,
+    /// JSON pointer to the price in the response
+    pointer: String,
+    currency: String,
+}
+
+impl HttpSource {
+    pub fn new(
+        url: impl Into<String>,
+        pointer: impl Into<String>,
+        currency: impl Into<String>,
+    ) -> HttpSource {
+        HttpSource {
+            url: url.into(),
+            pointer: pointer.into(),
+            currency: currency.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl PriceSource for HttpSource {
+    async fn price(&self) -> Result<Price, PriceError> {
+        let response: serde_json::Value = reqwest::get(&self.url)
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        // accept numbers as well as strings, and avoid floats for the conversion
+        let price = match response.pointer(&self.pointer).ok_or(PriceError::Missing)? {
+            serde_json::Value::Number(number) => number.to_string(),
+            serde_json::Value::String(string) => string.clone(),
+            _ => return Err(PriceError::Invalid),
+        };
+        let (per_eth, _) =
+            parse_fiat(&format!("{price}{}", self.currency)).ok_or(PriceError::Invalid)?;
+        Ok(Price {
+            currency: self.currency.clone(),
+            per_eth,
+        })
+    }
+}
@@ -0,0 +1,104 @@
+//! The encodings channels agree on with the contracts, for tools that interoperate with channels
+//! without a [`Channel`](crate::Channel), e.g. explorers, separate watchtowers or ports to other
+//! languages. Everything here is pure and mirrors the contracts, see [`test_vectors`] for
+//! expected outputs.
+//!
+//! A channel userop calls either `dispute(valueTransfer)`, settling on a transfer, or
+//! `coopWithdraw(valueTransfer, withdrawA, withdrawB)`, withdrawing both balances. The value
+//! transfer is the total transferred from A to B so far, negative if B transferred more. Both are
+//! signed by both parties, see [`combine_signatures`].
+//!
+//! [`test_vectors`]: crate::test_vectors
+use ch4nn337_sys::aa_channel::{AAChannelCalls, CoopWithdrawCall, DisputeCall};
+use ch4nn337_sys::aa_channel_factory::CreateAccountCall;
+use ch4nn337_sys::signature::{decode_pair, encode_pair};
+use ethers::abi::{AbiDecode, AbiEncode};
+use ethers::types::userop::UserOp;
+use ethers::types::{Address, Bytes, H256, U256};
+
+pub use ch4nn337_sys::channel_address::channel_address;
+
+/// The call a channel userop makes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Call {
+    Transfer {
+        value_transfer: i128,
+    },
+    Withdrawal {
+        value_transfer: i128,
+        withdraw_a: u128,
+        withdraw_b: u128,
+    },
+}
+
+/// The hash both parties sign, as the entry point computes it.
+pub fn user_op_hash(userop: &UserOp, entry_point: Address, chain_id: U256) -> H256 {
+    H256(
+        userop
+            .get_user_op_hash(entry_point, chain_id)
+            .expect("userops always encode")
+            .0,
+    )
+}
+
+/// The init code deploying the channel through the factory, for userops sent before the channel
+/// is deployed: the factory address followed by its `createAccount` call.
+pub fn init_code(factory: Address, party_a: Address, party_b: Address, salt: U256) -> Bytes {
+    factory
+        .to_fixed_bytes()
+        .into_iter()
+        .chain(AbiEncode::encode(CreateAccountCall {
+            party_a,
+            party_b,
+            salt,
+        }))
+        .collect()
+}
+
+/// Combines the signatures of party A and B over the userop hash into the signature of the
+/// userop.
+pub fn combine_signatures(signature_a: Bytes, signature_b: Bytes) -> Bytes {
+    encode_pair(signature_a, signature_b)
+}
+
+/// Splits the signature of a userop into those of party A and B, `None` if it is not a pair.
+pub fn split_signatures(signature: &[u8]) -> Option<(Bytes, Bytes)> {
+    decode_pair(signature)
+}
+
+/// The calldata of a userop making the call.
+pub fn encode_call(call: Call) -> Bytes {
+    match call {
+        Call::Transfer { value_transfer } => DisputeCall { value_transfer }.encode(),
+        Call::Withdrawal {
+            value_transfer,
+            withdraw_a,
+            withdraw_b,
+        } => CoopWithdrawCall {
+            value_transfer,
+            withdraw_a,
+            withdraw_b,
+        }
+        .encode(),
+    }
+    .into()
+}
+
+/// The call made by the calldata of a userop, `None` for anything but a transfer or withdrawal.
+pub fn decode_call(call_data: &[u8]) -> Option<Call> {
+    match AAChannelCalls::decode(call_data).ok()? {
+        AAChannelCalls::Dispute(DisputeCall { value_transfer }) => {
+            Some(Call::Transfer { value_transfer })
+        }
+        AAChannelCalls::CoopWithdraw(CoopWithdrawCall {
+            value_transfer,
+            withdraw_a,
+            withdraw_b,
+        }) => Some(Call::Withdrawal {
+            value_transfer,
+            withdraw_a,
+            withdraw_b,
+        }),
+        _ => None,
+    }
+}
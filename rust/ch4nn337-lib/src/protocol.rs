@@ -0,0 +1,89 @@
+//! Versioning of the messages exchanged with the counterparty. Every message carries the version
+//! of the protocol it was written in next to its userop, and both parties agree on a version while
+//! opening the channel, see [`ceremony`](crate::ceremony). Messages of a newer version are refused
+//! instead of being misread.
+//!
+//! Version 0 is the protocol before versioning, whose messages are bare userops. Channels stored
+//! before versioning keep speaking it. Later versions only add a field, so their messages remain
+//! readable for such peers.
+//!
+//! Outgoing messages are handed out as [`OutgoingMessage`]s, leaving the encoding to the caller.
+use crate::qr::to_qr_bytes;
+use crate::{Channel, Error};
+use ethers::types::userop::UserOp;
+use serde::{Deserialize, Serialize};
+
+pub const PROTOCOL_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct WireMessage {
+    #[serde(default)]
+    protocol: u32,
+    #[serde(flatten)]
+    userop: UserOp,
+}
+
+/// A message for the counterparty, e.g. a request to sign or our countersigned response.
+#[derive(Clone, Debug)]
+pub struct OutgoingMessage {
+    protocol: u32,
+    userop: UserOp,
+}
+
+impl OutgoingMessage {
+    pub fn userop(&self) -> &UserOp {
+        &self.userop
+    }
+
+    /// The protocol version the message is written in.
+    pub fn protocol(&self) -> u32 {
+        self.protocol
+    }
+
+    /// The JSON the counterparty reads with [`Channel::receive_message`] or
+    /// [`Channel::receive_response`].
+    pub fn to_json(&self) -> String {
+        match self.protocol {
+            0 => serde_json::to_string(&self.userop),
+            protocol => serde_json::to_string(&WireMessage {
+                protocol,
+                userop: self.userop.clone(),
+            }),
+        }
+        .expect("userops serialize to JSON")
+    }
+
+    /// The JSON compressed, e.g. for QR codes, see [`qr`](crate::qr).
+    pub fn to_compact(&self) -> Vec<u8> {
+        to_qr_bytes(&self.to_json())
+    }
+}
+
+/// The version spoken with a peer supporting up to `theirs`.
+pub fn negotiate(theirs: u32) -> u32 {
+    theirs.min(PROTOCOL_VERSION)
+}
+
+/// Reads a message of the counterparty, refusing versions newer than ours.
+pub fn decode_message(message: &str) -> Result<UserOp, Error> {
+    let message: WireMessage = serde_json::from_str(message)?;
+    if message.protocol > PROTOCOL_VERSION {
+        return Err(Error::UnsupportedVersion(message.protocol));
+    }
+    Ok(message.userop)
+}
+
+impl Channel {
+    /// The protocol version agreed on with the counterparty.
+    pub fn protocol(&self) -> u32 {
+        self.protocol
+    }
+
+    /// A message for the counterparty in the agreed version.
+    pub(crate) fn outgoing(&self, userop: &UserOp) -> OutgoingMessage {
+        OutgoingMessage {
+            protocol: self.protocol,
+            userop: userop.clone(),
+        }
+    }
+}
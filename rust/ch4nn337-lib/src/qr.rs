@@ -0,0 +1,21 @@
+//! Compressed encoding of the JSON messages exchanged between the parties, small enough to fit
+//! into a QR code.
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use std::io;
+use std::io::{Read, Write};
+
+pub fn to_qr_bytes(message: &str) -> Vec<u8> {
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::best());
+    encoder
+        .write_all(message.as_bytes())
+        .expect("writing to a vec does not fail");
+    encoder.finish().expect("writing to a vec does not fail")
+}
+
+pub fn from_qr_bytes(bytes: &[u8]) -> io::Result<String> {
+    let mut message = String::new();
+    DeflateDecoder::new(bytes).read_to_string(&mut message)?;
+    Ok(message)
+}
@@ -0,0 +1,106 @@
+//! Proof that a transfer was made. A receipt carries the fully signed userop of a transfer, so
+//! anyone can check with [`verify_receipt`] that both parties agreed to it, without access to the
+//! channel or the chain.
+use crate::amount::{AmountError, SignedWei, Wei};
+use crate::chain::ChainClient;
+use crate::{Channel, Error, Message};
+use ch4nn337_sys::aa_channel::DisputeCall;
+use ch4nn337_sys::signature;
+use ethers::abi::AbiDecode;
+use ethers::types::userop::UserOp;
+use ethers::types::{Address, H256, U256};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ReceiptError {
+    #[error("latest message is not a transfer")]
+    NotATransfer,
+    #[error("malformed signature")]
+    MalformedSignature,
+    #[error("not signed by party {0:?}")]
+    WrongSigner(Address),
+    #[error("userop does not transfer the stated value")]
+    ValueMismatch,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PaymentReceipt {
+    pub chain_id: U256,
+    pub entry_point: Address,
+    pub party_a: Address,
+    pub party_b: Address,
+    /// the fully signed userop, carrying the channel, the nonce and both signatures
+    pub userop: UserOp,
+    /// total value moved from A to B, as signed in the userop
+    pub value_transfer: i128,
+    /// value moved by this transfer, positive from A to B
+    pub amount: SignedWei,
+    /// balances after the transfer, as seen by the issuer
+    pub balance_a: Wei,
+    pub balance_b: Wei,
+    /// reference to an invoice, attached by the issuer and not covered by the signatures
+    pub invoice_hash: Option<H256>,
+}
+
+impl Channel {
+    /// Issues a receipt for the latest transfer signed by both parties, which must not be followed
+    /// by a withdrawal.
+    pub async fn payment_receipt<C: ChainClient + ?Sized>(
+        &self,
+        invoice_hash: Option<H256>,
+        client: Arc<C>,
+    ) -> Result<PaymentReceipt, Error> {
+        let Some(Message::Transfer(transfer)) = self.latest_settleable() else {
+            return Err(ReceiptError::NotATransfer.into());
+        };
+        let previous = self
+            .messages
+            .iter()
+            .rev()
+            .filter(|message| message.userop().nonce < transfer.userop.nonce)
+            .find_map(|message| match message {
+                Message::Transfer(message) => Some(message.value_transfer),
+                Message::Withdrawal(_) => None,
+            })
+            .unwrap_or(0);
+        let (balance_a, balance_b) = self.get_balances(client).await?;
+        let (party_a, party_b) = self.parties()?;
+        Ok(PaymentReceipt {
+            chain_id: self.chain_id,
+            entry_point: self.entry_point,
+            party_a,
+            party_b,
+            userop: transfer.userop.clone(),
+            value_transfer: transfer.value_transfer,
+            amount: SignedWei(transfer.value_transfer)
+                .checked_sub(SignedWei(previous))
+                .ok_or(AmountError::Overflow)?,
+            balance_a,
+            balance_b,
+            invoice_hash,
+        })
+    }
+}
+
+/// Checks that both parties signed the userop of the receipt and that it transfers the stated
+/// value. The amount and balances can not be checked without the channel history.
+pub fn verify_receipt(receipt: &PaymentReceipt) -> Result<(), ReceiptError> {
+    let hash = receipt
+        .userop
+        .get_user_op_hash(receipt.entry_point, receipt.chain_id)
+        .map_err(|_| ReceiptError::MalformedSignature)?;
+    let (signer_a, signer_b) = signature::recover_pair(&receipt.userop.signature, hash)
+        .ok_or(ReceiptError::MalformedSignature)?;
+    for (signer, party) in [(signer_a, receipt.party_a), (signer_b, receipt.party_b)] {
+        if signer != party {
+            return Err(ReceiptError::WrongSigner(party));
+        }
+    }
+
+    match DisputeCall::decode(&receipt.userop.call_data) {
+        Ok(call) if call.value_transfer == receipt.value_transfer => Ok(()),
+        _ => Err(ReceiptError::ValueMismatch),
+    }
+}
@@ -0,0 +1,80 @@
+//! Recovery of a channel whose local state was lost, from the key and the on-chain state.
+//!
+//! The off-chain history can not be recovered, so the channel starts from the balances of its
+//! last on-chain operation. Updates signed since then are only known to the counterparty, who has
+//! to resend the latest one, or the channel has to be settled by a dispute.
+use crate::chain::{self, ChainClient, LATEST};
+use crate::contracts::{self, CodeError};
+use crate::inspect::{self, ChannelReport};
+use crate::retry::RetryPolicy;
+use crate::{Channel, ChannelState, Error, Party};
+use ch4nn337_sys::aa_channel_factory::{AaChannelCall, AaChannelReturn};
+use ch4nn337_sys::artifacts::AACHANNEL_DEPLOYED_BYTECODE;
+use ch4nn337_sys::channel_address::channel_address;
+use ethers::core::k256::ecdsa::SigningKey;
+use ethers::signers::{Signer, Wallet};
+use ethers::types::{Address, U256};
+use std::sync::Arc;
+
+impl Channel {
+    /// Rebuilds our side of the channel with the counterparty. Whether we are party A or B is
+    /// found out from the chain, so the channel has to be deployed or funded already.
+    pub async fn recover<C: ChainClient + ?Sized>(
+        key: SigningKey,
+        factory: Address,
+        counterparty: Address,
+        salt: U256,
+        client: Arc<C>,
+    ) -> Result<Channel, Error> {
+        let us = Wallet::from(key.clone()).address();
+        let chain_id = client.chain_id().await?;
+
+        // the entry point is an immutable of the implementation
+        let retry = RetryPolicy::default();
+        let AaChannelReturn(implementation) =
+            chain::view(client.as_ref(), &retry, factory, AaChannelCall, LATEST).await?;
+        let code = client.get_code(implementation, LATEST).await?;
+        let entry_point = contracts::immutable(&code, &AACHANNEL_DEPLOYED_BYTECODE)
+            .ok_or(CodeError::Implementation(implementation))?
+            .into();
+
+        let mut found = None;
+        for (party, party_a, party_b) in
+            [(Party::A, us, counterparty), (Party::B, counterparty, us)]
+        {
+            let address = channel_address(factory, party_a, party_b, salt);
+            let report = inspect::inspect(address, client.clone()).await?;
+            if report.deployed || !report.balance.is_zero() {
+                found = Some((party, report));
+                break;
+            }
+        }
+        let Some((party, report)) = found else {
+            return Err(Error::ChannelNotFound);
+        };
+
+        let mut channel = Channel::new(
+            chain_id,
+            entry_point,
+            factory,
+            party,
+            &key,
+            counterparty,
+            salt,
+        );
+        channel.state = recovered_state(&report);
+        channel.recovered_nonce = (report.nonce > 0).then(|| report.nonce.into());
+        Ok(channel)
+    }
+}
+
+fn recovered_state(report: &ChannelReport) -> ChannelState {
+    if report.dispute.is_some() {
+        ChannelState::Disputed
+    } else if report.deployed && report.nonce > 0 && report.balance_a == 0 && report.balance_b == 0
+    {
+        ChannelState::Closed
+    } else {
+        ChannelState::Open
+    }
+}
@@ -0,0 +1,704 @@
+//! A store-and-forward relay, so two parties that can not reach each other directly can still
+//! exchange messages. Messages are sealed to the recipient (see [`crate::envelope`]) and queued
+//! in a mailbox named after the recipient's address until they are fetched.
+//!
+//! Liquidity advertisements are public and go unsealed into the mailbox of [`ADVERT_MAILBOX`].
+//!
+//! The protocol is one JSON request per line over TCP, answered by one JSON response per line.
+//!
+//! Anyone can put messages, so puts are rate limited per peer and have to carry a timestamp close
+//! to the relay's clock. A payload put again while it is remembered is refused, so old messages
+//! can not be replayed into a mailbox to flood the recipient. Gets are rate limited separately,
+//! requests longer than [`MAX_REQUEST`] bytes drop the connection, and mailboxes nobody put to
+//! for [`MAILBOX_TTL`] seconds are forgotten once there are too many. Connections are capped, and
+//! dropped when they stay open too long, so slow peers can not hold on to all of them.
+//!
+//! Every mailbox gets a random epoch when it is created, which is sent along with the index to
+//! continue from. A mailbox that was forgotten, or lost when the relay restarted, starts over with
+//! a new epoch, so a client with a cursor from before fetches it from the start instead of
+//! skipping the messages up to its old index.
+use crate::advert::SignedAdvertisement;
+use crate::envelope::EnvelopeError;
+use crate::transport::Transport;
+use crate::{now, Channel};
+use async_trait::async_trait;
+use ethers::types::Address;
+use ethers::utils::keccak256;
+use rand::rngs::OsRng;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{self, Display, Formatter};
+use std::io;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+use tokio::sync::Semaphore;
+use tracing::{debug, warn};
+
+/// messages kept per mailbox, older ones are dropped
+const MAILBOX_CAPACITY: usize = 1024;
+const MAX_PAYLOAD: usize = 64 * 1024;
+/// bytes of a request line, a put of the largest payload with room for escaping it in JSON
+pub const MAX_REQUEST: usize = 2 * MAX_PAYLOAD + 1024;
+/// mailboxes kept before those nobody put to within [`MAILBOX_TTL`] are forgotten
+const MAX_MAILBOXES: usize = 65536;
+/// seconds an idle mailbox is kept for once there are too many
+pub const MAILBOX_TTL: u64 = 7 * 24 * 60 * 60;
+/// peers with a rate limit tracked before idle ones are forgotten
+const MAX_PEERS: usize = 4096;
+/// the mailbox advertisements are published to, which no key controls
+pub const ADVERT_MAILBOX: Address = Address::zero();
+
+#[derive(Error, Debug)]
+pub enum RelayError {
+    #[error("{0}")]
+    Io(#[from] io::Error),
+    #[error("{0}")]
+    Serde(#[from] serde_json::Error),
+    #[error("{0}")]
+    Envelope(#[from] EnvelopeError),
+    #[error("{0}")]
+    Channel(#[from] crate::Error),
+    #[error("relay refused: {0}")]
+    Refused(String),
+    #[error("request longer than {MAX_REQUEST} bytes")]
+    RequestTooLong,
+    #[error("unexpected response")]
+    UnexpectedResponse,
+}
+
+#[derive(Serialize, Deserialize)]
+enum Request {
+    Put {
+        to: Address,
+        payload: String,
+        /// unix time the payload was sent at
+        sent_at: u64,
+    },
+    Get {
+        mailbox: Address,
+        /// epoch of the mailbox `since` was returned for
+        #[serde(default)]
+        epoch: u32,
+        since: usize,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+enum Response {
+    Stored,
+    /// messages starting at index `since`, and the index to continue from. If the epoch is not
+    /// the one asked for, the messages start at the first one kept.
+    Messages {
+        messages: Vec<String>,
+        #[serde(default)]
+        epoch: u32,
+        next: usize,
+    },
+    Error(String),
+}
+
+struct Mailbox {
+    /// random and never 0, so a cursor from another mailbox does not match
+    epoch: u32,
+    /// index of the first message still kept
+    offset: usize,
+    messages: VecDeque<String>,
+    /// unix time of the last put
+    updated: u64,
+}
+
+/// Limits on the requests of a single peer.
+#[derive(Clone, Debug)]
+pub struct RelayConfig {
+    /// puts a peer can make per minute on average
+    pub puts_per_minute: u32,
+    /// gets a peer can make per minute on average
+    pub gets_per_minute: u32,
+    /// puts, and separately gets, a peer can make at once after being idle
+    pub burst: u32,
+    /// seconds the timestamp of a put may be off the relay's clock
+    pub max_skew: u64,
+    /// connections served at once, further ones wait to be accepted
+    pub max_connections: usize,
+    /// seconds a connection is served for before it is dropped
+    pub connection_timeout: u64,
+}
+
+impl Default for RelayConfig {
+    fn default() -> Self {
+        RelayConfig {
+            puts_per_minute: 60,
+            gets_per_minute: 120,
+            burst: 20,
+            max_skew: 300,
+            max_connections: 1024,
+            connection_timeout: 30,
+        }
+    }
+}
+
+/// Counts of the puts the relay stored and refused since it started.
+#[derive(Default, Debug)]
+pub struct RelayMetrics {
+    pub stored: AtomicU64,
+    pub too_large: AtomicU64,
+    pub rate_limited: AtomicU64,
+    pub stale: AtomicU64,
+    pub replayed: AtomicU64,
+    /// puts refused because there were too many mailboxes
+    pub full: AtomicU64,
+    /// connections dropped for staying open too long
+    pub timed_out: AtomicU64,
+}
+
+impl RelayMetrics {
+    fn count(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl Display for RelayMetrics {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "stored {}, too large {}, rate limited {}, stale {}, replayed {}, full {}, timed out {}",
+            self.stored.load(Ordering::Relaxed),
+            self.too_large.load(Ordering::Relaxed),
+            self.rate_limited.load(Ordering::Relaxed),
+            self.stale.load(Ordering::Relaxed),
+            self.replayed.load(Ordering::Relaxed),
+            self.full.load(Ordering::Relaxed),
+            self.timed_out.load(Ordering::Relaxed),
+        )
+    }
+}
+
+impl Mailbox {
+    fn new() -> Mailbox {
+        Mailbox {
+            epoch: OsRng.gen_range(1..=u32::MAX),
+            offset: 0,
+            messages: VecDeque::new(),
+            updated: 0,
+        }
+    }
+}
+
+/// Packs the epoch of a mailbox and an index in it into the cursor of the [`Transport`].
+fn cursor(epoch: u32, index: usize) -> u64 {
+    (u64::from(epoch) << 32) | (index as u64 & u64::from(u32::MAX))
+}
+
+fn split_cursor(cursor: u64) -> (u32, usize) {
+    (
+        (cursor >> 32) as u32,
+        (cursor & u64::from(u32::MAX)) as usize,
+    )
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+struct Relay {
+    config: RelayConfig,
+    metrics: Arc<RelayMetrics>,
+    mailboxes: Mutex<HashMap<Address, Mailbox>>,
+    put_buckets: Mutex<HashMap<IpAddr, Bucket>>,
+    get_buckets: Mutex<HashMap<IpAddr, Bucket>>,
+    /// hashes of recently stored payloads, with when they were stored
+    seen: Mutex<HashMap<[u8; 32], u64>>,
+}
+
+impl Relay {
+    fn new(config: RelayConfig, metrics: Arc<RelayMetrics>) -> Relay {
+        Relay {
+            config,
+            metrics,
+            mailboxes: Mutex::default(),
+            put_buckets: Mutex::default(),
+            get_buckets: Mutex::default(),
+            seen: Mutex::default(),
+        }
+    }
+
+    /// Takes a token from the peer's bucket, which refills at `per_minute` tokens a minute.
+    fn allow(
+        &self,
+        buckets: &Mutex<HashMap<IpAddr, Bucket>>,
+        per_minute: u32,
+        peer: IpAddr,
+    ) -> bool {
+        let rate = f64::from(per_minute) / 60.0;
+        let burst = f64::from(self.config.burst);
+        let now = Instant::now();
+        let mut buckets = buckets.lock().unwrap();
+        if buckets.len() >= MAX_PEERS {
+            // full buckets are the same as no bucket
+            buckets.retain(|_, bucket| {
+                bucket.tokens + now.duration_since(bucket.updated).as_secs_f64() * rate < burst
+            });
+        }
+        let bucket = buckets.entry(peer).or_insert(Bucket {
+            tokens: burst,
+            updated: now,
+        });
+        bucket.tokens =
+            (bucket.tokens + now.duration_since(bucket.updated).as_secs_f64() * rate).min(burst);
+        bucket.updated = now;
+        if bucket.tokens < 1.0 {
+            return false;
+        }
+        bucket.tokens -= 1.0;
+        true
+    }
+
+    fn put(&self, peer: IpAddr, to: Address, payload: String, sent_at: u64) -> Response {
+        if payload.len() > MAX_PAYLOAD {
+            RelayMetrics::count(&self.metrics.too_large);
+            return Response::Error("payload too large".to_string());
+        }
+        if !self.allow(&self.put_buckets, self.config.puts_per_minute, peer) {
+            RelayMetrics::count(&self.metrics.rate_limited);
+            debug!(?peer, "rate limited");
+            return Response::Error("rate limited".to_string());
+        }
+        let now = now();
+        if now.abs_diff(sent_at) > self.config.max_skew {
+            RelayMetrics::count(&self.metrics.stale);
+            return Response::Error("stale timestamp".to_string());
+        }
+        {
+            let mut seen = self.seen.lock().unwrap();
+            // a payload is remembered as long as a timestamp sent with it could pass
+            let window = 2 * self.config.max_skew;
+            seen.retain(|_, stored| now.saturating_sub(*stored) <= window);
+            if seen.insert(keccak256(&payload), now).is_some() {
+                RelayMetrics::count(&self.metrics.replayed);
+                return Response::Error("payload already relayed".to_string());
+            }
+        }
+
+        let mut mailboxes = self.mailboxes.lock().unwrap();
+        if mailboxes.len() >= MAX_MAILBOXES && !mailboxes.contains_key(&to) {
+            mailboxes.retain(|_, mailbox| now.saturating_sub(mailbox.updated) <= MAILBOX_TTL);
+            if mailboxes.len() >= MAX_MAILBOXES {
+                RelayMetrics::count(&self.metrics.full);
+                return Response::Error("too many mailboxes".to_string());
+            }
+        }
+        let mailbox = mailboxes.entry(to).or_insert_with(Mailbox::new);
+        mailbox.updated = now;
+        mailbox.messages.push_back(payload);
+        if mailbox.messages.len() > MAILBOX_CAPACITY {
+            mailbox.messages.pop_front();
+            mailbox.offset += 1;
+        }
+        RelayMetrics::count(&self.metrics.stored);
+        Response::Stored
+    }
+
+    fn get(&self, peer: IpAddr, mailbox: Address, epoch: u32, since: usize) -> Response {
+        if !self.allow(&self.get_buckets, self.config.gets_per_minute, peer) {
+            RelayMetrics::count(&self.metrics.rate_limited);
+            debug!(?peer, "rate limited");
+            return Response::Error("rate limited".to_string());
+        }
+        let mailboxes = self.mailboxes.lock().unwrap();
+        match mailboxes.get(&mailbox) {
+            Some(mailbox) => {
+                let next = mailbox.offset + mailbox.messages.len();
+                // a cursor of another epoch, or past the end, is from a mailbox that is gone
+                let since = if epoch == mailbox.epoch && since <= next {
+                    since
+                } else {
+                    0
+                };
+                Response::Messages {
+                    messages: mailbox
+                        .messages
+                        .iter()
+                        .skip(since.saturating_sub(mailbox.offset))
+                        .cloned()
+                        .collect(),
+                    epoch: mailbox.epoch,
+                    next,
+                }
+            }
+            None => Response::Messages {
+                messages: vec![],
+                epoch: 0,
+                next: 0,
+            },
+        }
+    }
+}
+
+/// Runs a relay with the default limits until accepting connections fails.
+pub async fn serve(address: impl ToSocketAddrs) -> io::Result<()> {
+    serve_with(address, RelayConfig::default(), Arc::default()).await
+}
+
+/// Runs a relay until accepting connections fails, counting puts in the given metrics.
+pub async fn serve_with(
+    address: impl ToSocketAddrs,
+    config: RelayConfig,
+    metrics: Arc<RelayMetrics>,
+) -> io::Result<()> {
+    let listener = TcpListener::bind(address).await?;
+    let connections = Arc::new(Semaphore::new(config.max_connections));
+    let timeout = Duration::from_secs(config.connection_timeout);
+    let relay = Arc::new(Relay::new(config, metrics));
+    loop {
+        let permit = connections
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("the semaphore is never closed");
+        let (stream, peer) = listener.accept().await?;
+        debug!(?peer, "relay connection");
+        let relay = relay.clone();
+        tokio::spawn(async move {
+            match tokio::time::timeout(timeout, handle(stream, peer.ip(), relay.clone())).await {
+                Ok(Ok(())) => {}
+                Ok(Err(err)) => warn!(?peer, "relay connection failed: {err}"),
+                Err(_) => {
+                    RelayMetrics::count(&relay.metrics.timed_out);
+                    debug!(?peer, "relay connection timed out");
+                }
+            }
+            drop(permit);
+        });
+    }
+}
+
+async fn handle(stream: TcpStream, peer: IpAddr, relay: Arc<Relay>) -> Result<(), RelayError> {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+    loop {
+        let line = match read_request(&mut reader).await {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(err) => {
+                if matches!(err, RelayError::RequestTooLong) {
+                    RelayMetrics::count(&relay.metrics.too_large);
+                }
+                return Err(err);
+            }
+        };
+        let response = match serde_json::from_str(&line) {
+            Ok(Request::Put {
+                to,
+                payload,
+                sent_at,
+            }) => relay.put(peer, to, payload, sent_at),
+            Ok(Request::Get {
+                mailbox,
+                epoch,
+                since,
+            }) => relay.get(peer, mailbox, epoch, since),
+            Err(err) => Response::Error(err.to_string()),
+        };
+        let mut response = serde_json::to_vec(&response)?;
+        response.push(b'\n');
+        writer.write_all(&response).await?;
+    }
+    Ok(())
+}
+
+/// Reads a request line without its line ending, `None` at the end of the stream. Fails with
+/// [`RelayError::RequestTooLong`] instead of buffering a line longer than [`MAX_REQUEST`].
+async fn read_request<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+) -> Result<Option<String>, RelayError> {
+    let mut line = vec![];
+    let read = (&mut *reader)
+        .take(MAX_REQUEST as u64)
+        .read_until(b'\n', &mut line)
+        .await?;
+    if read == 0 {
+        return Ok(None);
+    }
+    if line.last() == Some(&b'\n') {
+        line.pop();
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+    } else if read == MAX_REQUEST {
+        return Err(RelayError::RequestTooLong);
+    }
+    let line =
+        String::from_utf8(line).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    Ok(Some(line))
+}
+
+pub struct RelayClient {
+    address: String,
+}
+
+impl RelayClient {
+    pub fn new(address: impl Into<String>) -> RelayClient {
+        RelayClient {
+            address: address.into(),
+        }
+    }
+
+    async fn request(&self, request: &Request) -> Result<Response, RelayError> {
+        let stream = TcpStream::connect(&self.address).await?;
+        let (reader, mut writer) = stream.into_split();
+        let mut request = serde_json::to_vec(request)?;
+        request.push(b'\n');
+        writer.write_all(&request).await?;
+        let line = BufReader::new(reader)
+            .lines()
+            .next_line()
+            .await?
+            .ok_or(RelayError::UnexpectedResponse)?;
+        match serde_json::from_str(&line)? {
+            Response::Error(err) => Err(RelayError::Refused(err)),
+            response => Ok(response),
+        }
+    }
+
+    /// Seals the message to the counterparty of the channel and queues it in their mailbox.
+    pub async fn send(&self, channel: &Channel, message: &str) -> Result<(), RelayError> {
+        let request = Request::Put {
+            to: channel.their_address(),
+            payload: channel.seal(message)?,
+            sent_at: now(),
+        };
+        match self.request(&request).await? {
+            Response::Stored => Ok(()),
+            _ => Err(RelayError::UnexpectedResponse),
+        }
+    }
+
+    /// Fetches and unseals the messages for us that arrived since the given cursor. Returns them
+    /// with the cursor to continue from next time, 0 starts from the oldest message kept.
+    pub async fn fetch(
+        &self,
+        channel: &Channel,
+        since: u64,
+    ) -> Result<(Vec<String>, u64), RelayError> {
+        let (epoch, since) = split_cursor(since);
+        let request = Request::Get {
+            mailbox: channel.our_address()?,
+            epoch,
+            since,
+        };
+        match self.request(&request).await? {
+            Response::Messages {
+                messages,
+                epoch,
+                next,
+            } => {
+                // anyone can put messages into our mailbox, so skip what is not from them
+                let messages = messages
+                    .iter()
+                    .filter_map(|message| match channel.unseal(message) {
+                        Ok(message) => Some(message),
+                        Err(err) => {
+                            warn!("skipping relayed message: {err}");
+                            None
+                        }
+                    })
+                    .collect();
+                Ok((messages, cursor(epoch, next)))
+            }
+            _ => Err(RelayError::UnexpectedResponse),
+        }
+    }
+
+    pub async fn publish(&self, advertisement: &SignedAdvertisement) -> Result<(), RelayError> {
+        let request = Request::Put {
+            to: ADVERT_MAILBOX,
+            payload: serde_json::to_string(advertisement)?,
+            sent_at: now(),
+        };
+        match self.request(&request).await? {
+            Response::Stored => Ok(()),
+            _ => Err(RelayError::UnexpectedResponse),
+        }
+    }
+
+    /// Fetches the valid advertisements published since the given cursor, with the cursor to
+    /// continue from next time.
+    pub async fn advertisements(
+        &self,
+        since: u64,
+    ) -> Result<(Vec<SignedAdvertisement>, u64), RelayError> {
+        let (epoch, since) = split_cursor(since);
+        let request = Request::Get {
+            mailbox: ADVERT_MAILBOX,
+            epoch,
+            since,
+        };
+        match self.request(&request).await? {
+            Response::Messages {
+                messages,
+                epoch,
+                next,
+            } => {
+                let advertisements = messages
+                    .iter()
+                    .filter_map(|message| serde_json::from_str(message).ok())
+                    .filter(|advertisement: &SignedAdvertisement| advertisement.verify().is_ok())
+                    .collect();
+                Ok((advertisements, cursor(epoch, next)))
+            }
+            _ => Err(RelayError::UnexpectedResponse),
+        }
+    }
+}
+
+#[async_trait]
+impl Transport for RelayClient {
+    type Error = RelayError;
+
+    async fn send(&self, channel: &Channel, message: &str) -> Result<(), RelayError> {
+        RelayClient::send(self, channel, message).await
+    }
+
+    /// The cursor is the epoch of our mailbox and the index in it.
+    async fn receive(
+        &self,
+        channel: &Channel,
+        since: u64,
+    ) -> Result<(Vec<String>, u64), RelayError> {
+        self.fetch(channel, since).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PEER: IpAddr = IpAddr::V4(std::net::Ipv4Addr::LOCALHOST);
+
+    fn relay(config: RelayConfig) -> Relay {
+        Relay::new(config, Arc::default())
+    }
+
+    fn put(relay: &Relay, payload: &str, sent_at: u64) -> Response {
+        relay.put(PEER, Address::random(), payload.to_string(), sent_at)
+    }
+
+    #[test]
+    fn puts_beyond_the_burst_are_rate_limited() {
+        let relay = relay(RelayConfig {
+            puts_per_minute: 1,
+            burst: 2,
+            ..RelayConfig::default()
+        });
+        assert!(matches!(put(&relay, "a", now()), Response::Stored));
+        assert!(matches!(put(&relay, "b", now()), Response::Stored));
+        assert!(matches!(put(&relay, "c", now()), Response::Error(_)));
+        // other peers have their own bucket
+        let other = IpAddr::V4(std::net::Ipv4Addr::new(10, 0, 0, 1));
+        assert!(matches!(
+            relay.put(other, Address::random(), "c".to_string(), now()),
+            Response::Stored
+        ));
+        assert_eq!(relay.metrics.rate_limited.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn gets_have_their_own_limit() {
+        let relay = relay(RelayConfig {
+            gets_per_minute: 1,
+            burst: 1,
+            ..RelayConfig::default()
+        });
+        assert!(matches!(
+            relay.get(PEER, Address::random(), 0, 0),
+            Response::Messages { .. }
+        ));
+        assert!(matches!(
+            relay.get(PEER, Address::random(), 0, 0),
+            Response::Error(_)
+        ));
+        assert!(matches!(put(&relay, "a", now()), Response::Stored));
+    }
+
+    #[test]
+    fn timestamps_outside_the_skew_are_stale() {
+        let relay = relay(RelayConfig {
+            max_skew: 10,
+            ..RelayConfig::default()
+        });
+        assert!(matches!(put(&relay, "old", now() - 11), Response::Error(_)));
+        assert!(matches!(put(&relay, "new", now() + 11), Response::Error(_)));
+        assert!(matches!(put(&relay, "late", now() - 10), Response::Stored));
+        assert_eq!(relay.metrics.stale.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn payloads_are_relayed_once() {
+        let relay = relay(RelayConfig::default());
+        assert!(matches!(put(&relay, "a", now()), Response::Stored));
+        assert!(matches!(put(&relay, "a", now()), Response::Error(_)));
+        assert!(matches!(put(&relay, "b", now()), Response::Stored));
+        assert_eq!(relay.metrics.replayed.load(Ordering::Relaxed), 1);
+    }
+
+    fn get(relay: &Relay, mailbox: Address, since: u64) -> (Vec<String>, u64) {
+        let (epoch, since) = split_cursor(since);
+        match relay.get(PEER, mailbox, epoch, since) {
+            Response::Messages {
+                messages,
+                epoch,
+                next,
+            } => (messages, cursor(epoch, next)),
+            _ => panic!("get refused"),
+        }
+    }
+
+    #[test]
+    fn cursors_of_a_forgotten_mailbox_start_over() {
+        let relay = relay(RelayConfig::default());
+        let mailbox = Address::random();
+        for payload in ["a", "b", "c"] {
+            relay.put(PEER, mailbox, payload.to_string(), now());
+        }
+        let (messages, old) = get(&relay, mailbox, 0);
+        assert_eq!(messages, ["a", "b", "c"]);
+        assert_eq!(get(&relay, mailbox, old).0, Vec::<String>::new());
+
+        // evicted after the TTL, and then put to again
+        relay.mailboxes.lock().unwrap().remove(&mailbox);
+        relay.put(PEER, mailbox, "d".to_string(), now());
+        let (messages, next) = get(&relay, mailbox, old);
+        assert_eq!(messages, ["d"]);
+        assert_eq!(split_cursor(next).1, 1);
+
+        // a restarted relay has lost all mailboxes
+        let relay = super::Relay::new(RelayConfig::default(), Arc::default());
+        relay.put(PEER, mailbox, "e".to_string(), now());
+        assert_eq!(get(&relay, mailbox, next).0, ["e"]);
+        // an index past the end is from a mailbox that is gone, too
+        let (epoch, _) = split_cursor(get(&relay, mailbox, 0).1);
+        assert_eq!(get(&relay, mailbox, cursor(epoch, 5)).0, ["e"]);
+    }
+
+    #[tokio::test]
+    async fn long_requests_are_refused() {
+        let mut input = BufReader::new(&b"{}\r\nlast"[..]);
+        assert_eq!(read_request(&mut input).await.unwrap().unwrap(), "{}");
+        assert_eq!(read_request(&mut input).await.unwrap().unwrap(), "last");
+        assert!(read_request(&mut input).await.unwrap().is_none());
+
+        let endless = vec![b'a'; MAX_REQUEST + 1];
+        let mut input = BufReader::new(&endless[..]);
+        assert!(matches!(
+            read_request(&mut input).await,
+            Err(RelayError::RequestTooLong)
+        ));
+    }
+}
@@ -0,0 +1,60 @@
+//! Retrying of chain interactions, so a single transient RPC failure does not abort an operation
+//! halfway through.
+use std::fmt::Display;
+use std::future::Future;
+use std::time::Duration;
+use tracing::warn;
+
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// total attempts, including the first one
+    pub attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            attempts: 3,
+            initial_backoff: Duration::from_millis(250),
+            max_backoff: Duration::from_secs(5),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn none() -> Self {
+        RetryPolicy {
+            attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    /// Runs the operation until it succeeds, fails with an error that is not `retryable`, or the
+    /// attempts are used up, doubling the backoff between attempts.
+    pub(crate) async fn run<T, E, F, Fut>(
+        &self,
+        mut operation: F,
+        retryable: impl Fn(&E) -> bool,
+    ) -> Result<T, E>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        E: Display,
+    {
+        let mut backoff = self.initial_backoff;
+        let mut attempt = 1;
+        loop {
+            match operation().await {
+                Err(err) if attempt < self.attempts && retryable(&err) => {
+                    warn!(attempt, "retrying after error: {err}");
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(self.max_backoff);
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
@@ -0,0 +1,37 @@
+//! Typed reasons for reverts of the channel contract. The contract only reverts with strings, so
+//! they are matched here once instead of by every caller.
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Revert {
+    #[error("dispute finished")]
+    DisputeFinished,
+    #[error("no dispute ongoing")]
+    NoDisputeOngoing,
+    #[error("dispute not finished")]
+    DisputeNotFinished,
+    #[error("dispute ongoing")]
+    DisputeOngoing,
+    #[error("illegal value transfer")]
+    IllegalValueTransfer,
+    #[error("insufficient balance")]
+    InsufficientBalance,
+    #[error("unable to subtract fees from withdrawal")]
+    FeesExceedWithdrawal,
+}
+
+impl Revert {
+    /// Maps a revert string of the channel contract to its reason, if it is one of ours.
+    pub fn from_reason(reason: &str) -> Option<Revert> {
+        Some(match reason {
+            "dispute finished" => Revert::DisputeFinished,
+            "no dispute ongoing" => Revert::NoDisputeOngoing,
+            "dispute not finished" => Revert::DisputeNotFinished,
+            "dispute ongoing" => Revert::DisputeOngoing,
+            "illegal valueTransfer" => Revert::IllegalValueTransfer,
+            "insufficient balance" => Revert::InsufficientBalance,
+            "unable to subtract fees from withdrawal" => Revert::FeesExceedWithdrawal,
+            _ => return None,
+        })
+    }
+}
@@ -0,0 +1,67 @@
+//! Versioning of the persisted channel format. Stored channels carry the version of the format
+//! they were written in and are upgraded step by step on load. Channels written by a newer
+//! version are refused instead of being misread.
+use crate::Channel;
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+
+pub const CURRENT_VERSION: u32 = 8;
+
+/// `MIGRATIONS[n]` upgrades a channel from version `n` to `n + 1`.
+const MIGRATIONS: [fn(&mut Value); CURRENT_VERSION as usize] = [
+    // channels written before versioning only lack fields that default on load
+    |_| {},
+    // the nonce of recovered channels defaults to none
+    |_| {},
+    // gas charges default to none
+    |_| {},
+    // channels default to not being observers
+    |_| {},
+    // the block policy defaults to the latest block
+    |_| {},
+    // funding holds default to none
+    |_| {},
+    // the known state defaults to none and pending messages to reconciled
+    |_| {},
+    // the cache TTL defaults to zero, always reading the chain
+    |_| {},
+];
+
+/// Decodes a stored channel, upgrading it to the current format.
+pub fn decode(data: &[u8]) -> Result<Channel, serde_json::Error> {
+    let mut value: Value = serde_json::from_slice(data)?;
+    let version = match value.get("version") {
+        None => 0,
+        Some(version) => version
+            .as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .ok_or_else(|| serde_json::Error::custom("invalid channel format version"))?,
+    };
+    check(version)?;
+    for migration in &MIGRATIONS[version as usize..] {
+        migration(&mut value);
+    }
+    if let Some(channel) = value.as_object_mut() {
+        channel.insert("version".to_string(), CURRENT_VERSION.into());
+    }
+    serde_json::from_value(value)
+}
+
+fn check<E: serde::de::Error>(version: u32) -> Result<(), E> {
+    if version > CURRENT_VERSION {
+        return Err(E::custom(format!(
+            "channel format version {version} is newer than the supported version {CURRENT_VERSION}"
+        )));
+    }
+    Ok(())
+}
+
+/// Refuses future versions even when a channel is deserialized without [`decode`].
+pub(crate) fn supported_version<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<u32, D::Error> {
+    let version = u32::deserialize(deserializer)?;
+    check(version)?;
+    Ok(version)
+}
@@ -0,0 +1,52 @@
+//! Key material that is wiped from memory when dropped and never shows up in logs.
+use ethers::core::k256::ecdsa::SigningKey;
+use serde::{de, Deserialize, Deserializer, Serialize};
+use std::fmt;
+use zeroize::{Zeroize, ZeroizeOnDrop};
+
+/// The raw bytes of a signing key, empty for watch-only channels. Serialized like a plain byte
+/// vector, so existing channel files keep working. Keys are checked when loaded, so anything
+/// else can rely on them being valid.
+#[derive(Serialize, Default, Zeroize, ZeroizeOnDrop)]
+#[serde(transparent)]
+pub(crate) struct KeyBytes(Vec<u8>);
+
+impl KeyBytes {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// The signing key, which wipes itself when dropped as well. `None` for watch-only channels.
+    pub(crate) fn signing_key(&self) -> Option<SigningKey> {
+        SigningKey::from_slice(&self.0).ok()
+    }
+}
+
+impl<'de> Deserialize<'de> for KeyBytes {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let key = KeyBytes(Vec::deserialize(deserializer)?);
+        if !key.is_empty() && key.signing_key().is_none() {
+            return Err(de::Error::custom("invalid signing key"));
+        }
+        Ok(key)
+    }
+}
+
+impl From<&SigningKey> for KeyBytes {
+    fn from(key: &SigningKey) -> Self {
+        let mut bytes = key.to_bytes();
+        let key = KeyBytes(bytes.to_vec());
+        bytes.as_mut_slice().zeroize();
+        key
+    }
+}
+
+impl fmt::Debug for KeyBytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("KeyBytes(..)")
+    }
+}
@@ -0,0 +1,97 @@
+//! Channel handles that can be used from several tasks at once. Every update runs under a lock and
+//! is persisted before the lock is released, and [`SharedChannels`] hands out one handle per name,
+//! so there is a single writer for each channel.
+use crate::store::{ChannelStore, StoreError, Version};
+use crate::{Channel, Error};
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Weak};
+use tokio::sync::Mutex;
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+struct Stored {
+    channel: Channel,
+    version: Version,
+}
+
+pub struct SharedChannel<S> {
+    name: String,
+    store: Arc<S>,
+    inner: Arc<Mutex<Stored>>,
+}
+
+impl<S> Clone for SharedChannel<S> {
+    fn clone(&self) -> Self {
+        SharedChannel {
+            name: self.name.clone(),
+            store: self.store.clone(),
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<S: ChannelStore> SharedChannel<S> {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Runs a read-only function on the channel.
+    pub async fn read<T>(&self, f: impl FnOnce(&Channel) -> T) -> T {
+        f(&self.inner.lock().await.channel)
+    }
+
+    /// Runs an update on the channel and saves it afterwards. The channel is saved even if the
+    /// update fails, as failing methods may still have changed it, e.g. by recording a
+    /// withdrawal that turned out to be included. Channel methods are passed boxed, e.g.
+    /// `|channel| Box::pin(channel.request_transfer(wei, client))`.
+    pub async fn update<T, F>(&self, f: F) -> Result<T, Error>
+    where
+        F: for<'a> FnOnce(&'a mut Channel) -> BoxFuture<'a, Result<T, Error>>,
+    {
+        let mut inner = self.inner.lock().await;
+        let result = f(&mut inner.channel).await;
+        inner.version = self
+            .store
+            .save(&self.name, &inner.channel, Some(inner.version))
+            .await?;
+        result
+    }
+}
+
+/// Opens channels from a store, handing out the same [`SharedChannel`] for a name as long as one
+/// is in use.
+pub struct SharedChannels<S> {
+    store: Arc<S>,
+    open: Mutex<HashMap<String, Weak<Mutex<Stored>>>>,
+}
+
+impl<S: ChannelStore> SharedChannels<S> {
+    pub fn new(store: Arc<S>) -> SharedChannels<S> {
+        SharedChannels {
+            store,
+            open: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub async fn get(&self, name: &str) -> Result<SharedChannel<S>, StoreError> {
+        let mut open = self.open.lock().await;
+        let inner = match open.get(name).and_then(Weak::upgrade) {
+            Some(inner) => inner,
+            None => {
+                let (channel, version) = self.store.load(name).await?;
+                let inner = Arc::new(Mutex::new(Stored { channel, version }));
+                open.insert(name.to_string(), Arc::downgrade(&inner));
+                inner
+            }
+        };
+        // forget handles that were dropped meanwhile
+        open.retain(|_, inner| inner.strong_count() > 0);
+        Ok(SharedChannel {
+            name: name.to_string(),
+            store: self.store.clone(),
+            inner,
+        })
+    }
+}
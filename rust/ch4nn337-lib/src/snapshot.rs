@@ -0,0 +1,138 @@
+//! Reading the on-chain state of many channels at once, e.g. to list or monitor every channel of
+//! a store. Instead of the three to six calls per channel of [`Channel::get_balances`] and
+//! [`Channel::get_dispute_info`], everything is read through Multicall3 in a single `eth_call`
+//! per [`CHUNK`] channels, plus resolving the block if the policy asks for confirmations.
+//!
+//! The results are not proven, channels reading against a trusted block should keep using their
+//! own methods, see [`verify`](crate::verify).
+use crate::chain::{BlockPolicy, ChainClient, ChainError};
+use crate::retry::RetryPolicy;
+use crate::{Channel, Error};
+use ch4nn337_sys::aa_channel::{
+    BalanceACall, BalanceAReturn, BalanceBCall, BalanceBReturn, DisputeStartNonceCall,
+    DisputeStartNonceReturn, DisputeTimestampCall, DisputeTimestampReturn, DisputeValueCall,
+    DisputeValueReturn,
+};
+use ch4nn337_sys::multicall::{
+    Aggregate3Call, Aggregate3Return, Call3, CallResult, GetEthBalanceCall, GetEthBalanceReturn,
+    MULTICALL3,
+};
+use ethers::abi::{AbiDecode, AbiEncode};
+use ethers::types::{Address, Bytes, U256};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Channels read per `eth_call`, keeping the call well below the gas cap of common nodes.
+pub const CHUNK: usize = 100;
+
+/// calls per channel: its ether balance and five views of the contract
+const CALLS: usize = 6;
+
+#[derive(Error, Debug)]
+pub enum SnapshotError {
+    #[error("multicall is not deployed on this chain")]
+    Unavailable,
+    #[error("reading the channel at {0:?} failed")]
+    Failed(Address),
+}
+
+/// The state of a channel account at a block.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AccountState {
+    /// ether held by the account itself, which is where funds sit before deployment
+    pub balance: U256,
+    pub deployed: bool,
+    pub balance_a: u128,
+    pub balance_b: u128,
+    pub dispute_start_nonce: u128,
+    pub dispute_value: i128,
+    pub dispute_timestamp: u64,
+}
+
+/// Reads the accounts of the channels at the block the policy resolves to, in the order of the
+/// channels. Interpret them with [`Channel::balances_at`] and [`Channel::dispute_info_at`].
+pub async fn read<C: ChainClient + ?Sized>(
+    client: &C,
+    channels: &[&Channel],
+    policy: BlockPolicy,
+) -> Result<Vec<AccountState>, Error> {
+    let retry = RetryPolicy::default();
+    let block = policy.resolve(client, &retry).await?;
+    let mut accounts = Vec::with_capacity(channels.len());
+    for chunk in channels.chunks(CHUNK) {
+        let calls = chunk
+            .iter()
+            .flat_map(|channel| calls(channel.address()))
+            .collect();
+        let data = Bytes::from(Aggregate3Call { calls }.encode());
+        let output = retry
+            .run(
+                || client.call(MULTICALL3, data.clone(), block),
+                |err| !err.is_revert(),
+            )
+            .await?;
+        // calling an address without code succeeds without output
+        if output.is_empty() {
+            return Err(SnapshotError::Unavailable.into());
+        }
+        let results = Aggregate3Return::decode(output)
+            .map_err(ChainError::from)?
+            .return_data;
+        if results.len() != chunk.len() * CALLS {
+            return Err(SnapshotError::Unavailable.into());
+        }
+        for (channel, results) in chunk.iter().zip(results.chunks(CALLS)) {
+            accounts.push(account(results).ok_or(SnapshotError::Failed(channel.address()))?);
+        }
+    }
+    Ok(accounts)
+}
+
+fn calls(address: Address) -> [Call3; CALLS] {
+    let call = |target, call_data: Vec<u8>| Call3 {
+        target,
+        allow_failure: true,
+        call_data: call_data.into(),
+    };
+    [
+        call(MULTICALL3, GetEthBalanceCall { addr: address }.encode()),
+        call(address, BalanceACall.encode()),
+        call(address, BalanceBCall.encode()),
+        call(address, DisputeStartNonceCall.encode()),
+        call(address, DisputeValueCall.encode()),
+        call(address, DisputeTimestampCall.encode()),
+    ]
+}
+
+/// The account from the results of [`calls`], `None` if any failed.
+fn account(results: &[CallResult]) -> Option<AccountState> {
+    fn decode<R: AbiDecode>(result: &CallResult) -> Option<R> {
+        result
+            .success
+            .then(|| R::decode(&result.return_data).ok())
+            .flatten()
+    }
+
+    let GetEthBalanceReturn { balance } = decode(&results[0])?;
+    // views of an account without code succeed without output, like any call to it
+    if results[1].success && results[1].return_data.is_empty() {
+        return Some(AccountState {
+            balance,
+            ..AccountState::default()
+        });
+    }
+    let BalanceAReturn(balance_a) = decode(&results[1])?;
+    let BalanceBReturn(balance_b) = decode(&results[2])?;
+    let DisputeStartNonceReturn(dispute_start_nonce) = decode(&results[3])?;
+    let DisputeValueReturn(dispute_value) = decode(&results[4])?;
+    let DisputeTimestampReturn(dispute_timestamp) = decode(&results[5])?;
+    Some(AccountState {
+        balance,
+        deployed: true,
+        balance_a,
+        balance_b,
+        dispute_start_nonce,
+        dispute_value,
+        dispute_timestamp,
+    })
+}
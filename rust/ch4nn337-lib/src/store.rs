@@ -0,0 +1,381 @@
+//! Persistence of channels. Every load hands out a version, and a save or delete only goes through
+//! if the channel is still at that version, so concurrent updates are noticed instead of one
+//! silently overwriting the other.
+use crate::journal::{Intent, Journal};
+use crate::{schema, Channel};
+use async_trait::async_trait;
+use ethers::utils::keccak256;
+use std::collections::HashMap;
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use thiserror::Error;
+use tokio::sync::Mutex;
+
+/// Opaque token for the state of a stored channel.
+pub type Version = u64;
+
+#[derive(Error, Debug)]
+pub enum StoreError {
+    #[error("{0}")]
+    Io(#[from] io::Error),
+    #[error("{0}")]
+    Serde(#[from] serde_json::Error),
+    #[error("channel {0} not found")]
+    NotFound(String),
+    #[error("channel {0} was changed concurrently")]
+    Conflict(String),
+    #[error("channel {0} already exists")]
+    Exists(String),
+}
+
+#[async_trait]
+pub trait ChannelStore {
+    /// Loads a channel together with its current version.
+    async fn load(&self, name: &str) -> Result<(Channel, Version), StoreError>;
+
+    /// Saves a channel if it is still at the expected version, or if it does not exist yet when
+    /// `None` is expected. Returns the new version.
+    async fn save(
+        &self,
+        name: &str,
+        channel: &Channel,
+        expected: Option<Version>,
+    ) -> Result<Version, StoreError>;
+
+    /// Names of all stored channels.
+    async fn list(&self) -> Result<Vec<String>, StoreError>;
+
+    /// Deletes a channel if it is still at the expected version.
+    async fn delete(&self, name: &str, expected: Version) -> Result<(), StoreError>;
+
+    /// Renames a channel if it is still at the expected version and the new name is free.
+    async fn rename(&self, name: &str, new_name: &str, expected: Version)
+        -> Result<(), StoreError>;
+
+    /// Moves a channel out of the list of channels if it is still at the expected version. It is
+    /// kept, but can no longer be loaded under its name.
+    async fn archive(&self, name: &str, expected: Version) -> Result<(), StoreError>;
+}
+
+fn version(data: &[u8]) -> Version {
+    let hash = keccak256(data);
+    u64::from_be_bytes(hash[..8].try_into().unwrap())
+}
+
+/// Stores every channel as `<name>.json` in a directory.
+///
+/// Versions are checked under a lock held by this store only, so two processes writing the same
+/// channel may still race between the check and the write.
+pub struct FileStore {
+    dir: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl FileStore {
+    pub fn new(dir: impl Into<PathBuf>) -> FileStore {
+        FileStore {
+            dir: dir.into(),
+            lock: Mutex::new(()),
+        }
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{name}.json"))
+    }
+
+    /// Where archived channels are kept, which [`ChannelStore::list`] does not look into.
+    pub fn archive_dir(&self) -> PathBuf {
+        self.dir.join("archive")
+    }
+
+    /// Where the [`Journal`] keeps intents, one file per channel.
+    pub fn journal_dir(&self) -> PathBuf {
+        self.dir.join("journal")
+    }
+
+    /// Moves the file of a channel if it is still at the expected version and the target is free.
+    async fn relocate(
+        &self,
+        name: &str,
+        target: PathBuf,
+        expected: Version,
+    ) -> Result<(), StoreError> {
+        let _lock = self.lock.lock().await;
+        match self.current(name).await? {
+            None => return Err(StoreError::NotFound(name.to_string())),
+            Some(version) if version != expected => {
+                return Err(StoreError::Conflict(name.to_string()))
+            }
+            Some(_) => {}
+        }
+        if tokio::fs::try_exists(&target).await? {
+            return Err(StoreError::Exists(name.to_string()));
+        }
+        Ok(tokio::fs::rename(self.path(name), target).await?)
+    }
+
+    async fn current(&self, name: &str) -> Result<Option<Version>, StoreError> {
+        match tokio::fs::read(self.path(name)).await {
+            Ok(data) => Ok(Some(version(&data))),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+#[async_trait]
+impl ChannelStore for FileStore {
+    async fn load(&self, name: &str) -> Result<(Channel, Version), StoreError> {
+        let data = match tokio::fs::read(self.path(name)).await {
+            Ok(data) => data,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                return Err(StoreError::NotFound(name.to_string()))
+            }
+            Err(err) => return Err(err.into()),
+        };
+        Ok((schema::decode(&data)?, version(&data)))
+    }
+
+    async fn save(
+        &self,
+        name: &str,
+        channel: &Channel,
+        expected: Option<Version>,
+    ) -> Result<Version, StoreError> {
+        let data = serde_json::to_vec(channel)?;
+        let _lock = self.lock.lock().await;
+        if self.current(name).await? != expected {
+            return Err(StoreError::Conflict(name.to_string()));
+        }
+        tokio::fs::create_dir_all(&self.dir).await?;
+        // write under a temporary name, so a crash never leaves a truncated channel behind
+        let partial = self.dir.join(format!(".{name}.json.partial"));
+        tokio::fs::write(&partial, &data).await?;
+        tokio::fs::rename(partial, self.path(name)).await?;
+        Ok(version(&data))
+    }
+
+    /// Other JSON files may share the directory, so only those holding a channel are listed.
+    async fn list(&self) -> Result<Vec<String>, StoreError> {
+        let mut names = vec![];
+        let mut entries = match tokio::fs::read_dir(&self.dir).await {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(names),
+            Err(err) => return Err(err.into()),
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            let file_name = entry.file_name();
+            let Some(name) = file_name
+                .to_str()
+                .and_then(|name| name.strip_suffix(".json"))
+            else {
+                continue;
+            };
+            let data = tokio::fs::read(entry.path()).await?;
+            if schema::decode(&data).is_ok() {
+                names.push(name.to_string());
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    async fn delete(&self, name: &str, expected: Version) -> Result<(), StoreError> {
+        let _lock = self.lock.lock().await;
+        match self.current(name).await? {
+            None => Err(StoreError::NotFound(name.to_string())),
+            Some(version) if version != expected => Err(StoreError::Conflict(name.to_string())),
+            Some(_) => Ok(tokio::fs::remove_file(self.path(name)).await?),
+        }
+    }
+
+    async fn rename(
+        &self,
+        name: &str,
+        new_name: &str,
+        expected: Version,
+    ) -> Result<(), StoreError> {
+        match self.relocate(name, self.path(new_name), expected).await {
+            Err(StoreError::Exists(_)) => Err(StoreError::Exists(new_name.to_string())),
+            result => result,
+        }
+    }
+
+    async fn archive(&self, name: &str, expected: Version) -> Result<(), StoreError> {
+        tokio::fs::create_dir_all(self.archive_dir()).await?;
+        let target = self.archive_dir().join(format!("{name}.json"));
+        self.relocate(name, target, expected).await
+    }
+}
+
+#[async_trait]
+impl Journal for FileStore {
+    async fn begin(&self, name: &str, intent: &Intent) -> Result<(), StoreError> {
+        let data = serde_json::to_vec(intent)?;
+        tokio::fs::create_dir_all(self.journal_dir()).await?;
+        let partial = self.journal_dir().join(format!(".{name}.json.partial"));
+        tokio::fs::write(&partial, &data).await?;
+        tokio::fs::rename(partial, self.journal_dir().join(format!("{name}.json"))).await?;
+        Ok(())
+    }
+
+    async fn complete(&self, name: &str) -> Result<(), StoreError> {
+        let path = self.journal_dir().join(format!("{name}.json"));
+        match tokio::fs::remove_file(path).await {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+
+    async fn pending(&self) -> Result<Vec<(String, Intent)>, StoreError> {
+        let mut intents = vec![];
+        let mut entries = match tokio::fs::read_dir(self.journal_dir()).await {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(intents),
+            Err(err) => return Err(err.into()),
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            let file_name = entry.file_name();
+            let Some(name) = file_name
+                .to_str()
+                .and_then(|name| name.strip_suffix(".json"))
+                .filter(|name| !name.starts_with('.'))
+            else {
+                continue;
+            };
+            let data = tokio::fs::read(entry.path()).await?;
+            intents.push((name.to_string(), serde_json::from_slice(&data)?));
+        }
+        intents.sort_by(|(a, _), (b, _)| a.cmp(b));
+        Ok(intents)
+    }
+}
+
+/// Keeps channels in memory, serialized like on disk.
+#[derive(Default)]
+pub struct MemoryStore {
+    channels: Mutex<HashMap<String, (Vec<u8>, Version)>>,
+    archived: Mutex<HashMap<String, Vec<u8>>>,
+    journal: Mutex<HashMap<String, Vec<u8>>>,
+    /// versions are never reused, even after a channel was deleted and saved again
+    next_version: AtomicU64,
+}
+
+impl MemoryStore {
+    pub fn new() -> MemoryStore {
+        MemoryStore::default()
+    }
+}
+
+#[async_trait]
+impl ChannelStore for MemoryStore {
+    async fn load(&self, name: &str) -> Result<(Channel, Version), StoreError> {
+        let channels = self.channels.lock().await;
+        let (data, version) = channels
+            .get(name)
+            .ok_or_else(|| StoreError::NotFound(name.to_string()))?;
+        Ok((schema::decode(data)?, *version))
+    }
+
+    async fn save(
+        &self,
+        name: &str,
+        channel: &Channel,
+        expected: Option<Version>,
+    ) -> Result<Version, StoreError> {
+        let data = serde_json::to_vec(channel)?;
+        let mut channels = self.channels.lock().await;
+        let current = channels.get(name).map(|(_, version)| *version);
+        if current != expected {
+            return Err(StoreError::Conflict(name.to_string()));
+        }
+        let version = self.next_version.fetch_add(1, Ordering::Relaxed);
+        channels.insert(name.to_string(), (data, version));
+        Ok(version)
+    }
+
+    async fn list(&self) -> Result<Vec<String>, StoreError> {
+        let mut names: Vec<_> = self.channels.lock().await.keys().cloned().collect();
+        names.sort();
+        Ok(names)
+    }
+
+    async fn delete(&self, name: &str, expected: Version) -> Result<(), StoreError> {
+        let mut channels = self.channels.lock().await;
+        match channels.get(name) {
+            None => Err(StoreError::NotFound(name.to_string())),
+            Some((_, version)) if *version != expected => {
+                Err(StoreError::Conflict(name.to_string()))
+            }
+            Some(_) => {
+                channels.remove(name);
+                Ok(())
+            }
+        }
+    }
+
+    async fn rename(
+        &self,
+        name: &str,
+        new_name: &str,
+        expected: Version,
+    ) -> Result<(), StoreError> {
+        let mut channels = self.channels.lock().await;
+        match channels.get(name) {
+            None => return Err(StoreError::NotFound(name.to_string())),
+            Some((_, version)) if *version != expected => {
+                return Err(StoreError::Conflict(name.to_string()))
+            }
+            Some(_) => {}
+        }
+        if channels.contains_key(new_name) {
+            return Err(StoreError::Exists(new_name.to_string()));
+        }
+        let channel = channels.remove(name).unwrap();
+        channels.insert(new_name.to_string(), channel);
+        Ok(())
+    }
+
+    async fn archive(&self, name: &str, expected: Version) -> Result<(), StoreError> {
+        let mut channels = self.channels.lock().await;
+        let mut archived = self.archived.lock().await;
+        match channels.get(name) {
+            None => return Err(StoreError::NotFound(name.to_string())),
+            Some((_, version)) if *version != expected => {
+                return Err(StoreError::Conflict(name.to_string()))
+            }
+            Some(_) => {}
+        }
+        if archived.contains_key(name) {
+            return Err(StoreError::Exists(name.to_string()));
+        }
+        let (data, _) = channels.remove(name).unwrap();
+        archived.insert(name.to_string(), data);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Journal for MemoryStore {
+    async fn begin(&self, name: &str, intent: &Intent) -> Result<(), StoreError> {
+        let data = serde_json::to_vec(intent)?;
+        self.journal.lock().await.insert(name.to_string(), data);
+        Ok(())
+    }
+
+    async fn complete(&self, name: &str) -> Result<(), StoreError> {
+        self.journal.lock().await.remove(name);
+        Ok(())
+    }
+
+    async fn pending(&self) -> Result<Vec<(String, Intent)>, StoreError> {
+        let journal = self.journal.lock().await;
+        let mut intents = journal
+            .iter()
+            .map(|(name, data)| Ok((name.clone(), serde_json::from_slice(data)?)))
+            .collect::<Result<Vec<_>, StoreError>>()?;
+        intents.sort_by(|(a, _), (b, _)| a.cmp(b));
+        Ok(intents)
+    }
+}
@@ -0,0 +1,310 @@
+//! Continuous payments on top of a [`Channel`]: the amount owed grows with time (and optionally
+//! with consumption), and every tick replaces our pending transfer with one covering everything
+//! owed so far. The counterparty only has to countersign the latest state.
+//!
+//! Amounts are accounted in milliwei, so rates below one wei per second are possible. Only the
+//! whole wei owed are transferred, the fraction is carried over to the next tick.
+use crate::chain::ChainClient;
+use crate::protocol::OutgoingMessage;
+use crate::{now, Channel, Error, Message};
+use ethers::types::U256;
+use std::num::NonZeroU128;
+use std::sync::Arc;
+
+pub const MILLIWEI_PER_WEI: u128 = 1000;
+
+pub struct PaymentStream {
+    /// milliwei per second
+    rate: u128,
+    started: u64,
+    stopped: Option<u64>,
+    /// milliwei owed for consumption on top of the time based amount
+    charged: u128,
+    /// wei included in countersigned transfers
+    settled: u128,
+    /// nonce and amount of the transfer we are waiting to be countersigned
+    pending: Option<(U256, u128)>,
+}
+
+impl PaymentStream {
+    /// Starts a stream paying `rate` wei per second.
+    pub fn start(rate: u128) -> PaymentStream {
+        PaymentStream::start_milliwei(rate.saturating_mul(MILLIWEI_PER_WEI))
+    }
+
+    /// Starts a stream paying `rate` milliwei per second.
+    pub fn start_milliwei(rate: u128) -> PaymentStream {
+        PaymentStream {
+            rate,
+            started: now(),
+            stopped: None,
+            charged: 0,
+            settled: 0,
+            pending: None,
+        }
+    }
+
+    /// Adds a consumption based amount to what is owed.
+    pub fn charge(&mut self, wei: u128) {
+        self.charge_milliwei(wei.saturating_mul(MILLIWEI_PER_WEI));
+    }
+
+    pub fn charge_milliwei(&mut self, milliwei: u128) {
+        self.charged = self.charged.saturating_add(milliwei);
+    }
+
+    pub fn stop(&mut self) {
+        self.stopped.get_or_insert_with(now);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.is_some()
+    }
+
+    /// Everything owed since the start of the stream, in whole wei.
+    pub fn owed(&self) -> u128 {
+        self.owed_milliwei() / MILLIWEI_PER_WEI
+    }
+
+    pub fn owed_milliwei(&self) -> u128 {
+        let elapsed = self
+            .stopped
+            .unwrap_or_else(now)
+            .saturating_sub(self.started);
+        self.rate
+            .saturating_mul(elapsed.into())
+            .saturating_add(self.charged)
+    }
+
+    /// Everything owed that is included in countersigned transfers.
+    pub fn settled(&self) -> u128 {
+        self.settled
+    }
+
+    /// Replaces our pending transfer with one covering everything owed so far and returns it, or
+    /// `None` if nothing new is owed.
+    pub async fn tick<C: ChainClient + ?Sized>(
+        &mut self,
+        channel: &mut Channel,
+        client: Arc<C>,
+    ) -> Result<Option<OutgoingMessage>, Error> {
+        let mut waiting = false;
+        if let Some((nonce, amount)) = self.pending {
+            waiting = channel
+                .pending_message()
+                .is_some_and(|message| message.userop().nonce == nonce);
+            if !waiting {
+                if countersigned(channel, nonce, amount) {
+                    self.settled += amount;
+                }
+                self.pending = None;
+            }
+        }
+
+        let Some(amount) = NonZeroU128::new(self.owed() - self.settled) else {
+            return Ok(None);
+        };
+        let nonce = channel.next_outgoing_nonce();
+        let request = if waiting {
+            channel.request_replacement(amount, client).await?
+        } else {
+            channel.request_transfer(amount, client).await?
+        };
+        self.pending = Some((nonce, amount.get()));
+        Ok(Some(request))
+    }
+
+    /// Stops the stream and returns the final transfer covering everything still owed.
+    pub async fn settle<C: ChainClient + ?Sized>(
+        &mut self,
+        channel: &mut Channel,
+        client: Arc<C>,
+    ) -> Result<Option<OutgoingMessage>, Error> {
+        self.stop();
+        self.tick(channel, client).await
+    }
+}
+
+/// Whether the channel holds a countersigned transfer of exactly `amount` with the nonce.
+fn countersigned(channel: &Channel, nonce: U256, amount: u128) -> bool {
+    channel.messages().iter().any(|message| match message {
+        Message::Transfer(transfer) => {
+            message.userop().nonce == nonce
+                && transfer
+                    .items()
+                    .iter()
+                    .map(|item| item.amount.0)
+                    .sum::<u128>()
+                    == amount
+        }
+        Message::Withdrawal(_) => false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockClient, MockMiddleware};
+    use ethers::types::Address;
+
+    const START: u64 = 1_000_000;
+
+    /// Lets the stream run for `seconds` after its start.
+    fn run_for(stream: &mut PaymentStream, seconds: u64) {
+        stream.started = START;
+        stream.stopped = Some(START + seconds);
+    }
+
+    async fn countersign(
+        a: &mut Channel,
+        b: &mut Channel,
+        request: OutgoingMessage,
+        provider: Arc<MockMiddleware>,
+    ) {
+        let (message, _) = b
+            .receive_message(&request.to_json(), provider.clone())
+            .await
+            .unwrap();
+        let response = b.sign_message(message, provider).await.unwrap();
+        a.receive_response(&response.to_json()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn fractions_of_a_wei_are_carried_over() {
+        let (provider, mock) = MockClient::mocked();
+        let provider = Arc::new(provider);
+        let (mut a, mut b) = Channel::open(
+            5.into(),
+            Address::random(),
+            Address::random(),
+            provider.clone(),
+        )
+        .await
+        .unwrap();
+        mock.set_balance(a.address(), 1_000_000_000_000_000_000u128.into());
+
+        // 0.3 wei per second
+        let mut stream = PaymentStream::start_milliwei(300);
+        run_for(&mut stream, 3);
+        assert_eq!(stream.owed_milliwei(), 900);
+        assert!(stream
+            .tick(&mut a, provider.clone())
+            .await
+            .unwrap()
+            .is_none());
+
+        run_for(&mut stream, 4);
+        assert_eq!(stream.owed(), 1);
+        let request = stream
+            .tick(&mut a, provider.clone())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stream.pending, Some((U256::zero(), 1)));
+        countersign(&mut a, &mut b, request, provider.clone()).await;
+
+        // the countersigned wei is settled, the 0.2 wei left over are owed on top of the next 1.8
+        run_for(&mut stream, 10);
+        assert_eq!(stream.owed(), 3);
+        stream
+            .tick(&mut a, provider.clone())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stream.settled(), 1);
+        assert_eq!(stream.pending, Some((U256::one(), 2)));
+
+        // not countersigned yet, so the next tick replaces the transfer with the same nonce
+        run_for(&mut stream, 13);
+        assert_eq!(stream.owed_milliwei(), 3900);
+        let request = stream
+            .tick(&mut a, provider.clone())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stream.settled(), 1);
+        assert_eq!(stream.pending, Some((U256::one(), 2)));
+        countersign(&mut a, &mut b, request, provider.clone()).await;
+
+        // everything whole is settled, only the 0.9 wei fraction remains owed
+        assert!(stream
+            .tick(&mut a, provider.clone())
+            .await
+            .unwrap()
+            .is_none());
+        assert_eq!(stream.settled(), 3);
+        assert_eq!(
+            stream.owed_milliwei() - stream.settled() * MILLIWEI_PER_WEI,
+            900
+        );
+    }
+
+    #[tokio::test]
+    async fn only_our_transfer_is_settled() {
+        let (provider, mock) = MockClient::mocked();
+        let provider = Arc::new(provider);
+        let (mut a, mut b) = Channel::open(
+            5.into(),
+            Address::random(),
+            Address::random(),
+            provider.clone(),
+        )
+        .await
+        .unwrap();
+        mock.set_balance(a.address(), 1_000_000_000_000_000_000u128.into());
+
+        let mut stream = PaymentStream::start(1);
+        run_for(&mut stream, 1);
+        stream
+            .tick(&mut a, provider.clone())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stream.pending, Some((U256::zero(), 1)));
+
+        // another transfer takes the nonce of the stream's
+        a.cancel_pending_message();
+        let request = a
+            .request_transfer(NonZeroU128::new(5).unwrap(), provider.clone())
+            .await
+            .unwrap();
+        countersign(&mut a, &mut b, request, provider.clone()).await;
+
+        stream
+            .tick(&mut a, provider.clone())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stream.settled(), 0);
+        assert_eq!(stream.pending, Some((U256::one(), 1)));
+    }
+
+    #[tokio::test]
+    async fn failed_replacements_keep_the_pending_transfer() {
+        let (provider, mock) = MockClient::mocked();
+        let provider = Arc::new(provider);
+        let (mut a, _) = Channel::open(
+            5.into(),
+            Address::random(),
+            Address::random(),
+            provider.clone(),
+        )
+        .await
+        .unwrap();
+        mock.set_balance(a.address(), 2.into());
+
+        let mut stream = PaymentStream::start(1);
+        run_for(&mut stream, 1);
+        stream
+            .tick(&mut a, provider.clone())
+            .await
+            .unwrap()
+            .unwrap();
+
+        // more is owed than the channel holds
+        run_for(&mut stream, 3);
+        assert!(stream.tick(&mut a, provider.clone()).await.is_err());
+        assert_eq!(stream.pending, Some((U256::zero(), 1)));
+        assert_eq!(a.pending_message().unwrap().userop().nonce, U256::zero());
+    }
+}
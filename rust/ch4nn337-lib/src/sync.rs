@@ -0,0 +1,152 @@
+//! Catching up with the counterparty after losing recent messages, e.g. when restoring an old
+//! backup. We send our last nonce and they answer with the fully signed userops after it. Every
+//! userop has to carry our own signature too, so the counterparty can only hand back what we
+//! agreed to, and what the messages mean is read from the calldata rather than taken from them.
+use crate::amount::Wei;
+use crate::proto::{self, Call};
+use crate::{
+    Channel, ChannelState, Error, Message, Party, SubmittedWithdrawal, TransferMessage,
+    WithdrawalMessage, WithdrawalStatus,
+};
+use ethers::types::userop::UserOp;
+use ethers::types::{Address, U256};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::{info, instrument};
+
+#[derive(Error, Debug)]
+pub enum SyncError {
+    #[error("sync is for another channel")]
+    WrongChannel,
+    #[error("userop {0} of the sync is neither a transfer nor a withdrawal")]
+    Calldata(usize),
+}
+
+/// Our last nonce, for the counterparty to answer with what we are missing.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SyncRequest {
+    pub channel: Address,
+    /// `None` if we have no message at all
+    pub last_nonce: Option<U256>,
+}
+
+/// The fully signed userops after the nonce of a [`SyncRequest`], oldest first.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SyncResponse {
+    pub channel: Address,
+    pub userops: Vec<UserOp>,
+}
+
+impl Channel {
+    pub fn sync_request(&self) -> SyncRequest {
+        SyncRequest {
+            channel: self.address,
+            last_nonce: self
+                .messages
+                .last()
+                .map(|message| message.userop().nonce)
+                .or(self.recovered_nonce),
+        }
+    }
+
+    /// The messages the counterparty is missing according to their request.
+    pub fn answer_sync(&self, request: &SyncRequest) -> Result<SyncResponse, Error> {
+        if request.channel != self.address {
+            return Err(SyncError::WrongChannel.into());
+        }
+        let userops = self
+            .messages
+            .iter()
+            .map(Message::userop)
+            .filter(|userop| {
+                request
+                    .last_nonce
+                    .map_or(true, |nonce| userop.nonce > nonce)
+            })
+            .cloned()
+            .collect();
+        Ok(SyncResponse {
+            channel: self.address,
+            userops,
+        })
+    }
+
+    /// Appends the messages of the counterparty's answer that are newer than ours, returning how
+    /// many. The history with them appended has to pass [`Channel::audit`], otherwise nothing is
+    /// changed. A pending request they cover is dropped, as it was either signed or superseded.
+    #[instrument(skip_all, fields(channel = ?self.address))]
+    pub fn apply_sync(&mut self, response: SyncResponse) -> Result<usize, Error> {
+        if response.channel != self.address {
+            return Err(SyncError::WrongChannel.into());
+        }
+        let last_nonce = self.sync_request().last_nonce;
+        let mut messages = vec![];
+        for (index, userop) in response.userops.into_iter().enumerate() {
+            if last_nonce.is_some_and(|nonce| userop.nonce <= nonce) {
+                continue;
+            }
+            let call = proto::decode_call(&userop.call_data).ok_or(SyncError::Calldata(index))?;
+            messages.push(match call {
+                Call::Transfer { value_transfer } => Message::Transfer(TransferMessage {
+                    userop,
+                    value_transfer,
+                    items: vec![],
+                    signed_at: None,
+                }),
+                Call::Withdrawal {
+                    withdraw_a,
+                    withdraw_b,
+                    ..
+                } => {
+                    let (withdraw_us, withdraw_them) = match self.us {
+                        Party::A => (Wei(withdraw_a), Wei(withdraw_b)),
+                        Party::B => (Wei(withdraw_b), Wei(withdraw_a)),
+                    };
+                    Message::Withdrawal(WithdrawalMessage {
+                        userop,
+                        withdraw_us,
+                        withdraw_them,
+                        signed_at: None,
+                    })
+                }
+            });
+        }
+        if messages.is_empty() {
+            return Ok(0);
+        }
+
+        let count = messages.len();
+        let kept = self.messages.len();
+        self.messages.extend(messages);
+        if let Err(err) = self.audit() {
+            self.messages.truncate(kept);
+            return Err(err.into());
+        }
+        info!(count, "synced messages from the counterparty");
+
+        let hashes: Vec<_> = self.messages[kept..]
+            .iter()
+            .map(|message| self.user_op_hash(message.userop()))
+            .collect();
+        self.processed_messages.extend(hashes);
+        let last = self.messages.last().expect("not empty");
+        let last_nonce = last.userop().nonce;
+        if self
+            .pending_message
+            .as_ref()
+            .is_some_and(|pending| pending.userop().nonce <= last_nonce)
+        {
+            self.pending_message = None;
+        }
+        if matches!(last, Message::Withdrawal(_)) && self.state == ChannelState::Open {
+            // the withdrawal was submitted, but who did is not known
+            self.withdrawal = Some(SubmittedWithdrawal {
+                hash: self.user_op_hash(last.userop()),
+                status: WithdrawalStatus::Submitted,
+                submitter: None,
+            });
+            self.transition(ChannelState::PendingWithdrawal);
+        }
+        Ok(count)
+    }
+}
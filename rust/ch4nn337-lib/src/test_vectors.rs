@@ -0,0 +1,228 @@
+//! Fixed inputs and expected outputs of channel address derivation, userop hashing and signing,
+//! for checking other implementations, e.g. wallets in other languages, against this one and for
+//! catching changes to the encoding in this one. The expected values were computed independently
+//! of this crate; the signatures are checked against the contract by the anvil integration test.
+//!
+//! All vectors share one channel: the factory is the first contract deployed by the default anvil
+//! account, the entry point is the second default anvil account. Signatures are deterministic
+//! (RFC 6979) and sign the userop hash as an Ethereum signed message. [`TestVector`]s serialize to
+//! JSON for use outside of Rust.
+use crate::userop::UserOpBuilder;
+use crate::{same_apart_from_signature, CALL_GAS_LIMIT_COOP, CALL_GAS_LIMIT_DISPUTE};
+use ch4nn337_sys::aa_channel::AAChannelCalls;
+use ch4nn337_sys::channel_address::channel_address;
+use ch4nn337_sys::signature::{encode_pair, verify_pair};
+use ethers::abi::AbiDecode;
+use ethers::signers::{LocalWallet, Signer};
+use ethers::types::userop::UserOp;
+use ethers::types::{Address, Bytes, H256, U256};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+const CHAIN_ID: u64 = 31337;
+const ENTRY_POINT: &str = "0x70997970C51812dc3A010C7d01b50e0d17dc79C8";
+const FACTORY: &str = "0x5FbDB2315678afecb367f032d93F642f64180aa3";
+const SALT: u64 = 42;
+const KEY_A: &str = "0x1111111111111111111111111111111111111111111111111111111111111111";
+const KEY_B: &str = "0x2222222222222222222222222222222222222222222222222222222222222222";
+const PARTY_A: &str = "0x19E7E376E7C213B7E7e7e46cc70A5dD086DAff2A";
+const PARTY_B: &str = "0x1563915e194D8CfBA1943570603F7606A3115508";
+const CHANNEL: &str = "0x0812F8AAa646335E96F0268398e39EdB8bff443f";
+
+#[derive(Error, Debug)]
+pub enum VectorError {
+    #[error("{0} does not match")]
+    Mismatch(&'static str),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TestVector {
+    pub name: String,
+    pub chain_id: U256,
+    pub entry_point: Address,
+    pub factory: Address,
+    pub salt: U256,
+    /// private keys of the parties
+    pub key_a: H256,
+    pub key_b: H256,
+    pub party_a: Address,
+    pub party_b: Address,
+    pub channel: Address,
+    /// the userop carrying the signatures of both parties
+    pub userop: UserOp,
+    pub user_op_hash: H256,
+    pub signature_a: Bytes,
+    pub signature_b: Bytes,
+}
+
+pub fn test_vectors() -> Vec<TestVector> {
+    vec![
+        vector(
+            "transfer",
+            2,
+            "0x4d4900d800000000000000000000000000000000000000000000000000038d7ea4c68000",
+            "0x1db1dc57ef7b937ad89db28ae38f5d42252a08d328d6e58cee7a6fbd955422b2",
+            concat!(
+                "0xc9ab5ebbce8a74e9429e8e6564b7b8844a3d759304274201ec8d3e2c861b694c0a73b1bc1c05a9",
+                "8e66c671b7d955c13555bc007a0f7b227ed5abffb6379f308d1c",
+            ),
+            concat!(
+                "0x51d161f3364d87e24c74f182f13ee049da327b3ced8a0240eeeb0efd86f72e7e7de91e6d09d9ef",
+                "5694794f91d14b57922292b45e4518de53d4dad76ca2a845df1c",
+            ),
+            concat!(
+                "0x000000000000000000000000000000000000000000000000000000000000004000000000000000",
+                "000000000000000000000000000000000000000000000000c0000000000000000000000000000000",
+                "0000000000000000000000000000000041c9ab5ebbce8a74e9429e8e6564b7b8844a3d7593042742",
+                "01ec8d3e2c861b694c0a73b1bc1c05a98e66c671b7d955c13555bc007a0f7b227ed5abffb6379f30",
+                "8d1c0000000000000000000000000000000000000000000000000000000000000000000000000000",
+                "0000000000000000000000000000000000000000000000004151d161f3364d87e24c74f182f13ee0",
+                "49da327b3ced8a0240eeeb0efd86f72e7e7de91e6d09d9ef5694794f91d14b57922292b45e4518de",
+                "53d4dad76ca2a845df1c000000000000000000000000000000000000000000000000000000000000",
+                "00",
+            ),
+        ),
+        vector(
+            "negative transfer",
+            3,
+            "0x4d4900d8fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffe0c",
+            "0xf1f0474111129675e8848273b9f0caede84af4a8b4e623d3e10d697e11427886",
+            concat!(
+                "0x3661240275f850ae62ab315cc30d049f1d8a3c45c2142a4ebe473c5cbbb571107c84b1133e3467",
+                "570f263efbb887aa995c71b5eeddfb502a3bce597a05b048f41c",
+            ),
+            concat!(
+                "0x8b9f6bf5fcb66bf1f917ec2d8f2c6070d3a55482afd5b8dc89629dbdc6d930605f67d80f189342",
+                "f00fd0e42a6010f5d90030dd043848da83d548a283706f41b51c",
+            ),
+            concat!(
+                "0x000000000000000000000000000000000000000000000000000000000000004000000000000000",
+                "000000000000000000000000000000000000000000000000c0000000000000000000000000000000",
+                "00000000000000000000000000000000413661240275f850ae62ab315cc30d049f1d8a3c45c2142a",
+                "4ebe473c5cbbb571107c84b1133e3467570f263efbb887aa995c71b5eeddfb502a3bce597a05b048",
+                "f41c0000000000000000000000000000000000000000000000000000000000000000000000000000",
+                "000000000000000000000000000000000000000000000000418b9f6bf5fcb66bf1f917ec2d8f2c60",
+                "70d3a55482afd5b8dc89629dbdc6d930605f67d80f189342f00fd0e42a6010f5d90030dd043848da",
+                "83d548a283706f41b51c000000000000000000000000000000000000000000000000000000000000",
+                "00",
+            ),
+        ),
+        vector(
+            "withdrawal",
+            5,
+            concat!(
+                "0xb5da5e5000000000000000000000000000000000000000000000000000000000000003e8000000",
+                "00000000000000000000000000000000000000000000000000000023280000000000000000000000",
+                "0000000000000000000000000000000000000003e8",
+            ),
+            "0xfad94890c0190e963fb7f35696c9f5f8e590917a77e7d9f22e9f19aa4ea5b132",
+            concat!(
+                "0x8eea6b928607617eef8eb93d6287c3d7082d3798b3f5f5967b9730fddb77914a58610117356bec",
+                "2ee8a0c3d0d5b26538d6424a9c21006eb93c6009b8b1f3a3921b",
+            ),
+            concat!(
+                "0x54abc2c95e83f62027aec87fc4ce7980f5486cd5e615a013fe7c82fe671b223b5345edb836e226",
+                "ae18d13c22aa800982bb2e5cdc9f906acdcace6dc46a2a75241c",
+            ),
+            concat!(
+                "0x000000000000000000000000000000000000000000000000000000000000004000000000000000",
+                "000000000000000000000000000000000000000000000000c0000000000000000000000000000000",
+                "00000000000000000000000000000000418eea6b928607617eef8eb93d6287c3d7082d3798b3f5f5",
+                "967b9730fddb77914a58610117356bec2ee8a0c3d0d5b26538d6424a9c21006eb93c6009b8b1f3a3",
+                "921b0000000000000000000000000000000000000000000000000000000000000000000000000000",
+                "0000000000000000000000000000000000000000000000004154abc2c95e83f62027aec87fc4ce79",
+                "80f5486cd5e615a013fe7c82fe671b223b5345edb836e226ae18d13c22aa800982bb2e5cdc9f906a",
+                "cdcace6dc46a2a75241c000000000000000000000000000000000000000000000000000000000000",
+                "00",
+            ),
+        ),
+    ]
+}
+
+fn vector(
+    name: &str,
+    nonce: u64,
+    call_data: &str,
+    user_op_hash: &str,
+    signature_a: &str,
+    signature_b: &str,
+    signature: &str,
+) -> TestVector {
+    let channel = CHANNEL.parse().unwrap();
+    TestVector {
+        name: name.to_string(),
+        chain_id: CHAIN_ID.into(),
+        entry_point: ENTRY_POINT.parse().unwrap(),
+        factory: FACTORY.parse().unwrap(),
+        salt: SALT.into(),
+        key_a: KEY_A.parse().unwrap(),
+        key_b: KEY_B.parse().unwrap(),
+        party_a: PARTY_A.parse().unwrap(),
+        party_b: PARTY_B.parse().unwrap(),
+        channel,
+        userop: UserOp {
+            sender: channel,
+            nonce: nonce.into(),
+            init_code: Bytes::new(),
+            call_data: call_data.parse().unwrap(),
+            call_gas_limit: 200_000u64.into(),
+            verification_gas_limit: 1_500_000u64.into(),
+            pre_verificaiton_gas: 200_000u64.into(),
+            max_fee_per_gas: 100_000_000u64.into(),
+            max_priority_fee_per_gas: 100_000_000u64.into(),
+            paymaster_and_data: Bytes::new(),
+            signature: signature.parse().unwrap(),
+        },
+        user_op_hash: user_op_hash.parse().unwrap(),
+        signature_a: signature_a.parse().unwrap(),
+        signature_b: signature_b.parse().unwrap(),
+    }
+}
+
+/// Recomputes the outputs of the vector the way channels do, failing on the first mismatch.
+pub async fn check(vector: &TestVector) -> Result<(), VectorError> {
+    let wallet_a = LocalWallet::from_bytes(vector.key_a.as_bytes())
+        .map_err(|_| VectorError::Mismatch("key_a"))?;
+    let wallet_b = LocalWallet::from_bytes(vector.key_b.as_bytes())
+        .map_err(|_| VectorError::Mismatch("key_b"))?;
+    expect(wallet_a.address() == vector.party_a, "party_a")?;
+    expect(wallet_b.address() == vector.party_b, "party_b")?;
+    let channel = channel_address(vector.factory, vector.party_a, vector.party_b, vector.salt);
+    expect(channel == vector.channel, "channel")?;
+
+    let call_gas_limit = match AAChannelCalls::decode(&vector.userop.call_data) {
+        Ok(AAChannelCalls::Dispute(_)) => CALL_GAS_LIMIT_DISPUTE,
+        Ok(AAChannelCalls::CoopWithdraw(_)) => CALL_GAS_LIMIT_COOP,
+        _ => return Err(VectorError::Mismatch("call_data")),
+    };
+    let mut built = UserOpBuilder::new(channel, vector.userop.nonce).build();
+    built.call_data = vector.userop.call_data.clone();
+    built.call_gas_limit = call_gas_limit.into();
+    expect(same_apart_from_signature(&built, &vector.userop), "userop")?;
+
+    let hash = vector
+        .userop
+        .get_user_op_hash(vector.entry_point, vector.chain_id)
+        .map_err(|_| VectorError::Mismatch("user_op_hash"))?;
+    let hash = H256(hash.0);
+    expect(hash == vector.user_op_hash, "user_op_hash")?;
+
+    let signature_a = wallet_a.sign_message(hash.0).await.unwrap().to_vec();
+    let signature_b = wallet_b.sign_message(hash.0).await.unwrap().to_vec();
+    expect(signature_a == vector.signature_a.to_vec(), "signature_a")?;
+    expect(signature_b == vector.signature_b.to_vec(), "signature_b")?;
+    let signature = encode_pair(signature_a.into(), signature_b.into());
+    expect(signature == vector.userop.signature, "signature")?;
+    expect(
+        verify_pair(&signature, hash, vector.party_a, vector.party_b).is_ok(),
+        "signature",
+    )
+}
+
+fn expect(matches: bool, field: &'static str) -> Result<(), VectorError> {
+    if matches {
+        Ok(())
+    } else {
+        Err(VectorError::Mismatch(field))
+    }
+}
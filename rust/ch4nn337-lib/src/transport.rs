@@ -0,0 +1,21 @@
+//! Ways of getting messages to the counterparty. All of them carry messages sealed to the
+//! counterparty (see [`crate::envelope`]), so they do not have to be trusted.
+use crate::Channel;
+use async_trait::async_trait;
+
+#[async_trait]
+pub trait Transport {
+    type Error: std::error::Error + Send + Sync + 'static;
+
+    /// Delivers a message to the counterparty of the channel.
+    async fn send(&self, channel: &Channel, message: &str) -> Result<(), Self::Error>;
+
+    /// Returns the messages for us that arrived since the cursor, with the cursor to continue
+    /// from next time. What the cursor counts is up to the transport, 0 always starts from the
+    /// beginning.
+    async fn receive(
+        &self,
+        channel: &Channel,
+        since: u64,
+    ) -> Result<(Vec<String>, u64), Self::Error>;
+}
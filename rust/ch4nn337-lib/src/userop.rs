@@ -0,0 +1,94 @@
+//! Construction of the userops of a channel, so every message type shares the gas constants and
+//! the handling of the init code.
+use crate::{
+    Channel, Error, MAX_FEE_PER_GAS, PRE_VERIFICATION_GAS, PRIORITY_FEE, VERIFICATION_GAS_LIMIT,
+};
+use ethers::abi::AbiEncode;
+use ethers::types::userop::UserOp;
+use ethers::types::{Address, Bytes, U256};
+
+/// Builds an unsigned userop of the channel.
+pub(crate) struct UserOpBuilder {
+    sender: Address,
+    nonce: U256,
+    /// empty once the channel is deployed
+    init_code: Bytes,
+    call_data: Bytes,
+    call_gas_limit: U256,
+}
+
+impl UserOpBuilder {
+    pub(crate) fn new(sender: Address, nonce: U256) -> UserOpBuilder {
+        UserOpBuilder {
+            sender,
+            nonce,
+            init_code: Bytes::new(),
+            call_data: Bytes::new(),
+            call_gas_limit: U256::zero(),
+        }
+    }
+
+    /// Deploys the channel with the userop, which bundlers only accept while it is not deployed.
+    pub(crate) fn init_code(mut self, init_code: Bytes) -> UserOpBuilder {
+        self.init_code = init_code;
+        self
+    }
+
+    /// The call of the channel contract the userop makes.
+    pub(crate) fn call(mut self, call: impl AbiEncode, call_gas_limit: u64) -> UserOpBuilder {
+        self.call_data = call.encode().into();
+        self.call_gas_limit = call_gas_limit.into();
+        self
+    }
+
+    pub(crate) fn build(self) -> UserOp {
+        UserOp {
+            sender: self.sender,
+            nonce: self.nonce,
+            init_code: self.init_code,
+            call_data: self.call_data,
+            call_gas_limit: self.call_gas_limit,
+            verification_gas_limit: VERIFICATION_GAS_LIMIT.into(),
+            pre_verificaiton_gas: PRE_VERIFICATION_GAS.into(),
+            max_fee_per_gas: MAX_FEE_PER_GAS.into(),
+            max_priority_fee_per_gas: PRIORITY_FEE.into(),
+            paymaster_and_data: Bytes::new(),
+            signature: Bytes::new(),
+        }
+    }
+}
+
+impl Channel {
+    /// A builder for our next outgoing userop, which deploys the channel unless it is deployed.
+    pub(crate) fn userop(&self, deployed: bool) -> Result<UserOpBuilder, Error> {
+        let builder = UserOpBuilder::new(self.address, self.next_outgoing_nonce());
+        Ok(if deployed {
+            builder
+        } else {
+            builder.init_code(self.init_code()?)
+        })
+    }
+
+    /// Checks the init code of an incoming userop against the deployment of the channel. Once
+    /// deployed, userops must not carry init code. Before, withdrawals are submitted right away
+    /// and need it, while transfers may also leave it out, as the channel can be deployed before
+    /// they are used in a dispute.
+    pub(crate) fn check_init_code(
+        &self,
+        userop: &UserOp,
+        deployed: bool,
+        withdrawal: bool,
+    ) -> Result<(), Error> {
+        let valid = if deployed {
+            userop.init_code.is_empty()
+        } else {
+            // transfers usually leave it out, which spares encoding ours
+            (!withdrawal && userop.init_code.is_empty()) || userop.init_code == self.init_code()?
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(Error::IllegalInitcode)
+        }
+    }
+}
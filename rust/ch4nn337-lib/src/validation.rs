@@ -0,0 +1,142 @@
+//! Relaxed checks of the gas fields of incoming userops. By default a userop of the counterparty
+//! has to use exactly our fee and gas constants. A [`ValidationPolicy`] accepts deviations within
+//! bounds, e.g. when the counterparty uses updated gas prices.
+//!
+//! Relaxing these checks means signing userops that may cost more than ours, paid from the
+//! channel deposit. Sender, nonce, init code, calldata, paymaster and signature are always
+//! checked.
+//!
+//! A refused userop reports which field failed with ours and theirs, see [`ConstantError`] and
+//! [`SignatureError`], so mismatches with other implementations show without a debugger.
+pub use ch4nn337_sys::signature::SignatureError;
+use ethers::types::{Bytes, U256};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::warn;
+
+/// A fee or gas field of an incoming userop we do not accept, or a paymaster we do not use.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ConstantError {
+    #[error("paymaster and data has to be empty, got {0}")]
+    PaymasterAndData(Bytes),
+    #[error("max fee per gas {actual} not accepted, ours is {expected}")]
+    MaxFeePerGas { expected: U256, actual: U256 },
+    #[error("max priority fee per gas {actual} not accepted, ours is {expected}")]
+    MaxPriorityFeePerGas { expected: U256, actual: U256 },
+    #[error("pre-verification gas {actual} not accepted, ours is {expected}")]
+    PreVerificationGas { expected: U256, actual: U256 },
+    #[error("verification gas limit {actual} not accepted, ours is {expected}")]
+    VerificationGasLimit { expected: U256, actual: U256 },
+    #[error("call gas limit {actual} not accepted, ours is {expected}")]
+    CallGasLimit { expected: U256, actual: U256 },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ValidationPolicy {
+    /// highest accepted max fee per gas, `None` to only accept ours
+    pub max_fee_per_gas: Option<U256>,
+    /// highest accepted max priority fee per gas, `None` to only accept ours
+    pub max_priority_fee_per_gas: Option<U256>,
+    /// how many percent the gas limits may deviate from ours, zero to only accept ours
+    pub gas_limit_tolerance: u64,
+}
+
+impl ValidationPolicy {
+    /// Whether a fee is acceptable. Nonzero fees below ours only delay inclusion.
+    fn fee(&self, name: &str, actual: U256, ours: U256, cap: Option<U256>) -> bool {
+        if actual == ours {
+            return true;
+        }
+        let accepted = cap.is_some_and(|cap| !actual.is_zero() && actual <= cap.max(ours));
+        if accepted {
+            warn!(%actual, %ours, "accepting {name} deviating from ours");
+        }
+        accepted
+    }
+
+    pub(crate) fn max_fee(&self, actual: U256, ours: U256) -> Result<(), ConstantError> {
+        self.fee("max fee per gas", actual, ours, self.max_fee_per_gas)
+            .then_some(())
+            .ok_or(ConstantError::MaxFeePerGas {
+                expected: ours,
+                actual,
+            })
+    }
+
+    pub(crate) fn priority_fee(&self, actual: U256, ours: U256) -> Result<(), ConstantError> {
+        self.fee(
+            "max priority fee per gas",
+            actual,
+            ours,
+            self.max_priority_fee_per_gas,
+        )
+        .then_some(())
+        .ok_or(ConstantError::MaxPriorityFeePerGas {
+            expected: ours,
+            actual,
+        })
+    }
+
+    pub(crate) fn pre_verification_gas(
+        &self,
+        actual: U256,
+        ours: U256,
+    ) -> Result<(), ConstantError> {
+        self.gas_limit("pre-verification gas", actual, ours)
+            .then_some(())
+            .ok_or(ConstantError::PreVerificationGas {
+                expected: ours,
+                actual,
+            })
+    }
+
+    pub(crate) fn verification_gas_limit(
+        &self,
+        actual: U256,
+        ours: U256,
+    ) -> Result<(), ConstantError> {
+        self.gas_limit("verification gas limit", actual, ours)
+            .then_some(())
+            .ok_or(ConstantError::VerificationGasLimit {
+                expected: ours,
+                actual,
+            })
+    }
+
+    pub(crate) fn call_gas_limit(&self, actual: U256, ours: U256) -> Result<(), ConstantError> {
+        self.gas_limit("call gas limit", actual, ours)
+            .then_some(())
+            .ok_or(ConstantError::CallGasLimit {
+                expected: ours,
+                actual,
+            })
+    }
+
+    /// Whether a gas limit is within the tolerance around ours.
+    fn gas_limit(&self, name: &str, actual: U256, ours: U256) -> bool {
+        if actual == ours {
+            return true;
+        }
+        if self.gas_limit_tolerance == 0 {
+            return false;
+        }
+        let tolerance = U256::from(self.gas_limit_tolerance);
+        let hundred = U256::from(100);
+        // the limits are the counterparty's, so anything overflowing is far off ours
+        let bounds = (
+            actual.checked_mul(hundred),
+            ours.checked_mul(hundred.saturating_sub(tolerance)),
+            hundred
+                .checked_add(tolerance)
+                .and_then(|factor| ours.checked_mul(factor)),
+        );
+        let (Some(scaled), Some(low), Some(high)) = bounds else {
+            return false;
+        };
+        let accepted = scaled >= low && scaled <= high;
+        if accepted {
+            warn!(%actual, %ours, "accepting {name} deviating from ours");
+        }
+        accepted
+    }
+}
@@ -0,0 +1,224 @@
+//! Verification of the channel's on-chain state with `eth_getProof`, so a lying RPC can not make
+//! us believe in wrong balances or a dispute that does not exist. The proofs are checked against
+//! the state root of a block whose hash has to come from a trusted source, e.g. a light client.
+use crate::chain::ChainClient;
+use crate::retry::RetryPolicy;
+use crate::snapshot::AccountState;
+use crate::Error;
+use ethers::types::{Address, Block, BlockId, Bytes, H256, U256};
+use ethers::utils::keccak256;
+use ethers::utils::rlp::{self, DecoderError, Rlp, RlpStream};
+use thiserror::Error;
+
+/// keccak256 of the empty byte string, the code hash of accounts without code
+const EMPTY_CODE_HASH: H256 = H256([
+    0xc5, 0xd2, 0x46, 0x01, 0x86, 0xf7, 0x23, 0x3c, 0x92, 0x7e, 0x7d, 0xb2, 0xdc, 0xc7, 0x03, 0xc0,
+    0xe5, 0x00, 0xb6, 0x53, 0xca, 0x82, 0x27, 0x3b, 0x7b, 0xfa, 0xd8, 0x04, 0x5d, 0x85, 0xa4, 0x70,
+]);
+
+/// root of the empty trie
+const EMPTY_ROOT: H256 = H256([
+    0x56, 0xe8, 0x1f, 0x17, 0x1b, 0xcc, 0x55, 0xa6, 0xff, 0x83, 0x45, 0xe6, 0x92, 0xc0, 0xf8, 0x6e,
+    0x5b, 0x48, 0xe0, 0x1b, 0x99, 0x6c, 0xad, 0xc0, 0x01, 0x62, 0x2f, 0xb5, 0xe3, 0x63, 0xb4, 0x21,
+]);
+
+// storage layout of AAChannel
+const SLOT_BALANCE_A: u64 = 1;
+const SLOT_BALANCE_B: u64 = 2;
+const SLOT_DISPUTE: u64 = 3;
+
+#[derive(Error, Debug)]
+pub enum ProofError {
+    #[error("trusted block not found")]
+    UnknownBlock,
+    #[error("block header does not match the trusted hash")]
+    HeaderMismatch,
+    #[error("proof is incomplete")]
+    Incomplete,
+    #[error("proof node does not match its hash")]
+    HashMismatch,
+    #[error("invalid trie node")]
+    InvalidNode,
+    #[error("proven value does not match the reported one")]
+    ValueMismatch,
+    #[error("{0}")]
+    Rlp(#[from] DecoderError),
+}
+
+/// The channel account as proven against the trusted block.
+pub(crate) async fn verified_account<C: ChainClient + ?Sized>(
+    client: &C,
+    retry: &RetryPolicy,
+    address: Address,
+    block_hash: H256,
+) -> Result<AccountState, Error> {
+    let block = retry
+        .run(|| client.block(BlockId::Hash(block_hash)), |_| true)
+        .await?
+        .ok_or(ProofError::UnknownBlock)?;
+    if header_hash(&block) != block_hash {
+        return Err(ProofError::HeaderMismatch.into());
+    }
+
+    let slots: Vec<H256> = [SLOT_BALANCE_A, SLOT_BALANCE_B, SLOT_DISPUTE]
+        .into_iter()
+        .map(H256::from_low_u64_be)
+        .collect();
+    let proof = retry
+        .run(
+            || client.get_proof(address, slots.clone(), BlockId::Hash(block_hash)),
+            |_| true,
+        )
+        .await?;
+
+    let mut account = RlpStream::new_list(4);
+    account
+        .append(&proof.nonce)
+        .append(&proof.balance)
+        .append(&proof.storage_hash)
+        .append(&proof.code_hash);
+    match verify_proof(block.state_root, &keccak256(address), &proof.account_proof)? {
+        Some(proven) if proven == account.out().to_vec() => {}
+        None if proof.nonce.is_zero()
+            && proof.balance.is_zero()
+            && proof.storage_hash == EMPTY_ROOT
+            && proof.code_hash == EMPTY_CODE_HASH => {}
+        _ => return Err(ProofError::ValueMismatch.into()),
+    }
+
+    if proof.storage_proof.len() != slots.len() {
+        return Err(ProofError::Incomplete.into());
+    }
+    let mut values = [U256::zero(); 3];
+    for ((slot, storage), value) in slots.iter().zip(&proof.storage_proof).zip(&mut values) {
+        match verify_proof(proof.storage_hash, &keccak256(slot), &storage.proof)? {
+            Some(proven) if proven == rlp::encode(&storage.value).to_vec() => {}
+            None if storage.value.is_zero() => {}
+            _ => return Err(ProofError::ValueMismatch.into()),
+        }
+        *value = storage.value;
+    }
+
+    // balances are packed behind the party addresses, the dispute fields share one slot
+    let [balance_a, balance_b, dispute] = values;
+    let dispute_value = ((dispute >> 112).low_u128() & ((1 << 96) - 1)) << 32;
+    Ok(AccountState {
+        balance: proof.balance,
+        deployed: proof.code_hash != EMPTY_CODE_HASH,
+        balance_a: (balance_a >> 160).low_u128(),
+        balance_b: (balance_b >> 160).low_u128(),
+        dispute_start_nonce: dispute.low_u128() & ((1 << 112) - 1),
+        dispute_value: dispute_value as i128 >> 32,
+        dispute_timestamp: (dispute >> 208).low_u64(),
+    })
+}
+
+fn header_hash(block: &Block<H256>) -> H256 {
+    let mut rlp = RlpStream::new();
+    rlp.begin_unbounded_list();
+    rlp.append(&block.parent_hash)
+        .append(&block.uncles_hash)
+        .append(&block.author.unwrap_or_default())
+        .append(&block.state_root)
+        .append(&block.transactions_root)
+        .append(&block.receipts_root)
+        .append(&block.logs_bloom.unwrap_or_default())
+        .append(&block.difficulty)
+        .append(&block.number.unwrap_or_default())
+        .append(&block.gas_limit)
+        .append(&block.gas_used)
+        .append(&block.timestamp)
+        .append(&block.extra_data)
+        .append(&block.mix_hash.unwrap_or_default())
+        .append(&block.nonce.unwrap_or_default());
+    // fields added by later forks, each implies the ones before it
+    if let Some(base_fee) = block.base_fee_per_gas {
+        rlp.append(&base_fee);
+    }
+    if let Some(withdrawals_root) = block.withdrawals_root {
+        rlp.append(&withdrawals_root);
+    }
+    if let (Some(blob_gas_used), Some(excess_blob_gas)) =
+        (block.blob_gas_used, block.excess_blob_gas)
+    {
+        rlp.append(&blob_gas_used).append(&excess_blob_gas);
+    }
+    if let Some(parent_beacon_block_root) = block.parent_beacon_block_root {
+        rlp.append(&parent_beacon_block_root);
+    }
+    rlp.finalize_unbounded_list();
+    H256(keccak256(rlp.out()))
+}
+
+/// Walks a Merkle-Patricia proof from the root to the value stored at `key`. Returns `None` if
+/// the proof shows that there is no such value.
+fn verify_proof(root: H256, key: &[u8], proof: &[Bytes]) -> Result<Option<Vec<u8>>, ProofError> {
+    let path: Vec<u8> = key.iter().flat_map(|b| [b >> 4, b & 0xf]).collect();
+    let mut position = 0;
+    let mut proof = proof.iter();
+    // either the hash of the next node or, for nodes shorter than 32 bytes, the node itself
+    let mut reference = root.as_bytes().to_vec();
+    loop {
+        let node = if reference.len() == 32 {
+            let Some(node) = proof.next() else {
+                return if reference == EMPTY_ROOT.as_bytes() {
+                    Ok(None)
+                } else {
+                    Err(ProofError::Incomplete)
+                };
+            };
+            if keccak256(node) != reference[..] {
+                return Err(ProofError::HashMismatch);
+            }
+            node.to_vec()
+        } else {
+            reference
+        };
+        let node = Rlp::new(&node);
+        let child = match node.item_count()? {
+            17 => {
+                if position == path.len() {
+                    let value = node.at(16)?.data()?;
+                    return Ok((!value.is_empty()).then(|| value.to_vec()));
+                }
+                position += 1;
+                node.at(path[position - 1] as usize)?
+            }
+            2 => {
+                let encoded = node.at(0)?.data()?;
+                let Some(flag) = encoded.first() else {
+                    return Err(ProofError::InvalidNode);
+                };
+                let mut nibbles: Vec<u8> = encoded[1..]
+                    .iter()
+                    .flat_map(|b| [b >> 4, b & 0xf])
+                    .collect();
+                if flag & 0x10 != 0 {
+                    nibbles.insert(0, flag & 0xf);
+                }
+                let leaf = flag & 0x20 != 0;
+                if !path[position..].starts_with(&nibbles) {
+                    return Ok(None);
+                }
+                position += nibbles.len();
+                if leaf {
+                    return Ok(if position == path.len() {
+                        Some(node.at(1)?.data()?.to_vec())
+                    } else {
+                        None
+                    });
+                }
+                node.at(1)?
+            }
+            _ => return Err(ProofError::InvalidNode),
+        };
+        if child.is_empty() {
+            return Ok(None);
+        }
+        reference = if child.is_list() {
+            child.as_raw().to_vec()
+        } else {
+            child.data()?.to_vec()
+        };
+    }
+}
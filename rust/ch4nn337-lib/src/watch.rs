@@ -0,0 +1,195 @@
+//! Waking long running watchers like a monitor on chain activity instead of polling. With a
+//! websocket endpoint, new heads and the `UserOperationEvent`s of the watched channels arrive
+//! through `eth_subscribe`. Without one, or while the connection is down, the watcher falls back
+//! to polling at a fixed interval and reconnects in the background.
+//!
+//! After reconnecting, the logs of the blocks since the last head seen are fetched, so userops
+//! included while disconnected are still reported.
+use crate::chain::ChainClient;
+use crate::retry::RetryPolicy;
+use crate::{Channel, Error};
+use ch4nn337_sys::i_entry_point::UserOperationEventFilter;
+use ethers::contract::EthEvent;
+use ethers::providers::{Middleware, Provider, ProviderError, Ws};
+use ethers::types::{Address, Filter, Log, H256};
+use futures_util::stream::{self, StreamExt};
+use std::collections::BTreeSet;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::{Instant, Interval, MissedTickBehavior};
+use tracing::{debug, info, warn};
+
+/// time between attempts to reconnect the websocket
+const RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+/// Why [`ChainWatcher::next`] returned.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Wakeup {
+    /// a new block, e.g. for checking dispute timers
+    Head(u64),
+    /// userops of these channels were included
+    Activity(Vec<Address>),
+    /// the polling interval passed, without a subscription
+    Tick,
+}
+
+enum Event {
+    Head(u64),
+    Log(Log),
+}
+
+pub struct ChainWatcher {
+    url: Option<String>,
+    /// logs of the watched channels, `None` if there are none
+    filter: Option<Filter>,
+    events: Option<mpsc::Receiver<Event>>,
+    /// last head seen through the subscription, where missed blocks are replayed from
+    last_block: Option<u64>,
+    reconnect_at: Instant,
+    ticks: Interval,
+    retry: RetryPolicy,
+}
+
+impl ChainWatcher {
+    /// Watches through the websocket endpoint at `url` if given, polling every `interval`
+    /// otherwise.
+    pub fn new(url: Option<String>, interval: Duration) -> ChainWatcher {
+        let mut ticks = tokio::time::interval(interval);
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        ChainWatcher {
+            url,
+            filter: None,
+            events: None,
+            last_block: None,
+            reconnect_at: Instant::now(),
+            ticks,
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    /// Sets the channels whose userops to report, resubscribing if they changed.
+    pub fn watch(&mut self, channels: &[&Channel]) {
+        let entry_points: BTreeSet<Address> =
+            channels.iter().map(|channel| channel.entry_point).collect();
+        let senders: BTreeSet<H256> = channels
+            .iter()
+            .map(|channel| H256::from(channel.address))
+            .collect();
+        let filter = (!channels.is_empty()).then(|| {
+            Filter::new()
+                .address(entry_points.into_iter().collect::<Vec<_>>())
+                .topic0(UserOperationEventFilter::signature())
+                .topic2(senders.into_iter().collect::<Vec<_>>())
+        });
+        if filter != self.filter {
+            self.filter = filter;
+            // dropping the receiver ends the subscription
+            self.events = None;
+            self.reconnect_at = Instant::now();
+        }
+    }
+
+    /// Waits for the next new head, activity of a watched channel, or polling interval.
+    pub async fn next<C: ChainClient + ?Sized>(&mut self, client: &C) -> Result<Wakeup, Error> {
+        loop {
+            let reconnect = self.events.is_none() && Instant::now() >= self.reconnect_at;
+            if let (true, Some(url)) = (reconnect, &self.url) {
+                match subscribe(url, self.filter.clone()).await {
+                    Ok(events) => {
+                        info!("subscribed to chain events");
+                        self.events = Some(events);
+                        if let Some(wakeup) = self.replay(client).await? {
+                            return Ok(wakeup);
+                        }
+                    }
+                    Err(err) => {
+                        warn!("unable to subscribe, polling instead: {err}");
+                        self.reconnect_at = Instant::now() + RECONNECT_DELAY;
+                    }
+                }
+            }
+
+            let Some(events) = &mut self.events else {
+                self.ticks.tick().await;
+                return Ok(Wakeup::Tick);
+            };
+            match events.recv().await {
+                Some(Event::Head(number)) => {
+                    self.last_block = Some(number);
+                    return Ok(Wakeup::Head(number));
+                }
+                Some(Event::Log(log)) => return Ok(Wakeup::Activity(senders([log]))),
+                None => {
+                    warn!("subscription dropped, polling until reconnected");
+                    self.events = None;
+                    self.reconnect_at = Instant::now() + RECONNECT_DELAY;
+                }
+            }
+        }
+    }
+
+    /// The activity in the blocks after the last head seen, if any.
+    async fn replay<C: ChainClient + ?Sized>(
+        &mut self,
+        client: &C,
+    ) -> Result<Option<Wakeup>, Error> {
+        let (Some(filter), Some(last_block)) = (&self.filter, self.last_block) else {
+            return Ok(None);
+        };
+        let filter = filter.clone().from_block(last_block + 1);
+        let logs = self.retry.run(|| client.logs(&filter), |_| true).await?;
+        debug!(
+            from = last_block + 1,
+            logs = logs.len(),
+            "replayed missed blocks"
+        );
+        Ok((!logs.is_empty()).then(|| Wakeup::Activity(senders(logs))))
+    }
+}
+
+/// The channels that sent the userops of the logs, each once.
+fn senders(logs: impl IntoIterator<Item = Log>) -> Vec<Address> {
+    logs.into_iter()
+        .filter_map(|log| log.topics.get(2).copied())
+        .map(Address::from)
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+/// Connects and forwards new heads and matching logs until the connection or the receiver is
+/// dropped.
+async fn subscribe(
+    url: &str,
+    filter: Option<Filter>,
+) -> Result<mpsc::Receiver<Event>, ProviderError> {
+    let provider = Provider::<Ws>::connect(url).await?;
+    let (sender, receiver) = mpsc::channel(64);
+    tokio::spawn(async move {
+        let heads = match provider.subscribe_blocks().await {
+            Ok(heads) => heads.filter_map(|block| async move { block.number }),
+            Err(err) => {
+                warn!("unable to subscribe to new heads: {err}");
+                return;
+            }
+        };
+        let heads = heads.map(|number| Event::Head(number.as_u64()));
+        let logs = match &filter {
+            Some(filter) => match provider.subscribe_logs(filter).await {
+                Ok(logs) => logs.map(Event::Log).boxed(),
+                Err(err) => {
+                    warn!("unable to subscribe to logs: {err}");
+                    return;
+                }
+            },
+            None => stream::pending().boxed(),
+        };
+        let mut events = stream::select(heads.boxed(), logs);
+        while let Some(event) = events.next().await {
+            if sender.send(event).await.is_err() {
+                break;
+            }
+        }
+    });
+    Ok(receiver)
+}
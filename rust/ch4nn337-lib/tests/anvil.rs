@@ -0,0 +1,360 @@
+use async_trait::async_trait;
+use ch4nn337_lib::amount::Wei;
+use ch4nn337_lib::chain::{ChainClient, ChainError, TransactionReceipt, UserOperationReceipt};
+use ch4nn337_lib::test_vectors::test_vectors;
+use ch4nn337_lib::{deploy_factory, Channel, ChannelState};
+use ch4nn337_sys::aa_channel::AAChannel;
+use ch4nn337_sys::aa_channel_factory::AAChannelFactory;
+use ch4nn337_sys::artifacts::ENTRYPOINT_BYTECODE;
+use ch4nn337_sys::i_entry_point::{IEntryPoint, UserOperationEventFilter, IENTRYPOINT_ABI};
+use ch4nn337_sys::shared_types::UserOperation;
+use ch4nn337_sys::signature::{decode_pair, encode_pair};
+use ethers::contract::{parse_log, ContractFactory};
+use ethers::middleware::SignerMiddleware;
+use ethers::providers::{Http, Middleware, Provider};
+use ethers::signers::{LocalWallet, Signer};
+use ethers::types::userop::UserOp;
+use ethers::types::{
+    Address, Block, BlockId, Bytes, EIP1186ProofResponse, Filter, Log, Transaction,
+    TransactionRequest, H256, U256, U64,
+};
+use ethers::utils::Anvil;
+use std::collections::HashMap;
+use std::num::NonZeroU128;
+use std::sync::{Arc, Mutex};
+
+/// enough for both balances to cover the prefund the entry point takes for the dispute
+const FUNDING: u128 = 1_000_000_000_000_000_000;
+/// `AAChannel.disputeTimeout`
+const DISPUTE_TIMEOUT: u64 = 15 * 60 * 60;
+
+type Client = SignerMiddleware<Provider<Http>, LocalWallet>;
+
+/// Anvil does not bundle, so userops are put on-chain right away with `handleOps`, paid by the
+/// client.
+struct Bundler {
+    client: Arc<Client>,
+    receipts: Mutex<HashMap<H256, UserOperationReceipt>>,
+}
+
+impl Bundler {
+    fn new(client: Arc<Client>) -> Bundler {
+        Bundler {
+            client,
+            receipts: Mutex::default(),
+        }
+    }
+}
+
+#[async_trait]
+impl ChainClient for Bundler {
+    async fn chain_id(&self) -> Result<U256, ChainError> {
+        ChainClient::chain_id(self.client.as_ref()).await
+    }
+
+    async fn block_number(&self) -> Result<U64, ChainError> {
+        ChainClient::block_number(self.client.as_ref()).await
+    }
+
+    async fn block(&self, block: BlockId) -> Result<Option<Block<H256>>, ChainError> {
+        ChainClient::block(self.client.as_ref(), block).await
+    }
+
+    async fn transaction(&self, hash: H256) -> Result<Option<Transaction>, ChainError> {
+        ChainClient::transaction(self.client.as_ref(), hash).await
+    }
+
+    async fn get_code(&self, address: Address, block: BlockId) -> Result<Bytes, ChainError> {
+        ChainClient::get_code(self.client.as_ref(), address, block).await
+    }
+
+    async fn get_balance(&self, address: Address, block: BlockId) -> Result<U256, ChainError> {
+        ChainClient::get_balance(self.client.as_ref(), address, block).await
+    }
+
+    async fn get_proof(
+        &self,
+        address: Address,
+        slots: Vec<H256>,
+        block: BlockId,
+    ) -> Result<EIP1186ProofResponse, ChainError> {
+        ChainClient::get_proof(self.client.as_ref(), address, slots, block).await
+    }
+
+    async fn call(&self, to: Address, data: Bytes, block: BlockId) -> Result<Bytes, ChainError> {
+        ChainClient::call(self.client.as_ref(), to, data, block).await
+    }
+
+    async fn logs(&self, filter: &Filter) -> Result<Vec<Log>, ChainError> {
+        ChainClient::logs(self.client.as_ref(), filter).await
+    }
+
+    async fn send_user_operation(
+        &self,
+        userop: &UserOp,
+        entry_point: Address,
+    ) -> Result<(), ChainError> {
+        let receipt = IEntryPoint::new(entry_point, self.client.clone())
+            .handle_ops(vec![user_operation(userop)], self.client.address())
+            .send()
+            .await
+            .map_err(ChainError::client)?
+            .await
+            .map_err(ChainError::client)?
+            .expect("anvil mines right away");
+        for log in receipt.logs {
+            let Ok(event) = parse_log::<UserOperationEventFilter>(log) else {
+                continue;
+            };
+            self.receipts.lock().unwrap().insert(
+                H256(event.user_op_hash),
+                UserOperationReceipt {
+                    success: event.success,
+                    actual_gas_cost: event.actual_gas_cost,
+                    receipt: TransactionReceipt {
+                        transaction_hash: receipt.transaction_hash,
+                        block_number: receipt.block_number.expect("mined"),
+                    },
+                },
+            );
+        }
+        Ok(())
+    }
+
+    async fn user_operation_known(&self, hash: H256) -> Result<bool, ChainError> {
+        Ok(self.receipts.lock().unwrap().contains_key(&hash))
+    }
+
+    async fn user_operation_receipt(
+        &self,
+        hash: H256,
+    ) -> Result<Option<UserOperationReceipt>, ChainError> {
+        Ok(self.receipts.lock().unwrap().get(&hash).cloned())
+    }
+}
+
+async fn deploy_entry_point(client: Arc<Client>) -> Address {
+    ContractFactory::new(IENTRYPOINT_ABI.clone(), ENTRYPOINT_BYTECODE.clone(), client)
+        .deploy(())
+        .unwrap()
+        .send()
+        .await
+        .unwrap()
+        .address()
+}
+
+/// Lets the chain time pass, mining a block at the new time.
+async fn increase_time(client: &Client, seconds: u64) {
+    let provider = client.provider();
+    provider
+        .request::<_, serde_json::Value>("evm_increaseTime", [seconds])
+        .await
+        .unwrap();
+    provider
+        .request::<_, serde_json::Value>("evm_mine", None::<()>)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn lifecycle() {
+    let anvil = Anvil::new().spawn();
+    let wallet: LocalWallet = anvil.keys()[0].clone().into();
+    let provider = Provider::<Http>::try_from(anvil.endpoint()).unwrap();
+    let client = Arc::new(SignerMiddleware::new(
+        provider,
+        wallet.with_chain_id(anvil.chain_id()),
+    ));
+    let bundler = Arc::new(Bundler::new(client.clone()));
+
+    let entry_point = deploy_entry_point(client.clone()).await;
+    let factory = deploy_factory(entry_point, client.clone()).await.unwrap();
+
+    let (a, mut b) = Channel::open(
+        anvil.chain_id().into(),
+        entry_point,
+        factory,
+        client.clone(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(a.address(), b.address());
+
+    client
+        .send_transaction(TransactionRequest::pay(a.address(), FUNDING), None)
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+    assert_eq!(
+        a.get_sorted_balances(client.clone()).await.unwrap(),
+        (Wei(FUNDING), Wei::ZERO)
+    );
+
+    a.deploy(client.clone()).await.unwrap();
+    assert!(a.is_deployed(&client).await.unwrap());
+    assert_eq!(
+        a.entry_point_deposit(client.clone()).await.unwrap(),
+        U256::from(FUNDING)
+    );
+    // the contract takes the fees of the dispute from both balances, so both need one
+    AAChannel::new(a.address(), client.clone())
+        .deposit_to_b()
+        .value(FUNDING)
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+    assert_eq!(
+        b.get_sorted_balances(client.clone()).await.unwrap(),
+        (Wei(FUNDING), Wei(FUNDING))
+    );
+
+    let request = a
+        .request_transfer(NonZeroU128::new(400).unwrap(), client.clone())
+        .await
+        .unwrap();
+    let (message, _) = b
+        .receive_message(&request.to_json(), client.clone())
+        .await
+        .unwrap();
+    b.sign_message(message, client.clone()).await.unwrap();
+    assert_eq!(
+        b.get_sorted_balances(client.clone()).await.unwrap(),
+        (Wei(FUNDING + 400), Wei(FUNDING - 400))
+    );
+    assert!(b.get_dispute_info(client.clone()).await.unwrap().is_none());
+
+    // the countersigned transfer passes the contract's own signature check, called as if by the
+    // entry point, and fails with the signatures swapped
+    let channel = AAChannel::new(a.address(), client.clone());
+    let mut userop = b.latest_settleable().unwrap().userop().clone();
+    let hash = userop
+        .get_user_op_hash(entry_point, anvil.chain_id().into())
+        .unwrap()
+        .0;
+    let validation = channel
+        .validate_user_op(user_operation(&userop), hash, U256::zero())
+        .from(entry_point)
+        .call()
+        .await
+        .unwrap();
+    assert_eq!(validation, U256::zero());
+
+    let (signature_a, signature_b) = decode_pair(&userop.signature).unwrap();
+    assert_eq!(
+        encode_pair(signature_a.clone(), signature_b.clone()),
+        userop.signature
+    );
+    userop.signature = encode_pair(signature_b, signature_a);
+    let validation = channel
+        .validate_user_op(user_operation(&userop), hash, U256::zero())
+        .from(entry_point)
+        .call()
+        .await
+        .unwrap();
+    assert_eq!(validation, U256::one());
+
+    // A stops answering, so B disputes with the transfer through the entry point
+    b.start_dispute(bundler.clone()).await.unwrap();
+    assert_eq!(b.state(), ChannelState::Disputed);
+    assert!(b.get_dispute_info(client.clone()).await.unwrap().is_some());
+    let receipt = bundler
+        .user_operation_receipt(H256(hash))
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(receipt.success);
+
+    // closing too early reverts and leaves the dispute in place
+    assert!(b.close_dispute(client.clone()).await.is_err());
+    assert_eq!(b.state(), ChannelState::Disputed);
+
+    increase_time(&client, DISPUTE_TIMEOUT + 1).await;
+    let deposit = a.entry_point_deposit(client.clone()).await.unwrap();
+    assert!(deposit < U256::from(2 * FUNDING), "the dispute paid gas");
+    b.close_dispute(client.clone()).await.unwrap();
+    assert_eq!(b.state(), ChannelState::Closed);
+
+    // the whole deposit is paid out to the parties, how it is split is up to the contract
+    assert!(a
+        .entry_point_deposit(client.clone())
+        .await
+        .unwrap()
+        .is_zero());
+    let payout_a = Middleware::get_balance(client.as_ref(), a.our_address().unwrap(), None)
+        .await
+        .unwrap();
+    let payout_b = Middleware::get_balance(client.as_ref(), b.our_address().unwrap(), None)
+        .await
+        .unwrap();
+    assert!(!payout_a.is_zero() && !payout_b.is_zero());
+    assert_eq!(payout_a + payout_b, deposit);
+}
+
+#[tokio::test]
+async fn vectors_validate() {
+    let anvil = Anvil::new().spawn();
+    let wallet: LocalWallet = anvil.keys()[0].clone().into();
+    let provider = Provider::<Http>::try_from(anvil.endpoint()).unwrap();
+    let client = Arc::new(SignerMiddleware::new(
+        provider.clone(),
+        wallet.with_chain_id(anvil.chain_id()),
+    ));
+    let entry_point_wallet: LocalWallet = anvil.keys()[1].clone().into();
+    let entry_point_client = Arc::new(SignerMiddleware::new(
+        provider,
+        entry_point_wallet.with_chain_id(anvil.chain_id()),
+    ));
+
+    let vectors = test_vectors();
+    let vector = &vectors[0];
+    assert_eq!(anvil.addresses()[1], vector.entry_point);
+    let factory = deploy_factory(vector.entry_point, client.clone())
+        .await
+        .unwrap();
+    assert_eq!(factory, vector.factory);
+    AAChannelFactory::new(factory, client.clone())
+        .create_account(vector.party_a, vector.party_b, vector.salt)
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+    assert!(!Middleware::get_code(client.as_ref(), vector.channel, None)
+        .await
+        .unwrap()
+        .0
+        .is_empty());
+
+    let channel = AAChannel::new(vector.channel, entry_point_client);
+    for vector in &vectors {
+        let validation = channel
+            .validate_user_op(
+                user_operation(&vector.userop),
+                vector.user_op_hash.0,
+                U256::zero(),
+            )
+            .call()
+            .await
+            .unwrap();
+        assert_eq!(validation, U256::zero(), "{}", vector.name);
+    }
+}
+
+fn user_operation(userop: &UserOp) -> UserOperation {
+    UserOperation {
+        sender: userop.sender,
+        nonce: userop.nonce,
+        init_code: userop.init_code.clone(),
+        call_data: userop.call_data.clone(),
+        call_gas_limit: userop.call_gas_limit,
+        verification_gas_limit: userop.verification_gas_limit,
+        pre_verification_gas: userop.pre_verificaiton_gas,
+        max_fee_per_gas: userop.max_fee_per_gas,
+        max_priority_fee_per_gas: userop.max_priority_fee_per_gas,
+        paymaster_and_data: userop.paymaster_and_data.clone(),
+        signature: userop.signature.clone(),
+    }
+}
@@ -0,0 +1,17 @@
+mod common;
+
+use common::{funded_channel, FUNDING};
+
+#[tokio::test]
+async fn reads_reuse_the_cache_within_its_ttl() {
+    let (provider, mock, mut a, _) = funded_channel().await;
+    a.set_cache_ttl(60);
+    a.refresh(provider.clone()).await.unwrap();
+    assert!(a.is_cached());
+
+    mock.set_balance(a.address(), (FUNDING * 2).into());
+    let (cached, _) = a.get_sorted_balances(provider.clone()).await.unwrap();
+    a.invalidate_cache();
+    let (read, _) = a.get_sorted_balances(provider.clone()).await.unwrap();
+    assert!(cached < read);
+}
@@ -0,0 +1,24 @@
+//! Fixtures shared by the integration tests.
+#![allow(dead_code)]
+use ch4nn337_lib::mock::{MockClient, MockMiddleware};
+use ch4nn337_lib::Channel;
+use ethers::types::Address;
+use std::sync::Arc;
+
+pub const FUNDING: u128 = 1_000_000_000_000_000_000;
+
+/// Both sides of a channel on a mocked chain, funded by A but not deployed yet.
+pub async fn funded_channel() -> (Arc<MockMiddleware>, MockClient, Channel, Channel) {
+    let (provider, mock) = MockClient::mocked();
+    let provider = Arc::new(provider);
+    let (a, b) = Channel::open(
+        5.into(),
+        Address::random(),
+        Address::random(),
+        provider.clone(),
+    )
+    .await
+    .unwrap();
+    mock.set_balance(a.address(), FUNDING.into());
+    (provider, mock, a, b)
+}
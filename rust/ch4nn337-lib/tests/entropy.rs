@@ -0,0 +1,30 @@
+use ch4nn337_lib::entropy;
+use ch4nn337_lib::mock::MockClient;
+use ch4nn337_lib::Channel;
+use ethers::types::Address;
+use std::sync::Arc;
+
+#[tokio::test]
+async fn seeded_open_is_reproducible() {
+    let (provider, _) = MockClient::mocked();
+    let provider = Arc::new(provider);
+    let (entry_point, factory) = (Address::random(), Address::random());
+    let mut addresses = vec![];
+    for _ in 0..2 {
+        let (a, b) = Channel::open_with_rng(
+            5.into(),
+            entry_point,
+            factory,
+            &mut entropy::seeded(7),
+            provider.clone(),
+        )
+        .await
+        .unwrap();
+        addresses.push((
+            a.address(),
+            a.our_address().unwrap(),
+            b.our_address().unwrap(),
+        ));
+    }
+    assert_eq!(addresses[0], addresses[1]);
+}
@@ -0,0 +1,31 @@
+mod common;
+
+use ch4nn337_lib::mock::MockChannelState;
+use common::{funded_channel, FUNDING};
+use std::num::NonZeroU128;
+
+#[tokio::test]
+async fn offline_transfer_is_reconciled() {
+    let (provider, mock, mut a, _) = funded_channel().await;
+
+    let payment = || vec![(NonZeroU128::new(400).unwrap(), None)];
+    assert!(a.request_transfer_offline(payment(), 60).await.is_err());
+    a.refresh(provider.clone()).await.unwrap();
+    a.request_transfer_offline(payment(), 60).await.unwrap();
+    assert!(!a.is_reconciled());
+    a.reconcile(provider.clone()).await.unwrap();
+    assert!(a.is_reconciled());
+
+    // deploying the channel invalidates the init code of a transfer requested before
+    a.cancel_pending_message();
+    a.request_transfer_offline(payment(), 60).await.unwrap();
+    mock.deploy_channel(
+        a.address(),
+        MockChannelState {
+            balance_a: FUNDING,
+            ..MockChannelState::default()
+        },
+    );
+    assert!(a.reconcile(provider.clone()).await.is_err());
+    assert!(a.pending_message().is_none());
+}
@@ -0,0 +1,28 @@
+mod common;
+
+use common::funded_channel;
+use std::num::NonZeroU128;
+
+#[tokio::test]
+async fn request_with_same_op_id_is_replayed() {
+    let (provider, _, mut a, _) = funded_channel().await;
+
+    let payments = || vec![(NonZeroU128::new(400).unwrap(), None)];
+    let first = a
+        .request_transfer_batch_once("op", payments(), provider.clone())
+        .await
+        .unwrap();
+    let again = a
+        .request_transfer_batch_once("op", payments(), provider.clone())
+        .await
+        .unwrap();
+    assert_eq!(first.to_json(), again.to_json());
+    assert!(a
+        .request_full_withdraw_once("op", provider.clone())
+        .await
+        .is_err());
+    assert!(a
+        .request_transfer_batch_once("other", payments(), provider.clone())
+        .await
+        .is_err());
+}
@@ -0,0 +1,53 @@
+use ch4nn337_lib::proto::{self, Call};
+use ch4nn337_lib::test_vectors::test_vectors;
+
+#[test]
+fn vectors_hash_and_split() {
+    for vector in test_vectors() {
+        let userop = &vector.userop;
+        assert_eq!(
+            proto::user_op_hash(userop, vector.entry_point, vector.chain_id),
+            vector.user_op_hash,
+            "{}",
+            vector.name
+        );
+        assert_eq!(
+            proto::split_signatures(&userop.signature),
+            Some((vector.signature_a.clone(), vector.signature_b.clone()))
+        );
+        assert_eq!(
+            proto::combine_signatures(vector.signature_a, vector.signature_b),
+            userop.signature
+        );
+        assert!(proto::split_signatures(&vector.user_op_hash.0).is_none());
+    }
+}
+
+#[test]
+fn vectors_calls_round_trip() {
+    for vector in test_vectors() {
+        let call = proto::decode_call(&vector.userop.call_data).unwrap();
+        assert_eq!(proto::encode_call(call), vector.userop.call_data);
+    }
+    let withdrawal = Call::Withdrawal {
+        value_transfer: -5,
+        withdraw_a: 1,
+        withdraw_b: 2,
+    };
+    assert_eq!(
+        proto::decode_call(&proto::encode_call(withdrawal)),
+        Some(withdrawal)
+    );
+    assert_eq!(proto::decode_call(&[0; 4]), None);
+}
+
+#[test]
+fn init_code_calls_the_factory() {
+    let vector = &test_vectors()[0];
+    let init_code = proto::init_code(vector.factory, vector.party_a, vector.party_b, vector.salt);
+    assert_eq!(&init_code[..20], vector.factory.as_bytes());
+    assert_eq!(
+        proto::channel_address(vector.factory, vector.party_a, vector.party_b, vector.salt),
+        vector.channel
+    );
+}
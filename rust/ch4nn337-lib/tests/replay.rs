@@ -0,0 +1,28 @@
+mod common;
+
+use ch4nn337_lib::Error;
+use common::funded_channel;
+use std::num::NonZeroU128;
+
+#[tokio::test]
+async fn same_transfer_is_signed_once() {
+    let (provider, _, mut a, mut b) = funded_channel().await;
+
+    let request = a
+        .request_transfer(NonZeroU128::new(400).unwrap(), provider.clone())
+        .await
+        .unwrap()
+        .to_json();
+    // received twice before signing, only the first is signed
+    let (first, _) = b.receive_message(&request, provider.clone()).await.unwrap();
+    let (second, _) = b.receive_message(&request, provider.clone()).await.unwrap();
+    b.sign_message(first, provider.clone()).await.unwrap();
+    assert!(matches!(
+        b.sign_message(second, provider.clone()).await,
+        Err(Error::DuplicateMessage)
+    ));
+    assert!(matches!(
+        b.receive_message(&request, provider.clone()).await,
+        Err(Error::DuplicateMessage)
+    ));
+}
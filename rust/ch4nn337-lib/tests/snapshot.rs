@@ -0,0 +1,64 @@
+mod common;
+
+use ch4nn337_lib::chain::BlockPolicy;
+use ch4nn337_lib::mock::{MockChannelState, MockClient};
+use ch4nn337_lib::snapshot;
+use ch4nn337_lib::Channel;
+use common::FUNDING;
+use ethers::types::Address;
+use std::sync::Arc;
+
+#[tokio::test]
+async fn snapshot_matches_single_reads() {
+    let (provider, mock) = MockClient::mocked();
+    let provider = Arc::new(provider);
+    let mut channels = vec![];
+    for _ in 0..3 {
+        let (a, _) = Channel::open(
+            5.into(),
+            Address::random(),
+            Address::random(),
+            provider.clone(),
+        )
+        .await
+        .unwrap();
+        channels.push(a);
+    }
+    // one waiting for deployment, one deployed and one disputed
+    mock.set_balance(channels[0].address(), FUNDING.into());
+    mock.deploy_channel(
+        channels[1].address(),
+        MockChannelState {
+            balance_a: FUNDING,
+            ..MockChannelState::default()
+        },
+    );
+    mock.deploy_channel(
+        channels[2].address(),
+        MockChannelState {
+            balance_a: 600,
+            balance_b: 400,
+            dispute_start_nonce: 3,
+            dispute_value: 100,
+            dispute_timestamp: 1_700_000_000,
+        },
+    );
+
+    let refs: Vec<_> = channels.iter().collect();
+    let accounts = snapshot::read(provider.as_ref(), &refs, BlockPolicy::Latest)
+        .await
+        .unwrap();
+    assert_eq!(accounts.len(), channels.len());
+    for (channel, account) in channels.iter().zip(&accounts) {
+        assert_eq!(
+            channel.balances_at(account).unwrap(),
+            channel.get_sorted_balances(provider.clone()).await.unwrap()
+        );
+        assert_eq!(
+            channel.dispute_info_at(account).unwrap(),
+            channel.get_dispute_info(provider.clone()).await.unwrap()
+        );
+    }
+    assert!(!accounts[0].deployed);
+    assert!(channels[2].dispute_info_at(&accounts[2]).unwrap().is_some());
+}
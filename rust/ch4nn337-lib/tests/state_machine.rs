@@ -0,0 +1,112 @@
+mod common;
+
+use ch4nn337_lib::amount::Wei;
+use common::{funded_channel, FUNDING};
+use ethers::types::U256;
+use proptest::prelude::*;
+use std::num::NonZeroU128;
+
+#[derive(Debug, Clone)]
+enum Op {
+    Transfer(usize, u128),
+    Withdraw(usize),
+    Cancel(usize),
+    /// hand the side's outstanding request to the other side, which receives and signs it
+    Deliver(usize),
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    let side = 0..2usize;
+    prop_oneof![
+        (side.clone(), 1..FUNDING).prop_map(|(side, wei)| Op::Transfer(side, wei)),
+        side.clone().prop_map(Op::Withdraw),
+        side.clone().prop_map(Op::Cancel),
+        side.prop_map(Op::Deliver),
+    ]
+}
+
+async fn run(ops: Vec<Op>) {
+    let (provider, _, a, b) = funded_channel().await;
+
+    let mut channels = [a, b];
+    let mut outbox: [Option<String>; 2] = [None, None];
+    let mut last_nonces = [U256::zero(); 2];
+    for op in ops {
+        match op {
+            Op::Transfer(side, wei) => {
+                let wei = NonZeroU128::new(wei).unwrap();
+                if let Ok(request) = channels[side].request_transfer(wei, provider.clone()).await {
+                    outbox[side] = Some(request.to_json());
+                }
+            }
+            Op::Withdraw(side) => {
+                if let Ok(request) = channels[side].request_full_withdraw(provider.clone()).await {
+                    outbox[side] = Some(request.to_json());
+                }
+            }
+            Op::Cancel(side) => {
+                channels[side].cancel_pending_message();
+                outbox[side] = None;
+            }
+            Op::Deliver(side) => {
+                let Some(request) = outbox[side].take() else {
+                    continue;
+                };
+                let other = &mut channels[1 - side];
+                if let Ok((message, _)) = other.receive_message(&request, provider.clone()).await {
+                    other.sign_message(message, provider.clone()).await.unwrap();
+                }
+            }
+        }
+
+        for (channel, last_nonce) in channels.iter().zip(&mut last_nonces) {
+            let (ours, theirs) = channel.get_sorted_balances(provider.clone()).await.unwrap();
+            assert_eq!(ours.checked_add(theirs), Some(Wei(FUNDING)));
+            assert!(channel.last_nonce() >= *last_nonce);
+            channel.audit().unwrap();
+            *last_nonce = channel.last_nonce();
+        }
+    }
+}
+
+proptest! {
+    #[test]
+    fn channel_state_machine(ops in proptest::collection::vec(op(), 1..32)) {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(run(ops));
+    }
+}
+
+/// Regression test for value transfers flowing the wrong way, which the state machine caught: a
+/// transfer has to move funds from the payer to the payee on both sides, and only the payer's
+/// balance limits what it can transfer.
+#[tokio::test]
+async fn transfers_flow_from_the_payer() {
+    let (provider, _, mut a, mut b) = funded_channel().await;
+    let request = a
+        .request_transfer(NonZeroU128::new(300).unwrap(), provider.clone())
+        .await
+        .unwrap();
+    let (message, _) = b
+        .receive_message(&request.to_json(), provider.clone())
+        .await
+        .unwrap();
+    let response = b.sign_message(message, provider.clone()).await.unwrap();
+    a.receive_response(&response.to_json()).unwrap();
+
+    let a_balances = a.get_sorted_balances(provider.clone()).await.unwrap();
+    let b_balances = b.get_sorted_balances(provider.clone()).await.unwrap();
+    assert_eq!(a_balances, (Wei(FUNDING - 300), Wei(300)));
+    assert_eq!(b_balances, (Wei(300), Wei(FUNDING - 300)));
+
+    assert!(b
+        .request_transfer(NonZeroU128::new(301).unwrap(), provider.clone())
+        .await
+        .is_err());
+    assert!(b
+        .request_transfer(NonZeroU128::new(300).unwrap(), provider.clone())
+        .await
+        .is_ok());
+}
@@ -0,0 +1,42 @@
+mod common;
+
+use ch4nn337_lib::store::{ChannelStore, MemoryStore};
+use common::funded_channel;
+use std::num::NonZeroU128;
+
+#[tokio::test]
+async fn restored_backup_is_synced() {
+    let (provider, _, mut a, mut b) = funded_channel().await;
+
+    let store = MemoryStore::new();
+    for round in 0..2 {
+        if round == 1 {
+            store.save("b", &b, None).await.unwrap();
+        }
+        let request = a
+            .request_transfer(NonZeroU128::new(400).unwrap(), provider.clone())
+            .await
+            .unwrap();
+        let (message, _) = b
+            .receive_message(&request.to_json(), provider.clone())
+            .await
+            .unwrap();
+        let response = b.sign_message(message, provider.clone()).await.unwrap();
+        a.receive_response(&response.to_json()).unwrap();
+    }
+
+    // the backup only has the first transfer
+    let (mut restored, _) = store.load("b").await.unwrap();
+    assert!(restored.last_nonce() < b.last_nonce());
+    let response = a.answer_sync(&restored.sync_request()).unwrap();
+    assert_eq!(restored.apply_sync(response).unwrap(), 1);
+    assert_eq!(restored.last_nonce(), b.last_nonce());
+    assert_eq!(
+        restored
+            .get_sorted_balances(provider.clone())
+            .await
+            .unwrap(),
+        b.get_sorted_balances(provider.clone()).await.unwrap()
+    );
+    restored.audit().unwrap();
+}
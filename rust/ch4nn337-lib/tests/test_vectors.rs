@@ -0,0 +1,19 @@
+use ch4nn337_lib::test_vectors::{check, test_vectors, TestVector};
+
+#[tokio::test]
+async fn vectors_match() {
+    for vector in test_vectors() {
+        check(&vector)
+            .await
+            .unwrap_or_else(|err| panic!("{}: {err}", vector.name));
+    }
+}
+
+#[tokio::test]
+async fn vectors_survive_json() {
+    let json = serde_json::to_string(&test_vectors()).unwrap();
+    let vectors: Vec<TestVector> = serde_json::from_str(&json).unwrap();
+    for vector in vectors {
+        check(&vector).await.unwrap();
+    }
+}
@@ -0,0 +1,59 @@
+mod common;
+
+use ch4nn337_lib::proto;
+use ch4nn337_lib::validation::{ConstantError, SignatureError, ValidationPolicy};
+use ch4nn337_lib::{Channel, Error};
+use common::funded_channel;
+use ethers::types::U256;
+use std::num::NonZeroU128;
+
+#[tokio::test]
+async fn refused_signatures_tell_why() {
+    let (provider, _, mut a, _) = funded_channel().await;
+
+    // our own request only carries our signature, not the pair of a response
+    let request = a
+        .request_transfer(NonZeroU128::new(400).unwrap(), provider.clone())
+        .await
+        .unwrap();
+    assert!(matches!(
+        a.receive_response(&request.to_json()),
+        Err(Error::IllegalSignature(SignatureError::MalformedPair))
+    ));
+}
+
+#[tokio::test]
+async fn overflowing_gas_fields_are_refused() {
+    let (provider, _, a, mut b) = funded_channel().await;
+
+    // a watch-only copy of A builds the request, A signs it with the gas fields changed
+    let json = serde_json::to_string(&a).unwrap();
+    let mut watch_only = serde_json::from_str::<Channel>(&json)
+        .unwrap()
+        .into_watch_only();
+    watch_only
+        .request_transfer(NonZeroU128::new(400).unwrap(), provider.clone())
+        .await
+        .unwrap();
+    let mut request = watch_only.signing_request().unwrap();
+    request.userop.pre_verificaiton_gas = U256::MAX;
+    request.userop.verification_gas_limit = U256::MAX;
+    request.userop.call_gas_limit = U256::MAX;
+    request.hash = proto::user_op_hash(&request.userop, request.entry_point, request.chain_id);
+    let mut userop = request.userop.clone();
+    userop.signature = a.sign_request(&request).await.unwrap();
+    let message = serde_json::to_string(&userop).unwrap();
+
+    for tolerance in [0, 10] {
+        b.set_validation_policy(ValidationPolicy {
+            gas_limit_tolerance: tolerance,
+            ..ValidationPolicy::default()
+        });
+        assert!(matches!(
+            b.receive_message(&message, provider.clone()).await,
+            Err(Error::IllegalConstant(
+                ConstantError::PreVerificationGas { actual, .. }
+            )) if actual == U256::MAX
+        ));
+    }
+}
@@ -0,0 +1,32 @@
+mod common;
+
+use ch4nn337_lib::{ChannelState, Message};
+use common::funded_channel;
+use std::time::Duration;
+
+#[tokio::test]
+async fn dropped_withdrawal_stays_recorded() {
+    let (provider, mock, mut a, mut b) = funded_channel().await;
+
+    let request = a.request_full_withdraw(provider.clone()).await.unwrap();
+    let (message, _) = b
+        .receive_message(&request.to_json(), provider.clone())
+        .await
+        .unwrap();
+
+    // give up while the withdrawal is being handed to the bundler
+    mock.stall_bundler(true);
+    let signing = b.sign_message(message, provider.clone());
+    assert!(tokio::time::timeout(Duration::from_millis(100), signing)
+        .await
+        .is_err());
+    assert_eq!(b.state(), ChannelState::PendingWithdrawal);
+    assert!(matches!(b.messages().last(), Some(Message::Withdrawal(_))));
+    assert!(mock.user_operations().is_empty());
+
+    mock.stall_bundler(false);
+    let response = b.resubmit_withdrawal(provider.clone()).await.unwrap();
+    assert_eq!(mock.user_operations().len(), 1);
+    a.receive_response(&response.to_json()).unwrap();
+    assert_eq!(a.state(), ChannelState::PendingWithdrawal);
+}
@@ -0,0 +1,92 @@
+//! Warns if the Solidity sources changed since the bindings were generated from them, see
+//! `artifacts.sha256`. With the `solc` feature, compiles them with forge to embed their bytecode
+//! instead, see `src/artifacts.rs`.
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::Path;
+
+/// root of the foundry project, relative to this crate
+const PROJECT: &str = "../..";
+
+fn main() {
+    println!("cargo:rerun-if-changed=artifacts.sha256");
+    check_sources();
+    #[cfg(feature = "solc")]
+    solc::compile();
+}
+
+fn check_sources() {
+    for line in include_str!("artifacts.sha256").lines() {
+        let Some((hash, source)) = line.split_once("  ") else {
+            continue;
+        };
+        let path = Path::new(PROJECT).join(source);
+        println!("cargo:rerun-if-changed={}", path.display());
+        // built without the sources, e.g. from a package
+        let Ok(contents) = fs::read(&path) else {
+            continue;
+        };
+        if format!("{:x}", Sha256::digest(contents)) != hash {
+            println!(
+                "cargo:warning={source} changed since the bindings were generated, regenerate \
+                 them and artifacts.sha256 or build with the solc feature"
+            );
+        }
+    }
+}
+
+#[cfg(feature = "solc")]
+mod solc {
+    use super::PROJECT;
+    use std::path::{Path, PathBuf};
+    use std::process::Command;
+    use std::{env, fs};
+
+    /// contracts whose bytecode is embedded, with the file they are compiled from
+    const CONTRACTS: [(&str, &str); 4] = [
+        ("AAChannel.sol", "AAChannel"),
+        ("AAChannelFactory.sol", "AAChannelFactory"),
+        ("ERC1967Proxy.sol", "ERC1967Proxy"),
+        // only for deploying on a local node, see test/Dependencies.sol
+        ("EntryPoint.sol", "EntryPoint"),
+    ];
+
+    pub fn compile() {
+        println!("cargo:rerun-if-env-changed=FORGE");
+        let forge = env::var("FORGE").unwrap_or_else(|_| "forge".to_string());
+        let status = Command::new(&forge)
+            .args(["build", "--root", PROJECT])
+            .status()
+            .unwrap_or_else(|err| panic!("unable to run {forge}: {err}"));
+        assert!(status.success(), "forge build failed");
+
+        let out = PathBuf::from(env::var("OUT_DIR").expect("set by cargo"));
+        for (source, contract) in CONTRACTS {
+            let path = Path::new(PROJECT)
+                .join("out")
+                .join(source)
+                .join(format!("{contract}.json"));
+            let artifact = fs::read(&path)
+                .unwrap_or_else(|err| panic!("unable to read {}: {err}", path.display()));
+            let artifact: serde_json::Value =
+                serde_json::from_slice(&artifact).expect("forge writes JSON");
+            for (field, file) in [
+                ("bytecode", format!("{contract}.bin")),
+                ("deployedBytecode", format!("{contract}.deployed.bin")),
+            ] {
+                let object = artifact[field]["object"]
+                    .as_str()
+                    .unwrap_or_else(|| panic!("{contract} has no {field}"));
+                fs::write(out.join(file), decode_hex(object)).expect("OUT_DIR is writable");
+            }
+        }
+    }
+
+    fn decode_hex(hex: &str) -> Vec<u8> {
+        let hex = hex.trim_start_matches("0x");
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).expect("forge writes hex"))
+            .collect()
+    }
+}
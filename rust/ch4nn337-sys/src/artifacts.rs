@@ -0,0 +1,46 @@
+//! Bytecode of the channel contracts, for deploying them and for checking deployed code against
+//! it. Unlike the rest of this crate, this module is written by hand.
+//!
+//! By default this is the bytecode embedded in the generated bindings, and the build warns if the
+//! Solidity sources changed since. With the `solc` feature, the contracts are compiled from source
+//! with forge while building instead, which has to be installed (or given in `FORGE`). The address
+//! of a channel depends on the proxy bytecode, so channels are only found by builds with the same
+//! bytecode as the factory that deploys them.
+//!
+//! The `solc` build also embeds the entry point, which is never deployed by the channel but lets
+//! tests run userops on a local node.
+#[cfg(not(feature = "solc"))]
+pub use crate::aa_channel::{AACHANNEL_BYTECODE, AACHANNEL_DEPLOYED_BYTECODE};
+#[cfg(not(feature = "solc"))]
+pub use crate::aa_channel_factory::{
+    AACHANNELFACTORY_BYTECODE, AACHANNELFACTORY_DEPLOYED_BYTECODE,
+};
+#[cfg(not(feature = "solc"))]
+pub use crate::erc1967_proxy::{ERC1967PROXY_BYTECODE, ERC1967PROXY_DEPLOYED_BYTECODE};
+
+#[cfg(feature = "solc")]
+macro_rules! compiled {
+    ($name:ident, $file:literal) => {
+        pub static $name: ethers::core::types::Bytes = ethers::core::types::Bytes::from_static(
+            include_bytes!(concat!(env!("OUT_DIR"), "/", $file)),
+        );
+    };
+}
+
+#[cfg(feature = "solc")]
+compiled!(AACHANNEL_BYTECODE, "AAChannel.bin");
+#[cfg(feature = "solc")]
+compiled!(AACHANNEL_DEPLOYED_BYTECODE, "AAChannel.deployed.bin");
+#[cfg(feature = "solc")]
+compiled!(AACHANNELFACTORY_BYTECODE, "AAChannelFactory.bin");
+#[cfg(feature = "solc")]
+compiled!(
+    AACHANNELFACTORY_DEPLOYED_BYTECODE,
+    "AAChannelFactory.deployed.bin"
+);
+#[cfg(feature = "solc")]
+compiled!(ERC1967PROXY_BYTECODE, "ERC1967Proxy.bin");
+#[cfg(feature = "solc")]
+compiled!(ERC1967PROXY_DEPLOYED_BYTECODE, "ERC1967Proxy.deployed.bin");
+#[cfg(feature = "solc")]
+compiled!(ENTRYPOINT_BYTECODE, "EntryPoint.bin");
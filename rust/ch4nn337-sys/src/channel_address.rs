@@ -0,0 +1,35 @@
+//! Address derivation of channels, mirroring `AAChannelFactory.getAddress`. Unlike the rest of this
+//! crate, this module is written by hand.
+use crate::aa_channel::InitializeCall;
+use crate::artifacts::ERC1967PROXY_BYTECODE;
+use ethers::core::abi::{self, AbiEncode, Token};
+use ethers::core::types::{Address, U256};
+use ethers::core::utils::{get_contract_address, get_create2_address_from_hash, keccak256};
+
+/// The channel implementation of a factory, which the factory deploys in its constructor as its
+/// first contract.
+pub fn implementation_address(factory: Address) -> Address {
+    // contract accounts start at nonce 1
+    get_contract_address(factory, 1)
+}
+
+/// The address of the channel between the parties with the salt, as deployed by the factory.
+pub fn channel_address(
+    factory: Address,
+    party_a: Address,
+    party_b: Address,
+    salt: U256,
+) -> Address {
+    let constructor_args = abi::encode(&[
+        Token::Address(implementation_address(factory)),
+        Token::Bytes(InitializeCall { party_a, party_b }.encode()),
+    ]);
+    let init_code: Vec<u8> = ERC1967PROXY_BYTECODE
+        .iter()
+        .copied()
+        .chain(constructor_args)
+        .collect();
+    let mut salt_bytes = [0u8; 32];
+    salt.to_big_endian(&mut salt_bytes);
+    get_create2_address_from_hash(factory, salt_bytes, keccak256(init_code))
+}
@@ -3,9 +3,13 @@
 //! This is autogenerated code.
 //! Do not manually edit these files.
 //! These files may be overwritten by the codegen system at any time.
+//! The exceptions are `artifacts`, `channel_address`, `multicall` and `signature`, which are
+//! written by hand.
 pub mod aa_channel;
 pub mod aa_channel_factory;
 pub mod address;
+pub mod artifacts;
+pub mod channel_address;
 pub mod create_2;
 pub mod ecdsa;
 pub mod erc1967_proxy;
@@ -21,8 +25,10 @@ pub mod ierc1822_proxiable;
 pub mod ierc1967;
 pub mod initializable;
 pub mod math;
+pub mod multicall;
 pub mod proxy;
 pub mod shared_types;
+pub mod signature;
 pub mod signed_math;
 pub mod simple_account_factory;
 pub mod storage_slot;
@@ -0,0 +1,107 @@
+//! The part of Multicall3 used to read many channels in one `eth_call`. Unlike the rest of this
+//! crate, this module is written by hand, as the contract is not part of this repository.
+//!
+//! Multicall3 is deployed at the same address on almost every chain, see
+//! <https://github.com/mds1/multicall>.
+use ethers::core::types::{Address, Bytes, H160, U256};
+
+/// Address of Multicall3 on every chain it is deployed to.
+pub const MULTICALL3: Address = H160([
+    0xca, 0x11, 0xbd, 0xe0, 0x59, 0x77, 0xb3, 0x63, 0x11, 0x67, 0x02, 0x88, 0x62, 0xbe, 0x2a, 0x17,
+    0x39, 0x76, 0xca, 0x11,
+]);
+
+///`Call3(address,bool,bytes)`
+#[derive(
+    Clone,
+    ::ethers::contract::EthAbiType,
+    ::ethers::contract::EthAbiCodec,
+    Default,
+    Debug,
+    PartialEq,
+    Eq,
+    Hash
+)]
+pub struct Call3 {
+    pub target: Address,
+    pub allow_failure: bool,
+    pub call_data: Bytes,
+}
+
+///`Result(bool,bytes)`
+#[derive(
+    Clone,
+    ::ethers::contract::EthAbiType,
+    ::ethers::contract::EthAbiCodec,
+    Default,
+    Debug,
+    PartialEq,
+    Eq,
+    Hash
+)]
+pub struct CallResult {
+    pub success: bool,
+    pub return_data: Bytes,
+}
+
+///Container type for all input parameters for the `aggregate3` function with signature `aggregate3((address,bool,bytes)[])` and selector `0x82ad56cb`
+#[derive(
+    Clone,
+    ::ethers::contract::EthCall,
+    ::ethers::contract::EthDisplay,
+    Default,
+    Debug,
+    PartialEq,
+    Eq,
+    Hash
+)]
+#[ethcall(name = "aggregate3", abi = "aggregate3((address,bool,bytes)[])")]
+pub struct Aggregate3Call {
+    pub calls: Vec<Call3>,
+}
+
+///Container type for all return fields from the `aggregate3` function with signature `aggregate3((address,bool,bytes)[])` and selector `0x82ad56cb`
+#[derive(
+    Clone,
+    ::ethers::contract::EthAbiType,
+    ::ethers::contract::EthAbiCodec,
+    Default,
+    Debug,
+    PartialEq,
+    Eq,
+    Hash
+)]
+pub struct Aggregate3Return {
+    pub return_data: Vec<CallResult>,
+}
+
+///Container type for all input parameters for the `getEthBalance` function with signature `getEthBalance(address)` and selector `0x4d2301cc`
+#[derive(
+    Clone,
+    ::ethers::contract::EthCall,
+    ::ethers::contract::EthDisplay,
+    Default,
+    Debug,
+    PartialEq,
+    Eq,
+    Hash
+)]
+#[ethcall(name = "getEthBalance", abi = "getEthBalance(address)")]
+pub struct GetEthBalanceCall {
+    pub addr: Address,
+}
+
+///Container type for all return fields from the `getEthBalance` function with signature `getEthBalance(address)` and selector `0x4d2301cc`
+#[derive(
+    Clone,
+    ::ethers::contract::EthAbiType,
+    ::ethers::contract::EthAbiCodec,
+    Default,
+    Debug,
+    PartialEq,
+    Eq,
+    Hash
+)]
+pub struct GetEthBalanceReturn {
+    pub balance: U256,
+}
@@ -0,0 +1,183 @@
+//! Signature encoding of channel userops, mirroring `AAChannel.validateUserOp`. Unlike the rest of
+//! this crate, this module is written by hand.
+//!
+//! Both parties sign the userop hash as an Ethereum signed message. `dispute` and `coopWithdraw`
+//! carry both signatures as `abi.encode(bytes signatureA, bytes signatureB)`, any other call a
+//! single signature of the party whose turn it is by nonce parity.
+use ethers::core::abi::{self, ParamType, Token};
+use ethers::core::types::{Address, Bytes, Signature, H256};
+use std::fmt;
+
+/// Why the contract would refuse a signature, with the signer it recovers if it gets that far.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignatureError {
+    /// a single signature that does not parse, with its length
+    Malformed(usize),
+    /// a settling signature that is not the encoding of two signatures
+    MalformedPair,
+    Unrecoverable,
+    WrongSigner {
+        expected: Address,
+        actual: Address,
+    },
+}
+
+impl fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignatureError::Malformed(length) => {
+                write!(f, "signature of {length} bytes can not be parsed")
+            }
+            SignatureError::MalformedPair => write!(f, "signature pair can not be decoded"),
+            SignatureError::Unrecoverable => {
+                write!(f, "no signer can be recovered from the signature")
+            }
+            SignatureError::WrongSigner { expected, actual } => {
+                write!(f, "signed by {actual:?} instead of {expected:?}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SignatureError {}
+
+/// Combines the signatures of both parties into the signature of a settling userop.
+pub fn encode_pair(signature_a: Bytes, signature_b: Bytes) -> Bytes {
+    abi::encode(&[
+        Token::Bytes(signature_a.to_vec()),
+        Token::Bytes(signature_b.to_vec()),
+    ])
+    .into()
+}
+
+/// Splits the signature of a settling userop into those of party A and B.
+pub fn decode_pair(signature: &[u8]) -> Option<(Bytes, Bytes)> {
+    let mut tokens = abi::decode(&[ParamType::Bytes, ParamType::Bytes], signature)
+        .ok()?
+        .into_iter()
+        .map(Token::into_bytes);
+    Some((tokens.next()??.into(), tokens.next()??.into()))
+}
+
+/// The signer of a single signature over the userop hash, as `ECDSA.recover` sees it.
+pub fn recover(signature: &[u8], user_op_hash: H256) -> Option<Address> {
+    Signature::try_from(signature)
+        .and_then(|signature| signature.recover(user_op_hash.0.to_vec()))
+        .ok()
+}
+
+/// The signers of both signatures of a settling userop, in the order of party A and B.
+pub fn recover_pair(signature: &[u8], user_op_hash: H256) -> Option<(Address, Address)> {
+    let (signature_a, signature_b) = decode_pair(signature)?;
+    Some((
+        recover(&signature_a, user_op_hash)?,
+        recover(&signature_b, user_op_hash)?,
+    ))
+}
+
+/// Checks the signature of a settling userop of the parties like the contract does, telling which
+/// signature it would refuse and why.
+pub fn verify_pair(
+    signature: &[u8],
+    user_op_hash: H256,
+    party_a: Address,
+    party_b: Address,
+) -> Result<(), SignatureError> {
+    let (signature_a, signature_b) = decode_pair(signature).ok_or(SignatureError::MalformedPair)?;
+    verify_signer(&signature_a, user_op_hash, party_a)?;
+    verify_signer(&signature_b, user_op_hash, party_b)
+}
+
+/// Checks that a single signature over the userop hash is by `expected`, telling apart signatures
+/// that do not parse, do not recover and are by someone else.
+pub fn verify_signer(
+    signature: &[u8],
+    user_op_hash: H256,
+    expected: Address,
+) -> Result<(), SignatureError> {
+    let actual = Signature::try_from(signature)
+        .map_err(|_| SignatureError::Malformed(signature.len()))?
+        .recover(user_op_hash.0.to_vec())
+        .map_err(|_| SignatureError::Unrecoverable)?;
+    if actual != expected {
+        return Err(SignatureError::WrongSigner { expected, actual });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::core::utils::hash_message;
+    use ethers::signers::{LocalWallet, Signer};
+
+    // the first two default anvil accounts
+    const KEY_A: &str = "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80";
+    const KEY_B: &str = "59c6995e998f97a5a0044966f0945389dc9e86dae88c7a8412f4603b6b78690d";
+
+    fn sign(key: &str, user_op_hash: H256) -> (Address, Bytes) {
+        let wallet: LocalWallet = key.parse().unwrap();
+        let signature = wallet.sign_hash(hash_message(user_op_hash)).unwrap();
+        (wallet.address(), signature.to_vec().into())
+    }
+
+    #[test]
+    fn pairs_round_trip() {
+        let (a, b) = (Bytes::from(vec![1; 65]), Bytes::from(vec![2; 3]));
+        let encoded = encode_pair(a.clone(), b.clone());
+        assert_eq!(decode_pair(&encoded), Some((a, b)));
+        assert_eq!(
+            decode_pair(&encode_pair(Bytes::new(), Bytes::new())),
+            Some((Bytes::new(), Bytes::new()))
+        );
+    }
+
+    #[test]
+    fn malformed_pairs_are_refused() {
+        let encoded = encode_pair(vec![1; 65].into(), vec![2; 65].into());
+        assert_eq!(decode_pair(&[]), None);
+        assert_eq!(decode_pair(&[0xff; 65]), None);
+        assert_eq!(decode_pair(&encoded[..encoded.len() - 32]), None);
+        assert_eq!(
+            verify_pair(&[0xff; 65], H256::zero(), Address::zero(), Address::zero()),
+            Err(SignatureError::MalformedPair)
+        );
+    }
+
+    #[test]
+    fn pairs_recover_the_parties() {
+        let hash = H256::repeat_byte(7);
+        let (party_a, signature_a) = sign(KEY_A, hash);
+        let (party_b, signature_b) = sign(KEY_B, hash);
+        assert_eq!(
+            party_a,
+            "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266"
+                .parse()
+                .unwrap()
+        );
+        assert_eq!(
+            party_b,
+            "0x70997970C51812dc3A010C7d01b50e0d17dc79C8"
+                .parse()
+                .unwrap()
+        );
+
+        let pair = encode_pair(signature_a.clone(), signature_b.clone());
+        assert_eq!(recover_pair(&pair, hash), Some((party_a, party_b)));
+        assert_eq!(verify_pair(&pair, hash, party_a, party_b), Ok(()));
+
+        // in the wrong order, the signature of A is the first to be refused
+        let swapped = encode_pair(signature_b, signature_a.clone());
+        assert_eq!(
+            verify_pair(&swapped, hash, party_a, party_b),
+            Err(SignatureError::WrongSigner {
+                expected: party_a,
+                actual: party_b
+            })
+        );
+        assert_eq!(
+            verify_signer(&signature_a[..64], hash, party_a),
+            Err(SignatureError::Malformed(64))
+        );
+    }
+}
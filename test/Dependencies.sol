@@ -0,0 +1,6 @@
+// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.12;
+
+// Not deployed by the channel, but compiled so the Rust integration tests can deploy their own
+// entry point on a local node.
+import "account-abstraction/core/EntryPoint.sol";